@@ -2,7 +2,7 @@ use r2r::std_msgs::msg::Header;
 use r2r::tf2_msgs::msg::TFMessage;
 use r2r::visualization_msgs::msg::Marker;
 use r2r::Context;
-use r2r_teaching_markers::TeachingMarkerServer;
+use r2r_teaching_markers::{TeachingMarker, TeachingMarkerServer};
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use r2r::QosProfile;
@@ -105,16 +105,19 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     };
     marker.mesh_resource = mesh_path;
 
-    let server = TeachingMarkerServer::new("teaching_markers", arc_node_clone);
-
-    let arc_node_clone = arc_node.clone();
-    server.insert(
-        "teaching_marker".to_string(),
-        "base_link".to_string(),
-        None,
-        Some(marker),
+    let server = TeachingMarkerServer::new_with_runtime(
+        "teaching_markers",
         arc_node_clone,
-    );
+        tokio::runtime::Handle::current(),
+    )?;
+
+    server.insert_marker(
+        TeachingMarker::builder("teaching_marker")
+            .frame("base_link")
+            .scale(0.5)
+            .with_visual(marker)
+            .build(),
+    )?;
 
     let arc_node_clone = arc_node.clone();
     server.insert(
@@ -123,7 +126,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         None,
         None,
         arc_node_clone,
-    );
+    )?;
 
     // Keep the node alive
     let arc_node_clone: Arc<Mutex<r2r::Node>> = arc_node.clone();
@@ -0,0 +1,41 @@
+use std::sync::atomic::Ordering;
+
+use crate::TeachingMarkerServer;
+
+impl TeachingMarkerServer {
+    /// Sets whether changes to RViz are held back until `commit` is called.
+    ///
+    /// # Arguments
+    ///
+    /// * `deferred` - `true` to accumulate the changes, `false` to show them right away again.
+    ///
+    /// # Remarks
+    ///
+    /// In deferred mode, building a large scene with many calls to e.g. `insert_marker`, `set_pose`
+    /// and `set_visual` results in a single update burst to RViz on `commit`, instead of one per
+    /// call. Transforms, pose topics and streams are published right away as usual. Changes
+    /// caused by the RViz user, e.g. overlays following a drag, are held back as well, so keep
+    /// the deferred sections short while the markers are being taught. Turning the mode off
+    /// commits the pending changes.
+    pub fn set_deferred(&self, deferred: bool) {
+        self.deferred.store(deferred, Ordering::SeqCst);
+        if !deferred {
+            self.commit();
+        }
+    }
+
+    /// Returns whether changes to RViz are held back until `commit`, see `set_deferred`.
+    pub fn is_deferred(&self) -> bool {
+        self.deferred.load(Ordering::SeqCst)
+    }
+
+    /// Shows the changes held back in deferred mode in RViz, in one update per marker topic.
+    ///
+    /// # Remarks
+    ///
+    /// Does nothing if there are no pending changes. The server stays in deferred mode.
+    pub fn commit(&self) {
+        self.interactive_marker_server.commit();
+        self.regular_marker_server.commit();
+    }
+}
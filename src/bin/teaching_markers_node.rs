@@ -0,0 +1,90 @@
+//! Runs a teaching marker server as a standalone node.
+//!
+//! The markers are read from the node parameters, see `TeachingMarkerServer::insert_from_parameters`,
+//! and from a marker file written by `TeachingMarkerServer::save_to_file`. Parameters:
+//!
+//! * `server_name` - The topic namespace of the server, `teaching_markers` by default.
+//! * `marker_file` - A YAML or JSON marker file to load at startup, if it exists.
+//! * `save_on_exit` - Whether to write the taught poses back to `marker_file` on Ctrl-C.
+//! * `publish_events` - Whether to publish the teaching events on `<server_name>/events`.
+//! * `echo_feedback` - Whether to republish the feedback acted upon on `<server_name>/feedback_echo`.
+//! * `markers.names`, `markers.<name>.frame`, ... - Markers declared in the parameters.
+
+use r2r::{Context, ParameterValue};
+use r2r_teaching_markers::TeachingMarkerServer;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+pub static NODE_ID: &str = "teaching_markers_node";
+
+/// Reads an optional string parameter.
+fn string_param(node: &r2r::Node, name: &str) -> Option<String> {
+    match node.params.lock().unwrap().get(name).map(|p| &p.value) {
+        Some(ParameterValue::String(value)) => Some(value.clone()),
+        _ => None,
+    }
+}
+
+/// Reads an optional bool parameter, `false` if it isn't set.
+fn bool_param(node: &r2r::Node, name: &str) -> bool {
+    matches!(
+        node.params.lock().unwrap().get(name).map(|p| &p.value),
+        Some(ParameterValue::Bool(true))
+    )
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let context = Context::create()?;
+    let node = r2r::Node::create(context, NODE_ID, "")?;
+    let server_name = string_param(&node, "server_name").unwrap_or_else(|| "teaching_markers".to_string());
+    let marker_file = string_param(&node, "marker_file");
+    let save_on_exit = bool_param(&node, "save_on_exit");
+    let publish_events = bool_param(&node, "publish_events");
+    let echo_feedback = bool_param(&node, "echo_feedback");
+    let arc_node = Arc::new(Mutex::new(node));
+
+    let server = TeachingMarkerServer::new_with_runtime(
+        &server_name,
+        arc_node.clone(),
+        tokio::runtime::Handle::current(),
+    )?;
+    // Markers declared on a frame of the robot start where the frame is
+    server.listen_tf()?;
+    if publish_events {
+        server.publish_events()?;
+    }
+    if echo_feedback {
+        server.publish_feedback_echo()?;
+    }
+
+    let names = server.insert_from_parameters("markers")?;
+    r2r::log_info!(NODE_ID, "Inserted {} markers from the parameters.", names.len());
+    if let Some(path) = &marker_file {
+        if Path::new(path).exists() {
+            server.load_from_file(path)?;
+            r2r::log_info!(NODE_ID, "Loaded the markers of '{}'.", path);
+        }
+    }
+
+    // Keep the node alive
+    let arc_node_clone = arc_node.clone();
+    std::thread::spawn(move || loop {
+        arc_node_clone
+            .lock()
+            .unwrap()
+            .spin_once(std::time::Duration::from_millis(100));
+    });
+
+    r2r::log_info!(NODE_ID, "Node started.");
+    tokio::signal::ctrl_c().await?;
+
+    if let (Some(path), true) = (&marker_file, save_on_exit) {
+        match server.save_to_file(path) {
+            Ok(()) => r2r::log_info!(NODE_ID, "Saved the markers to '{}'.", path),
+            Err(e) => r2r::log_error!(NODE_ID, "Failed to save the markers with: '{}'.", e),
+        }
+    }
+    server.shutdown()?;
+    Ok(())
+}
@@ -0,0 +1,156 @@
+use r2r::geometry_msgs::msg::{Pose, PoseStamped};
+use r2r::std_msgs::msg::Header;
+use r2r::visualization_msgs::msg::InteractiveMarkerFeedback;
+use std::sync::Arc;
+
+use crate::overlay::tinted;
+use crate::{Result, TeachingMarkerError, TeachingMarkerServer};
+
+/// Color of the visual of a marker whose pose is in collision.
+pub(crate) const COLLISION_RGB: [f32; 3] = [1.0, 0.0, 0.0];
+
+/// A user check returning `true` if a candidate pose is in collision.
+pub(crate) type CollisionCheck = Arc<dyn Fn(&PoseStamped) -> bool + Send + Sync>;
+
+/// The collision check of a marker and whether colliding poses are rejected.
+#[derive(Clone)]
+pub(crate) struct CollisionGuard {
+    pub(crate) check: CollisionCheck,
+    pub(crate) veto: bool,
+}
+
+impl CollisionGuard {
+    /// The result of this check on a pose, `None` if the verdict is of another check or pose.
+    pub(crate) fn verdict(&self, verdict: Option<&CollisionVerdict>, pose: &Pose) -> Option<bool> {
+        verdict
+            .filter(|verdict| {
+                std::ptr::eq(
+                    Arc::as_ptr(&verdict.check) as *const (),
+                    Arc::as_ptr(&self.check) as *const (),
+                ) && verdict.pose == *pose
+            })
+            .map(|verdict| verdict.colliding)
+    }
+}
+
+/// The result of a collision check run outside of the lock, together with what it was run on.
+pub(crate) struct CollisionVerdict {
+    check: CollisionCheck,
+    pose: Pose,
+    colliding: bool,
+}
+
+impl TeachingMarkerServer {
+    /// Checks every candidate pose of a marker for collisions and turns its visual red while colliding.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the marker.
+    /// * `check` - Returns `true` if the pose, relative to the marker's parent frame, is in collision.
+    /// * `veto` - Whether colliding poses are rejected, the marker then stays at its last free pose.
+    ///
+    /// # Remarks
+    ///
+    /// The check runs on every pose received from RViz, after the constraints, so it has to be
+    /// fast, e.g. a query of a local collision world. It runs without the server being locked
+    /// and may read other markers, e.g. with `pose`. Replaces any previous check of the marker.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::MarkerNotFound` if no marker with the given name exists.
+    pub fn check_collisions<F>(&self, name: &str, check: F, veto: bool) -> Result<()>
+    where
+        F: Fn(&PoseStamped) -> bool + Send + Sync + 'static,
+    {
+        let pose = match self.markers.lock().unwrap().get(name) {
+            Some(state) => PoseStamped {
+                header: Header {
+                    frame_id: state.config.frame.clone(),
+                    ..Header::default()
+                },
+                pose: state.pose.clone(),
+            },
+            None => return Err(TeachingMarkerError::MarkerNotFound(name.to_string())),
+        };
+        // The check may call back into the server, so it runs without the lock
+        let colliding = check(&pose);
+        let (colliding, visual) = match self.markers.lock().unwrap().get_mut(name) {
+            Some(state) => {
+                // The marker may have moved in the meantime, its next feedback is checked again
+                state.colliding = colliding && state.pose == pose.pose;
+                state.collision_guard = Some(CollisionGuard {
+                    check: Arc::new(check),
+                    veto,
+                });
+                (state.colliding, state.config.visual.clone())
+            }
+            None => return Err(TeachingMarkerError::MarkerNotFound(name.to_string())),
+        };
+        if let Some(visual) = visual {
+            let visual = if colliding { tinted(&visual, COLLISION_RGB) } else { visual };
+            self.regular_marker_server.insert(name, visual);
+            self.regular_marker_server.apply_changes();
+        }
+        Ok(())
+    }
+
+    /// Runs the collision check of a marker on the pose a feedback would move it to.
+    ///
+    /// Runs without the lock, so the check can call back into the server. Returns `None` if the
+    /// marker has no check or the feedback pose is invalid.
+    pub(crate) fn collision_verdict(
+        &self,
+        name: &str,
+        feedback: &InteractiveMarkerFeedback,
+    ) -> Option<CollisionVerdict> {
+        let (check, frame, constraints) = {
+            let markers = self.markers.lock().unwrap();
+            let state = markers.get(name)?;
+            let guard = state.collision_guard.as_ref()?;
+            (guard.check.clone(), state.config.frame.clone(), state.config.constraints.clone())
+        };
+        let (pose, _) = Self::process_feedback(name, &frame, &constraints, feedback).ok()?;
+        let candidate = PoseStamped {
+            header: Header {
+                frame_id: frame,
+                ..Header::default()
+            },
+            pose: pose.clone(),
+        };
+        let colliding = check(&candidate);
+        Some(CollisionVerdict { check, pose, colliding })
+    }
+
+    /// Stops checking a marker for collisions and restores its visual.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::MarkerNotFound` if no marker with the given name exists.
+    pub fn stop_checking_collisions(&self, name: &str) -> Result<()> {
+        let visual = match self.markers.lock().unwrap().get_mut(name) {
+            Some(state) => {
+                state.collision_guard = None;
+                state.colliding = false;
+                state.config.visual.clone()
+            }
+            None => return Err(TeachingMarkerError::MarkerNotFound(name.to_string())),
+        };
+        if let Some(visual) = visual {
+            self.regular_marker_server.insert(name, visual);
+            self.regular_marker_server.apply_changes();
+        }
+        Ok(())
+    }
+
+    /// Returns whether the current pose of a marker was reported in collision.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::MarkerNotFound` if no marker with the given name exists.
+    pub fn is_colliding(&self, name: &str) -> Result<bool> {
+        match self.markers.lock().unwrap().get(name) {
+            Some(state) => Ok(state.colliding),
+            None => Err(TeachingMarkerError::MarkerNotFound(name.to_string())),
+        }
+    }
+}
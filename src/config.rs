@@ -0,0 +1,116 @@
+#[derive(Clone, Debug, PartialEq)]
+/// The topic names of a teaching marker server, see `TeachingMarkerServer::new_with_config`.
+///
+/// Every topic can be overridden, and a prefix can be put in front of all of them so several
+/// servers, e.g. one per robot, can run side by side without collisions.
+pub struct ServerConfig {
+    pub(crate) namespace: String,
+    pub(crate) prefix: String,
+    pub(crate) interactive_namespace: Option<String>,
+    pub(crate) regular_namespace: Option<String>,
+    pub(crate) tf_topic: String,
+    pub(crate) tf_static_topic: String,
+    pub(crate) clicked_point_topic: String,
+    pub(crate) initial_pose_topic: String,
+    pub(crate) diagnostics_topic: String,
+    pub(crate) headless: bool,
+    pub(crate) regular_markers: bool,
+}
+
+impl ServerConfig {
+    /// Creates the configuration of a server with the default topics.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace` - The topic namespace of the server, as the `name` of `TeachingMarkerServer::new`.
+    pub fn new(namespace: &str) -> Self {
+        ServerConfig {
+            namespace: namespace.to_string(),
+            prefix: String::new(),
+            interactive_namespace: None,
+            regular_namespace: None,
+            tf_topic: "tf".to_string(),
+            tf_static_topic: "tf_static".to_string(),
+            clicked_point_topic: "clicked_point".to_string(),
+            initial_pose_topic: "initialpose".to_string(),
+            diagnostics_topic: "/diagnostics".to_string(),
+            headless: false,
+            regular_markers: true,
+        }
+    }
+
+    /// Puts a prefix in front of every topic of the server, e.g. `robot1` for `robot1/tf`.
+    ///
+    /// An absolute prefix like `/robot1` makes all topics absolute.
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Sets the namespace of the interactive marker topics, the server namespace by default.
+    pub fn interactive_namespace(mut self, namespace: &str) -> Self {
+        self.interactive_namespace = Some(namespace.to_string());
+        self
+    }
+
+    /// Sets the namespace of the regular marker topic, the server namespace by default.
+    pub fn regular_namespace(mut self, namespace: &str) -> Self {
+        self.regular_namespace = Some(namespace.to_string());
+        self
+    }
+
+    /// Sets the topic of the dynamic transforms, `tf` by default.
+    pub fn tf_topic(mut self, topic: &str) -> Self {
+        self.tf_topic = topic.to_string();
+        self
+    }
+
+    /// Sets the topic of the latched transforms, `tf_static` by default.
+    pub fn tf_static_topic(mut self, topic: &str) -> Self {
+        self.tf_static_topic = topic.to_string();
+        self
+    }
+
+    /// Sets the topic of the points clicked in RViz, `clicked_point` by default.
+    pub fn clicked_point_topic(mut self, topic: &str) -> Self {
+        self.clicked_point_topic = topic.to_string();
+        self
+    }
+
+    /// Sets the topic of the poses set in RViz with "2D Pose Estimate", `initialpose` by default.
+    pub fn initial_pose_topic(mut self, topic: &str) -> Self {
+        self.initial_pose_topic = topic.to_string();
+        self
+    }
+
+    /// Sets the topic of the diagnostics, `/diagnostics` by default.
+    pub fn diagnostics_topic(mut self, topic: &str) -> Self {
+        self.diagnostics_topic = topic.to_string();
+        self
+    }
+
+    /// Sets whether nothing is shown in RViz, see `TeachingMarkerServer::new_headless`.
+    pub fn headless(mut self, headless: bool) -> Self {
+        self.headless = headless;
+        self
+    }
+
+    /// Sets whether the visuals and overlays of the markers are shown, `true` by default.
+    ///
+    /// Without them the server doesn't advertise the regular marker topic, which saves a topic
+    /// in deployments that only use the draggable markers. `TeachingMarkerBuilder::with_visual`,
+    /// the visual states and the overlays then have no effect in RViz.
+    pub fn regular_markers(mut self, regular_markers: bool) -> Self {
+        self.regular_markers = regular_markers;
+        self
+    }
+
+    /// Applies the prefix to a topic name.
+    pub(crate) fn topic(&self, topic: &str) -> String {
+        if self.prefix.is_empty() {
+            topic.to_string()
+        } else {
+            format!("{}/{}", self.prefix, topic.trim_start_matches('/'))
+        }
+    }
+}
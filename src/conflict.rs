@@ -0,0 +1,120 @@
+use r2r::visualization_msgs::msg::InteractiveMarkerFeedback;
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+use crate::events::TeachingEvent;
+use crate::{MarkerState, Result, TeachingMarkerError, TeachingMarkerServer, NODE_ID};
+
+/// How long a client owns a drag without sending feedback, e.g. after it crashed mid-drag.
+const DRAG_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The RViz client currently dragging a marker.
+pub(crate) struct DragGesture {
+    client_id: String,
+    /// Counts the drags of the marker, starting at 1.
+    sequence: u64,
+    last_seen: Instant,
+    /// The clients whose feedback has been rejected during this drag.
+    rejected: HashSet<String>,
+}
+
+/// Feedback of a client interfering with the drag of another one.
+pub(crate) struct DragConflict {
+    owner: String,
+    client: String,
+    sequence: u64,
+    /// Whether this client is rejected for the first time during the drag.
+    first: bool,
+}
+
+impl MarkerState {
+    /// Decides whether feedback is taken, the first client pressing the mouse owns the drag.
+    ///
+    /// # Remarks
+    ///
+    /// The owner keeps the marker until it releases the mouse or stays silent for
+    /// `DRAG_TIMEOUT`, feedback of all other clients is rejected in the meantime.
+    pub(crate) fn claim_drag(
+        &mut self,
+        feedback: &InteractiveMarkerFeedback,
+    ) -> std::result::Result<(), DragConflict> {
+        let now = Instant::now();
+        if let Some(gesture) = self.drag_gesture.as_mut() {
+            if gesture.client_id == feedback.client_id {
+                gesture.last_seen = now;
+                if feedback.event_type == InteractiveMarkerFeedback::MOUSE_UP as u8 {
+                    self.drag_gesture = None;
+                }
+                return Ok(());
+            }
+            if now.duration_since(gesture.last_seen) < DRAG_TIMEOUT {
+                return Err(DragConflict {
+                    owner: gesture.client_id.clone(),
+                    client: feedback.client_id.clone(),
+                    sequence: gesture.sequence,
+                    first: gesture.rejected.insert(feedback.client_id.clone()),
+                });
+            }
+            // The owner is gone, the marker is up for grabs again
+            self.drag_gesture = None;
+        }
+
+        if feedback.event_type == InteractiveMarkerFeedback::MOUSE_DOWN as u8 {
+            self.drag_sequence += 1;
+            self.drag_gesture = Some(DragGesture {
+                client_id: feedback.client_id.clone(),
+                sequence: self.drag_sequence,
+                last_seen: now,
+                rejected: HashSet::new(),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl TeachingMarkerServer {
+    /// Returns the id of the RViz client dragging a marker, `None` if it isn't dragged.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the marker.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::MarkerNotFound` if no marker with the given name exists.
+    pub fn drag_owner(&self, name: &str) -> Result<Option<String>> {
+        match self.markers.lock().unwrap().get(name) {
+            Some(state) => Ok(state
+                .drag_gesture
+                .as_ref()
+                .filter(|gesture| gesture.last_seen.elapsed() < DRAG_TIMEOUT)
+                .map(|gesture| gesture.client_id.clone())),
+            None => Err(TeachingMarkerError::MarkerNotFound(name.to_string())),
+        }
+    }
+
+    /// Rejects the feedback of a client interfering with a drag and shows it the owner's pose.
+    pub(crate) fn reject_feedback(&self, name: &str, conflict: DragConflict) {
+        let pose = match self.markers.lock().unwrap().get(name) {
+            Some(state) => state.pose.clone(),
+            None => return,
+        };
+        self.interactive_marker_server.set_pose(name, pose, None);
+        self.interactive_marker_server.apply_changes();
+        if conflict.first {
+            r2r::log_warn!(
+                NODE_ID,
+                "Ignoring feedback of '{}' for '{}' dragged by '{}'.",
+                conflict.client,
+                name,
+                conflict.owner
+            );
+            self.emit(TeachingEvent::Conflict {
+                marker: name.to_string(),
+                owner: conflict.owner,
+                client: conflict.client,
+                sequence: conflict.sequence,
+            });
+        }
+    }
+}
@@ -0,0 +1,405 @@
+use r2r::geometry_msgs::msg::{Point, Pose, Quaternion, Vector3};
+
+use crate::math::{normalize_quaternion, quaternion_to_rpy, rpy_to_quaternion};
+
+#[derive(Clone, Debug, PartialEq)]
+/// An axis-aligned box in the marker's parent frame.
+pub struct Workspace {
+    /// The corner with the smallest coordinates.
+    pub min: Point,
+    /// The corner with the largest coordinates.
+    pub max: Point,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// A half-space `normal · p >= offset` in the marker's parent frame.
+///
+/// For example, a normal of `(0, 0, 1)` with an offset of `0.8` keeps the marker above a table at 0.8 m.
+pub struct HalfSpace {
+    /// The normal pointing into the allowed region, it doesn't need to be normalized.
+    pub normal: Vector3,
+    /// The signed distance of the boundary plane from the origin along the normalized normal.
+    pub offset: f64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+/// Constraints applied to every pose received from RViz before its transform is published.
+pub struct PoseConstraints {
+    /// Grid step in meters the translation is snapped to.
+    pub translation_step: Option<f64>,
+    /// Angle step in radians the roll, pitch and yaw angles are snapped to.
+    pub rotation_step: Option<f64>,
+    /// Box the marker position is clamped to.
+    pub workspace: Option<Workspace>,
+    /// Half-spaces the marker position is projected into, applied in order.
+    pub half_spaces: Vec<HalfSpace>,
+    /// Whether the Z axis of the marker is kept aligned with the Z axis of the parent frame,
+    /// leaving only the yaw.
+    pub gravity_aligned: bool,
+    /// Height in meters the marker position is pinned to along the Z axis of the parent frame.
+    ///
+    /// The height takes precedence, the workspace and the half-spaces then only move the marker horizontally.
+    pub fixed_height: Option<f64>,
+}
+
+impl PoseConstraints {
+    /// Applies the constraints to a pose.
+    ///
+    /// # Arguments
+    ///
+    /// * `pose` - The pose received from RViz, relative to the marker's parent frame.
+    ///
+    /// # Returns
+    ///
+    /// The constrained pose.
+    pub fn apply(&self, pose: &Pose) -> Pose {
+        let mut pose = pose.clone();
+        // Pinned first so clamping, projecting and snapping keep the height
+        if let Some(height) = self.fixed_height {
+            pose.position.z = height;
+        }
+        if let Some(workspace) = &self.workspace {
+            pose.position.x = clamp(pose.position.x, workspace.min.x, workspace.max.x);
+            pose.position.y = clamp(pose.position.y, workspace.min.y, workspace.max.y);
+            if self.fixed_height.is_none() {
+                pose.position.z = clamp(pose.position.z, workspace.min.z, workspace.max.z);
+            }
+        }
+        for half_space in &self.half_spaces {
+            project_into(&mut pose.position, half_space, self.fixed_height.is_some());
+        }
+        // Snapped last so clamped and projected poses stay on the grid
+        if let Some(step) = self.translation_step.filter(|step| *step > 0.0) {
+            pose.position = self.snap_position(&pose.position, step);
+        }
+        if self.gravity_aligned {
+            pose.orientation = yaw_only(&pose.orientation);
+        }
+        if let Some(step) = self.rotation_step.filter(|step| *step > 0.0) {
+            let (roll, pitch, yaw) = quaternion_to_rpy(&pose.orientation);
+            pose.orientation = rpy_to_quaternion(snap(roll, step), snap(pitch, step), snap(yaw, step));
+        }
+        pose
+    }
+
+    /// Snaps a position to the nearest grid point inside the workspace and the half-spaces.
+    ///
+    /// Only the grid points of the cell around the position are considered. If none of them is
+    /// allowed, e.g. for a workspace narrower than the step, the position is kept as it is.
+    fn snap_position(&self, position: &Point, step: f64) -> Point {
+        let around = |value: f64| [snap(value, step), (value / step).floor() * step, (value / step).ceil() * step];
+        // A pinned height is never moved
+        let heights = match self.fixed_height {
+            Some(_) => [position.z; 3],
+            None => around(position.z),
+        };
+        let mut best: Option<(f64, Point)> = None;
+        for x in around(position.x) {
+            for y in around(position.y) {
+                for z in heights {
+                    let candidate = Point { x, y, z };
+                    if !self.allows(&candidate) {
+                        continue;
+                    }
+                    let (dx, dy, dz) = (x - position.x, y - position.y, z - position.z);
+                    let distance = dx * dx + dy * dy + dz * dz;
+                    if best.as_ref().is_none_or(|(shortest, _)| distance < *shortest) {
+                        best = Some((distance, candidate));
+                    }
+                }
+            }
+        }
+        best.map_or_else(|| position.clone(), |(_, point)| point)
+    }
+
+    /// Returns whether a position is inside the workspace and all half-spaces.
+    ///
+    /// The height of the workspace is ignored with a fixed height, which takes precedence.
+    fn allows(&self, point: &Point) -> bool {
+        const TOLERANCE: f64 = 1e-9;
+        let inside = |value: f64, min: f64, max: f64| value >= min - TOLERANCE && value <= max + TOLERANCE;
+        let in_workspace = self.workspace.as_ref().is_none_or(|w| {
+            inside(point.x, w.min.x, w.max.x)
+                && inside(point.y, w.min.y, w.max.y)
+                && (self.fixed_height.is_some() || inside(point.z, w.min.z, w.max.z))
+        });
+        in_workspace
+            && self
+                .half_spaces
+                .iter()
+                .all(|half_space| signed_distance(point, half_space).is_none_or(|d| d >= -TOLERANCE))
+    }
+}
+
+/// Rounds a value to the nearest multiple of a step.
+fn snap(value: f64, step: f64) -> f64 {
+    (value / step).round() * step
+}
+
+/// Clamps a value between a lower and an upper bound, preferring the lower bound if they cross.
+fn clamp(value: f64, min: f64, max: f64) -> f64 {
+    value.min(max).max(min)
+}
+
+/// Removes the roll and pitch from an orientation, keeping its rotation around Z.
+///
+/// Uses the twist around Z of a swing-twist decomposition, which stays stable where the
+/// yaw angle of roll, pitch and yaw is undefined.
+fn yaw_only(q: &Quaternion) -> Quaternion {
+    let norm = (q.z * q.z + q.w * q.w).sqrt();
+    if norm < 1e-9 {
+        // Upside down, any yaw is as good as another
+        return Quaternion {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+            w: 1.0,
+        };
+    }
+    Quaternion {
+        x: 0.0,
+        y: 0.0,
+        z: q.z / norm,
+        w: q.w / norm,
+    }
+}
+
+/// Returns the unit normal of a half-space, `None` if the normal is degenerate.
+fn unit_normal(half_space: &HalfSpace) -> Option<(f64, f64, f64)> {
+    let n = &half_space.normal;
+    let norm = (n.x * n.x + n.y * n.y + n.z * n.z).sqrt();
+    if norm == 0.0 || !norm.is_finite() {
+        return None;
+    }
+    Some((n.x / norm, n.y / norm, n.z / norm))
+}
+
+/// Returns the signed distance of a point from the boundary of a half-space, negative outside of it.
+fn signed_distance(point: &Point, half_space: &HalfSpace) -> Option<f64> {
+    let (nx, ny, nz) = unit_normal(half_space)?;
+    Some(nx * point.x + ny * point.y + nz * point.z - half_space.offset)
+}
+
+/// Projects a point onto the boundary of a half-space if it lies outside of it.
+///
+/// With `horizontal` the point is only moved in the XY plane, keeping its height. A point outside
+/// of a half-space with a vertical normal then stays where it is.
+fn project_into(point: &mut Point, half_space: &HalfSpace, horizontal: bool) {
+    let (Some((nx, ny, nz)), Some(distance)) = (unit_normal(half_space), signed_distance(point, half_space))
+    else {
+        return;
+    };
+    if distance >= 0.0 {
+        return;
+    }
+    if horizontal {
+        let planar = nx * nx + ny * ny;
+        if planar < 1e-12 {
+            return;
+        }
+        point.x -= distance * nx / planar;
+        point.y -= distance * ny / planar;
+    } else {
+        point.x -= distance * nx;
+        point.y -= distance * ny;
+        point.z -= distance * nz;
+    }
+}
+
+/// Validates a pose and normalizes its orientation.
+///
+/// # Arguments
+///
+/// * `pose` - The pose to validate.
+///
+/// # Returns
+///
+/// The pose with a unit quaternion, or `None` if it contains NaN or infinite values
+/// or its quaternion has zero length.
+pub(crate) fn sanitize_pose(pose: &Pose) -> Option<Pose> {
+    let p = &pose.position;
+    let q = &pose.orientation;
+    if ![p.x, p.y, p.z, q.x, q.y, q.z, q.w].iter().all(|v| v.is_finite()) {
+        return None;
+    }
+    let norm = q.x * q.x + q.y * q.y + q.z * q.z + q.w * q.w;
+    if norm < 1e-12 {
+        return None;
+    }
+    let mut pose = pose.clone();
+    // Leave nearly normalized quaternions untouched so they compare equal to the input
+    if (norm - 1.0).abs() > 1e-9 {
+        normalize_quaternion(&mut pose.orientation);
+    }
+    Some(pose)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{assert_near, assert_position, pose};
+
+    fn point(x: f64, y: f64, z: f64) -> Point {
+        Point { x, y, z }
+    }
+
+    #[test]
+    fn snaps_translation_to_the_grid() {
+        let constraints = PoseConstraints {
+            translation_step: Some(0.1),
+            ..PoseConstraints::default()
+        };
+        let snapped = constraints.apply(&pose(0.24, -0.26, 1.05001));
+        assert_position(&snapped, 0.2, -0.3, 1.1);
+    }
+
+    #[test]
+    fn clamped_poses_stay_on_the_grid() {
+        let constraints = PoseConstraints {
+            translation_step: Some(0.1),
+            workspace: Some(Workspace {
+                min: point(0.0, 0.0, 0.0),
+                max: point(0.95, 0.95, 0.95),
+            }),
+            ..PoseConstraints::default()
+        };
+        // Clamped to 0.95, the grid point inside the box is 0.9
+        let snapped = constraints.apply(&pose(2.0, 0.5, -1.0));
+        assert_position(&snapped, 0.9, 0.5, 0.0);
+    }
+
+    #[test]
+    fn projected_poses_stay_on_the_grid() {
+        let constraints = PoseConstraints {
+            translation_step: Some(0.1),
+            half_spaces: vec![HalfSpace {
+                normal: Vector3 { x: 0.0, y: 0.0, z: 1.0 },
+                offset: 0.75,
+            }],
+            ..PoseConstraints::default()
+        };
+        let snapped = constraints.apply(&pose(0.31, 0.0, 0.2));
+        assert_position(&snapped, 0.3, 0.0, 0.8);
+    }
+
+    #[test]
+    fn keeps_the_clamped_position_without_a_grid_point_inside() {
+        let constraints = PoseConstraints {
+            translation_step: Some(1.0),
+            workspace: Some(Workspace {
+                min: point(0.2, 0.2, 0.2),
+                max: point(0.4, 0.4, 0.4),
+            }),
+            ..PoseConstraints::default()
+        };
+        let constrained = constraints.apply(&pose(1.0, 0.3, 0.0));
+        assert_position(&constrained, 0.4, 0.3, 0.2);
+    }
+
+    #[test]
+    fn clamps_to_the_workspace() {
+        let constraints = PoseConstraints {
+            workspace: Some(Workspace {
+                min: point(-1.0, -1.0, 0.0),
+                max: point(1.0, 1.0, 2.0),
+            }),
+            ..PoseConstraints::default()
+        };
+        let clamped = constraints.apply(&pose(3.0, -0.5, -1.0));
+        assert_position(&clamped, 1.0, -0.5, 0.0);
+    }
+
+    #[test]
+    fn projects_into_half_spaces() {
+        let constraints = PoseConstraints {
+            half_spaces: vec![HalfSpace {
+                normal: Vector3 { x: 0.0, y: 0.0, z: 2.0 },
+                offset: 0.8,
+            }],
+            ..PoseConstraints::default()
+        };
+        assert_position(&constraints.apply(&pose(0.1, 0.2, 0.5)), 0.1, 0.2, 0.8);
+        assert_position(&constraints.apply(&pose(0.1, 0.2, 1.5)), 0.1, 0.2, 1.5);
+    }
+
+    #[test]
+    fn the_fixed_height_survives_clamping_projecting_and_snapping() {
+        let constraints = PoseConstraints {
+            translation_step: Some(0.1),
+            workspace: Some(Workspace {
+                min: point(-2.0, -2.0, 0.0),
+                max: point(2.0, 2.0, 0.5),
+            }),
+            half_spaces: vec![HalfSpace {
+                normal: Vector3 { x: 1.0, y: 0.0, z: 1.0 },
+                offset: 1.5,
+            }],
+            fixed_height: Some(1.0),
+            ..PoseConstraints::default()
+        };
+        // Projected horizontally to x = 1.12 at the pinned height, the nearest allowed grid point is 1.2
+        assert_position(&constraints.apply(&pose(0.0, 0.23, 0.0)), 1.2, 0.2, 1.0);
+    }
+
+    #[test]
+    fn keeps_the_fixed_height_outside_of_a_horizontal_half_space() {
+        let constraints = PoseConstraints {
+            half_spaces: vec![HalfSpace {
+                normal: Vector3 { x: 0.0, y: 0.0, z: 1.0 },
+                offset: 1.5,
+            }],
+            fixed_height: Some(1.0),
+            ..PoseConstraints::default()
+        };
+        assert_position(&constraints.apply(&pose(0.3, 0.2, 2.0)), 0.3, 0.2, 1.0);
+    }
+
+    #[test]
+    fn snaps_rotation_angles() {
+        let constraints = PoseConstraints {
+            rotation_step: Some(std::f64::consts::FRAC_PI_2),
+            ..PoseConstraints::default()
+        };
+        let mut rotated = pose(0.0, 0.0, 0.0);
+        rotated.orientation = rpy_to_quaternion(0.1, -0.2, 1.4);
+        let snapped = constraints.apply(&rotated);
+        let (roll, pitch, yaw) = quaternion_to_rpy(&snapped.orientation);
+        assert_near(roll, 0.0);
+        assert_near(pitch, 0.0);
+        assert_near(yaw, std::f64::consts::FRAC_PI_2);
+    }
+
+    #[test]
+    fn gravity_alignment_keeps_only_the_yaw() {
+        let constraints = PoseConstraints {
+            gravity_aligned: true,
+            ..PoseConstraints::default()
+        };
+        let mut tilted = pose(0.0, 0.0, 0.0);
+        tilted.orientation = rpy_to_quaternion(0.3, 0.2, 0.5);
+        let aligned = constraints.apply(&tilted);
+        assert_near(aligned.orientation.x, 0.0);
+        assert_near(aligned.orientation.y, 0.0);
+        let norm = aligned.orientation.z.hypot(aligned.orientation.w);
+        assert_near(norm, 1.0);
+    }
+
+    #[test]
+    fn rejects_invalid_poses() {
+        let mut invalid = pose(f64::NAN, 0.0, 0.0);
+        assert!(sanitize_pose(&invalid).is_none());
+        invalid = pose(0.0, 0.0, 0.0);
+        invalid.orientation.w = 0.0;
+        assert!(sanitize_pose(&invalid).is_none());
+    }
+
+    #[test]
+    fn normalizes_quaternions() {
+        let mut scaled = pose(0.0, 0.0, 0.0);
+        scaled.orientation.w = 2.0;
+        let sanitized = sanitize_pose(&scaled).unwrap();
+        assert_near(sanitized.orientation.w, 1.0);
+        let unit = pose(0.0, 0.0, 0.0);
+        assert_eq!(sanitize_pose(&unit), Some(unit));
+    }
+}
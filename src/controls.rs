@@ -0,0 +1,437 @@
+use r2r::geometry_msgs::msg::Quaternion;
+use serde::{Deserialize, Serialize};
+use std::ops::BitOr;
+use std::str::FromStr;
+
+use crate::math::normalize_quaternion;
+use crate::TeachingMarkerError;
+use r2r::visualization_msgs::msg::{InteractiveMarkerControl, Marker};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+/// Enum representing the set of controls a teaching marker is created with.
+pub enum ControlProfile {
+    /// Rotation and translation along all three axes.
+    #[default]
+    Full6D,
+    /// Translation along all three axes only, e.g. for pick points.
+    TranslateOnly,
+    /// Rotation around all three axes only, e.g. for orientation markers.
+    RotateOnly,
+    /// Translation along X and Y and rotation around Z.
+    Planar,
+}
+
+impl FromStr for ControlProfile {
+    type Err = TeachingMarkerError;
+
+    /// Parses a profile name like `"translate_only"` or `"TranslateOnly"`, ignoring case and underscores.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.replace('_', "").to_lowercase().as_str() {
+            "full6d" => Ok(ControlProfile::Full6D),
+            "translateonly" => Ok(ControlProfile::TranslateOnly),
+            "rotateonly" => Ok(ControlProfile::RotateOnly),
+            "planar" => Ok(ControlProfile::Planar),
+            _ => Err(TeachingMarkerError::InvalidParameter(format!(
+                "unknown control profile '{}'",
+                s
+            ))),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+/// Enum representing how the handles of a control are oriented in RViz.
+pub enum ControlOrientation {
+    /// The handles rotate with the marker.
+    #[default]
+    Inherit,
+    /// The handles stay aligned with the parent frame, easier to use for novice operators.
+    Fixed,
+    /// The handles face the camera.
+    ViewFacing,
+}
+
+impl ControlOrientation {
+    /// The `orientation_mode` of an `InteractiveMarkerControl`.
+    pub(crate) fn mode(self) -> u8 {
+        match self {
+            ControlOrientation::Inherit => InteractiveMarkerControl::INHERIT as u8,
+            ControlOrientation::Fixed => InteractiveMarkerControl::FIXED as u8,
+            ControlOrientation::ViewFacing => InteractiveMarkerControl::VIEW_FACING as u8,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// Enum representing the axes X, Y, and Z.
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// Enum representing controls that can be added on top of a control profile.
+pub enum ExtraControl {
+    /// Drag in the plane normal to the given axis.
+    MovePlane(Axis),
+    /// Drag freely in 3D.
+    Move3D,
+    /// Rotate freely in 3D.
+    Rotate3D,
+    /// Drag and rotate freely in 3D.
+    MoveRotate3D,
+    /// Drag in the camera plane and rotate around the view axis.
+    ViewFacing,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+/// A set of translation and rotation axes, used to lock marker degrees of freedom.
+pub struct AxisMask(u8);
+
+impl AxisMask {
+    /// No axes.
+    pub const NONE: AxisMask = AxisMask(0);
+    /// Translation along X.
+    pub const MOVE_X: AxisMask = AxisMask(1 << 0);
+    /// Translation along Y.
+    pub const MOVE_Y: AxisMask = AxisMask(1 << 1);
+    /// Translation along Z.
+    pub const MOVE_Z: AxisMask = AxisMask(1 << 2);
+    /// Rotation around X.
+    pub const ROTATE_X: AxisMask = AxisMask(1 << 3);
+    /// Rotation around Y.
+    pub const ROTATE_Y: AxisMask = AxisMask(1 << 4);
+    /// Rotation around Z.
+    pub const ROTATE_Z: AxisMask = AxisMask(1 << 5);
+    /// Translation along all axes.
+    pub const MOVE: AxisMask = AxisMask(0b000111);
+    /// Rotation around all axes.
+    pub const ROTATE: AxisMask = AxisMask(0b111000);
+    /// All axes.
+    pub const ALL: AxisMask = AxisMask(0b111111);
+
+    /// Returns the translation mask of an axis.
+    pub fn moving(axis: Axis) -> AxisMask {
+        match axis {
+            Axis::X => AxisMask::MOVE_X,
+            Axis::Y => AxisMask::MOVE_Y,
+            Axis::Z => AxisMask::MOVE_Z,
+        }
+    }
+
+    /// Returns the rotation mask of an axis.
+    pub fn rotating(axis: Axis) -> AxisMask {
+        match axis {
+            Axis::X => AxisMask::ROTATE_X,
+            Axis::Y => AxisMask::ROTATE_Y,
+            Axis::Z => AxisMask::ROTATE_Z,
+        }
+    }
+
+    /// Returns `true` if all axes of `other` are in the mask.
+    pub fn contains(self, other: AxisMask) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Returns `true` if any axis of `other` is in the mask.
+    pub fn intersects(self, other: AxisMask) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    /// Returns the mask without the axes of `other`.
+    pub fn difference(self, other: AxisMask) -> AxisMask {
+        AxisMask(self.0 & !other.0)
+    }
+}
+
+impl BitOr for AxisMask {
+    type Output = AxisMask;
+
+    fn bitor(self, rhs: AxisMask) -> AxisMask {
+        AxisMask(self.0 | rhs.0)
+    }
+}
+
+/// Prepares an interactive marker control with the specified parameters.
+///
+/// # Arguments
+///
+/// * `name` - The name of the control.
+/// * `interaction_mode` - The interaction mode for the control.
+/// * `axis` - The axis along which the control operates.
+/// * `marker` - An optional visual replacing the default rings and arrows of RViz.
+///
+/// # Returns
+///
+/// An `InteractiveMarkerControl` configured with the given parameters.
+pub(crate) fn prepare_control(
+    name: &str,
+    interaction_mode: u8,
+    axis: Axis,
+    marker: Option<Marker>,
+) -> InteractiveMarkerControl {
+    let mut control = InteractiveMarkerControl::default();
+    // Controls operate along the X axis of their orientation, so rotate it onto the requested axis
+    control.orientation = Quaternion {
+        w: 1.0,
+        x: if axis == Axis::X { 1.0 } else { 0.0 },
+        y: if axis == Axis::Z { 1.0 } else { 0.0 },
+        z: if axis == Axis::Y { 1.0 } else { 0.0 },
+    };
+    control.always_visible = true;
+    normalize_quaternion(&mut control.orientation);
+    control.name = name.to_string();
+    control.interaction_mode = interaction_mode;
+    if let Some(marker) = marker {
+        control.markers.push(marker);
+    }
+    control
+}
+
+/// Returns the controls of a control profile.
+///
+/// # Arguments
+///
+/// * `profile` - The control profile.
+/// * `locked` - The axes whose controls are left out.
+/// * `visuals` - Custom visuals of the controls, the first visual whose axes contain the axis of
+///   a control is used for it.
+/// * `orientations` - Orientation modes of the controls, matched like the visuals. Controls
+///   without a match inherit the marker orientation.
+///
+/// # Returns
+///
+/// The `InteractiveMarkerControl`s making up the profile.
+pub(crate) fn profile_controls(
+    profile: ControlProfile,
+    locked: AxisMask,
+    visuals: &[(AxisMask, Marker)],
+    orientations: &[(AxisMask, ControlOrientation)],
+) -> Vec<InteractiveMarkerControl> {
+    let rotate = InteractiveMarkerControl::ROTATE_AXIS as u8;
+    let translate = InteractiveMarkerControl::MOVE_AXIS as u8;
+    let controls: Vec<(&str, u8, Axis)> = match profile {
+        ControlProfile::Full6D => vec![
+            ("rotate_x", rotate, Axis::X),
+            ("move_x", translate, Axis::X),
+            ("rotate_y", rotate, Axis::Y),
+            ("move_y", translate, Axis::Y),
+            ("rotate_z", rotate, Axis::Z),
+            ("move_z", translate, Axis::Z),
+        ],
+        ControlProfile::TranslateOnly => vec![
+            ("move_x", translate, Axis::X),
+            ("move_y", translate, Axis::Y),
+            ("move_z", translate, Axis::Z),
+        ],
+        ControlProfile::RotateOnly => vec![
+            ("rotate_x", rotate, Axis::X),
+            ("rotate_y", rotate, Axis::Y),
+            ("rotate_z", rotate, Axis::Z),
+        ],
+        ControlProfile::Planar => vec![
+            ("move_x", translate, Axis::X),
+            ("move_y", translate, Axis::Y),
+            ("rotate_z", rotate, Axis::Z),
+        ],
+    };
+
+    let mask = |interaction_mode: u8, axis: Axis| {
+        if interaction_mode == rotate {
+            AxisMask::rotating(axis)
+        } else {
+            AxisMask::moving(axis)
+        }
+    };
+    controls
+        .into_iter()
+        .filter(|(_, interaction_mode, axis)| !locked.intersects(mask(*interaction_mode, *axis)))
+        .map(|(name, interaction_mode, axis)| {
+            let visual = visuals
+                .iter()
+                .find(|(axes, _)| axes.contains(mask(interaction_mode, axis)))
+                .map(|(_, visual)| visual.clone());
+            let mut control = prepare_control(name, interaction_mode, axis, visual);
+            if let Some((_, orientation)) = orientations
+                .iter()
+                .find(|(axes, _)| axes.contains(mask(interaction_mode, axis)))
+            {
+                control.orientation_mode = orientation.mode();
+            }
+            control
+        })
+        .collect()
+}
+
+/// Returns the axes an additional control can move or rotate along.
+pub(crate) fn extra_control_axes(extra: ExtraControl) -> AxisMask {
+    match extra {
+        ExtraControl::MovePlane(Axis::X) => AxisMask::MOVE_Y | AxisMask::MOVE_Z,
+        ExtraControl::MovePlane(Axis::Y) => AxisMask::MOVE_X | AxisMask::MOVE_Z,
+        ExtraControl::MovePlane(Axis::Z) => AxisMask::MOVE_X | AxisMask::MOVE_Y,
+        ExtraControl::Move3D => AxisMask::MOVE,
+        ExtraControl::Rotate3D => AxisMask::ROTATE,
+        ExtraControl::MoveRotate3D | ExtraControl::ViewFacing => AxisMask::ALL,
+    }
+}
+
+/// Prepares an additional control.
+///
+/// # Arguments
+///
+/// * `extra` - The kind of control.
+/// * `scale` - The scale of the marker, used to size the grab handle.
+///
+/// # Returns
+///
+/// An `InteractiveMarkerControl` with a handle that can be grabbed in RViz.
+pub(crate) fn extra_control(extra: ExtraControl, scale: f32) -> InteractiveMarkerControl {
+    let mut control = match extra {
+        ExtraControl::MovePlane(axis) => prepare_control(
+            &format!("move_plane_{}", axis_name(axis)),
+            InteractiveMarkerControl::MOVE_PLANE as u8,
+            axis,
+            None,
+        ),
+        ExtraControl::Move3D => prepare_control(
+            "move_3d",
+            InteractiveMarkerControl::MOVE_3D as u8,
+            Axis::X,
+            None,
+        ),
+        ExtraControl::Rotate3D => prepare_control(
+            "rotate_3d",
+            InteractiveMarkerControl::ROTATE_3D as u8,
+            Axis::X,
+            None,
+        ),
+        ExtraControl::MoveRotate3D => prepare_control(
+            "move_rotate_3d",
+            InteractiveMarkerControl::MOVE_ROTATE_3D as u8,
+            Axis::X,
+            None,
+        ),
+        ExtraControl::ViewFacing => {
+            let mut control = prepare_control(
+                "move_rotate_view",
+                InteractiveMarkerControl::MOVE_ROTATE as u8,
+                Axis::X,
+                None,
+            );
+            control.orientation_mode = InteractiveMarkerControl::VIEW_FACING as u8;
+            control.independent_marker_orientation = true;
+            control
+        }
+    };
+
+    // Free and view-facing controls have no default visual in RViz, so give them a sphere to grab
+    if !matches!(extra, ExtraControl::MovePlane(_)) {
+        control.markers.push(handle_sphere(scale));
+    }
+    control
+}
+
+/// Name of the button control that grows a marker.
+pub(crate) const GROW_CONTROL: &str = "resize_grow";
+
+/// Name of the button control that shrinks a marker.
+pub(crate) const SHRINK_CONTROL: &str = "resize_shrink";
+
+/// Prepares the two corner buttons that grow and shrink a marker.
+///
+/// # Arguments
+///
+/// * `scale` - The scale of the marker, used to place and size the handles.
+///
+/// # Returns
+///
+/// A green cube growing and a red cube shrinking the marker, at opposite top corners of the controls.
+pub(crate) fn resize_controls(scale: f32) -> Vec<InteractiveMarkerControl> {
+    let offset = scale as f64 * 0.5;
+    [(GROW_CONTROL, offset, [0.2, 0.9, 0.2]), (SHRINK_CONTROL, -offset, [0.9, 0.2, 0.2])]
+        .into_iter()
+        .map(|(name, y, rgb)| {
+            let size = scale as f64 * 0.1;
+            let mut handle = Marker::default();
+            handle.type_ = Marker::CUBE as i32;
+            handle.pose.position.x = offset;
+            handle.pose.position.y = y;
+            handle.pose.position.z = offset;
+            handle.pose.orientation.w = 1.0;
+            handle.scale.x = size;
+            handle.scale.y = size;
+            handle.scale.z = size;
+            handle.color.r = rgb[0];
+            handle.color.g = rgb[1];
+            handle.color.b = rgb[2];
+            handle.color.a = 0.9;
+
+            let mut control = InteractiveMarkerControl::default();
+            control.name = name.to_string();
+            control.interaction_mode = InteractiveMarkerControl::BUTTON as u8;
+            control.always_visible = true;
+            control.markers.push(handle);
+            control
+        })
+        .collect()
+}
+
+/// Returns the lowercase name of an axis.
+fn axis_name(axis: Axis) -> &'static str {
+    match axis {
+        Axis::X => "x",
+        Axis::Y => "y",
+        Axis::Z => "z",
+    }
+}
+
+/// Creates a translucent sphere used as a grab handle.
+///
+/// # Arguments
+///
+/// * `scale` - The scale of the marker the handle belongs to.
+fn handle_sphere(scale: f32) -> Marker {
+    let size = scale as f64 * 0.45;
+    let mut marker = Marker::default();
+    marker.type_ = Marker::SPHERE as i32;
+    marker.scale.x = size;
+    marker.scale.y = size;
+    marker.scale.z = size;
+    marker.color.r = 0.5;
+    marker.color.g = 0.5;
+    marker.color.b = 0.5;
+    marker.color.a = 0.5;
+    marker.pose.orientation.w = 1.0;
+    marker
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_control_profiles() {
+        assert_eq!("full6d".parse::<ControlProfile>().unwrap(), ControlProfile::Full6D);
+        assert_eq!("Full6D".parse::<ControlProfile>().unwrap(), ControlProfile::Full6D);
+        assert_eq!(
+            "translate_only".parse::<ControlProfile>().unwrap(),
+            ControlProfile::TranslateOnly
+        );
+        assert_eq!(
+            "TranslateOnly".parse::<ControlProfile>().unwrap(),
+            ControlProfile::TranslateOnly
+        );
+        assert_eq!("ROTATE_ONLY".parse::<ControlProfile>().unwrap(), ControlProfile::RotateOnly);
+        assert_eq!("planar".parse::<ControlProfile>().unwrap(), ControlProfile::Planar);
+    }
+
+    #[test]
+    fn rejects_unknown_control_profiles() {
+        assert!(matches!(
+            "scale".parse::<ControlProfile>(),
+            Err(TeachingMarkerError::InvalidParameter(_))
+        ));
+        assert!("".parse::<ControlProfile>().is_err());
+    }
+}
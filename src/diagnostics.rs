@@ -0,0 +1,127 @@
+use r2r::diagnostic_msgs::msg::{DiagnosticArray, DiagnosticStatus, KeyValue};
+use r2r::std_msgs::msg::Header;
+use r2r::QosProfile;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::{Result, TeachingMarkerError, TeachingMarkerServer, NODE_ID};
+
+/// Creates a key value pair of a diagnostic status.
+fn key_value(key: &str, value: impl ToString) -> KeyValue {
+    KeyValue {
+        key: key.to_string(),
+        value: value.to_string(),
+    }
+}
+
+impl TeachingMarkerServer {
+    /// Periodically publishes the status of the server and its markers on `/diagnostics`.
+    ///
+    /// # Arguments
+    ///
+    /// * `period` - How often to publish, diagnostic aggregators expect about once per second.
+    ///
+    /// # Remarks
+    ///
+    /// A status named after the server namespace holds the number of markers. Each marker gets a
+    /// status with the age of the last feedback from RViz, the failed publishes and the
+    /// intermediate poses dropped because the TF thread fell behind. A marker is reported as a
+    /// warning while publishes fail, and the server while any of its markers does. The topic is
+    /// the one diagnostic aggregators and rqt_robot_monitor listen on, see `ServerConfig` to change it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the publisher can't be created.
+    pub fn publish_diagnostics(&self, period: Duration) -> Result<()> {
+        let publisher = self.node.lock().unwrap().create_publisher::<DiagnosticArray>(
+            &self.config.topic(&self.config.diagnostics_topic),
+            QosProfile::default(),
+        )?;
+
+        let server = self.handle();
+        self.executor.spawn(async move {
+            // The publish errors of each marker at the previous report
+            let mut reported = HashMap::new();
+            loop {
+                server.executor.sleep(period).await;
+                let result = server
+                    .diagnostics(&mut reported)
+                    .and_then(|array| publisher.publish(&array).map_err(TeachingMarkerError::from));
+                if let Err(e) = result {
+                    r2r::log_error!(NODE_ID, "Failed to publish the diagnostics with: '{}'.", e);
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Collects the diagnostic status of the server and its markers, ordered by marker name.
+    ///
+    /// # Arguments
+    ///
+    /// * `reported` - The publish errors of each marker at the previous report, updated in place.
+    fn diagnostics(&self, reported: &mut HashMap<String, u64>) -> Result<DiagnosticArray> {
+        let now = Instant::now();
+        let states = self.markers.lock().unwrap();
+        // Forget the markers that have been removed
+        reported.retain(|name, _| states.contains_key(name));
+        let mut markers: Vec<DiagnosticStatus> = states
+            .iter()
+            .map(|(name, state)| {
+                let failing = state.publish_errors > reported.get(name).copied().unwrap_or(0);
+                reported.insert(name.clone(), state.publish_errors);
+                let feedback_age = match state.last_feedback {
+                    Some(time) => format!("{:.1}", now.duration_since(time).as_secs_f64()),
+                    None => "never".to_string(),
+                };
+                DiagnosticStatus {
+                    level: if failing {
+                        DiagnosticStatus::WARN as u8
+                    } else {
+                        DiagnosticStatus::OK as u8
+                    },
+                    name: format!("{}: {}", self.namespace, name),
+                    message: if failing { "Publishing failed" } else { "OK" }.to_string(),
+                    hardware_id: String::new(),
+                    values: vec![
+                        key_value("Feedback age (s)", feedback_age),
+                        key_value("Publish errors", state.publish_errors),
+                        key_value("Dropped updates", state.dropped_updates),
+                    ],
+                }
+            })
+            .collect();
+        drop(states);
+        markers.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let warnings = markers
+            .iter()
+            .filter(|m| m.level != DiagnosticStatus::OK as u8)
+            .count();
+        let summary = DiagnosticStatus {
+            level: if warnings > 0 {
+                DiagnosticStatus::WARN as u8
+            } else {
+                DiagnosticStatus::OK as u8
+            },
+            name: self.namespace.clone(),
+            message: if warnings > 0 {
+                format!("{warnings} markers failing to publish")
+            } else {
+                "OK".to_string()
+            },
+            hardware_id: String::new(),
+            values: vec![key_value("Markers", markers.len())],
+        };
+
+        let mut status = vec![summary];
+        status.extend(markers);
+        Ok(DiagnosticArray {
+            header: Header {
+                stamp: Self::now()?,
+                frame_id: String::new(),
+            },
+            status,
+        })
+    }
+}
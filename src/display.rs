@@ -0,0 +1,235 @@
+use r2r::geometry_msgs::msg::Pose;
+use r2r::std_msgs::msg::Header;
+use r2r::visualization_msgs::msg::{InteractiveMarker, InteractiveMarkerFeedback, Marker};
+use r2r_interactive_markers::InteractiveMarkerServer;
+use r2r_regular_markers::RegularMarkerServer;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A callback receiving the feedback of an interactive marker from RViz.
+pub type FeedbackCallback = Arc<dyn Fn(InteractiveMarkerFeedback) + Send + Sync>;
+
+/// Where the server shows its markers.
+///
+/// The default backend publishes interactive and regular markers for RViz. Implement this to
+/// show the markers elsewhere, e.g. in another visualization tool, or to record the changes and
+/// play back feedback in tests. Pass the backend to `TeachingMarkerServer::new_with_backend`.
+///
+/// Changes are staged by `insert`, `set_pose` and `erase` and shown together on `apply_changes`,
+/// likewise for the visuals.
+pub trait MarkerBackend: Send + Sync {
+    /// Stages an interactive marker, replacing the one with the same name.
+    fn insert(&self, marker: InteractiveMarker);
+
+    /// Sets the callback receiving the feedback of an interactive marker, `None` to remove it.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the interactive marker.
+    /// * `callback` - Must be called with the feedback of the user.
+    /// * `feedback_type` - The `InteractiveMarkerFeedback` event type the callback is for.
+    fn set_callback(&self, name: &str, callback: Option<FeedbackCallback>, feedback_type: u8);
+
+    /// Stages a new pose of an interactive marker, in the frame of the header if given.
+    fn set_pose(&self, name: &str, pose: Pose, header: Option<Header>);
+
+    /// Stages the removal of an interactive marker.
+    fn erase(&self, name: &str);
+
+    /// Shows the staged changes of the interactive markers.
+    fn apply_changes(&self);
+
+    /// Stages a visual or overlay, replacing the one with the same name.
+    fn insert_visual(&self, name: &str, marker: Marker);
+
+    /// Stages the removal of a visual or overlay.
+    fn erase_visual(&self, name: &str);
+
+    /// Shows the staged changes of the visuals and overlays.
+    fn apply_visual_changes(&self);
+}
+
+/// The default backend, showing the markers in RViz.
+pub(crate) struct RvizBackend {
+    pub(crate) interactive: InteractiveMarkerServer,
+    /// `None` if the regular marker topic is opted out, see `ServerConfig::regular_markers`.
+    pub(crate) regular: Option<RegularMarkerServer>,
+}
+
+impl MarkerBackend for RvizBackend {
+    fn insert(&self, marker: InteractiveMarker) {
+        self.interactive.insert(marker);
+    }
+
+    fn set_callback(&self, name: &str, callback: Option<FeedbackCallback>, feedback_type: u8) {
+        self.interactive.set_callback(name, callback, feedback_type);
+    }
+
+    fn set_pose(&self, name: &str, pose: Pose, header: Option<Header>) {
+        self.interactive.set_pose(name, pose, header);
+    }
+
+    fn erase(&self, name: &str) {
+        self.interactive.erase(name);
+    }
+
+    fn apply_changes(&self) {
+        self.interactive.apply_changes();
+    }
+
+    fn insert_visual(&self, name: &str, marker: Marker) {
+        if let Some(regular) = &self.regular {
+            regular.insert(name, marker);
+        }
+    }
+
+    fn erase_visual(&self, name: &str) {
+        if let Some(regular) = &self.regular {
+            regular.erase(name);
+        }
+    }
+
+    fn apply_visual_changes(&self) {
+        if let Some(regular) = &self.regular {
+            regular.apply_changes();
+        }
+    }
+}
+
+#[derive(Clone)]
+/// The interactive markers shown by the backend, or nothing for a headless server.
+///
+/// Changes are dropped while the server is inactive, see `TeachingMarkerServer::deactivate`.
+pub(crate) struct InteractiveMarkers {
+    backend: Option<Arc<dyn MarkerBackend>>,
+    active: Arc<AtomicBool>,
+    deferred: Arc<AtomicBool>,
+    pending: Arc<AtomicBool>,
+}
+
+impl InteractiveMarkers {
+    /// Shows the interactive markers with a backend, nothing if `None`.
+    ///
+    /// Changes are held back while `deferred` is set, until `commit` is called.
+    pub(crate) fn new(
+        backend: Option<Arc<dyn MarkerBackend>>,
+        active: Arc<AtomicBool>,
+        deferred: Arc<AtomicBool>,
+    ) -> Self {
+        InteractiveMarkers {
+            backend,
+            active,
+            deferred,
+            pending: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// The backend to apply changes to, `None` if headless or inactive.
+    fn target(&self) -> Option<&dyn MarkerBackend> {
+        self.backend.as_deref().filter(|_| self.active.load(Ordering::SeqCst))
+    }
+
+    pub(crate) fn insert(&self, marker: InteractiveMarker) {
+        if let Some(backend) = self.target() {
+            backend.insert(marker);
+        }
+    }
+
+    pub(crate) fn set_callback(&self, name: &str, callback: Option<FeedbackCallback>, feedback_type: u8) {
+        if let Some(backend) = self.target() {
+            backend.set_callback(name, callback, feedback_type);
+        }
+    }
+
+    pub(crate) fn set_pose(&self, name: &str, pose: Pose, header: Option<Header>) {
+        if let Some(backend) = self.target() {
+            backend.set_pose(name, pose, header);
+        }
+    }
+
+    pub(crate) fn erase(&self, name: &str) {
+        if let Some(backend) = self.target() {
+            backend.erase(name);
+        }
+    }
+
+    pub(crate) fn apply_changes(&self) {
+        if self.deferred.load(Ordering::SeqCst) {
+            self.pending.store(true, Ordering::SeqCst);
+        } else if let Some(backend) = self.target() {
+            backend.apply_changes();
+        }
+    }
+
+    /// Applies the changes held back in deferred mode, if any.
+    pub(crate) fn commit(&self) {
+        if self.pending.swap(false, Ordering::SeqCst) {
+            if let Some(backend) = self.target() {
+                backend.apply_changes();
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+/// The regular markers shown by the backend, e.g. visuals and overlays, or nothing for a headless server.
+///
+/// Changes are dropped while the server is inactive, see `TeachingMarkerServer::deactivate`.
+pub(crate) struct RegularMarkers {
+    backend: Option<Arc<dyn MarkerBackend>>,
+    active: Arc<AtomicBool>,
+    deferred: Arc<AtomicBool>,
+    pending: Arc<AtomicBool>,
+}
+
+impl RegularMarkers {
+    /// Shows the regular markers with a backend, nothing if `None`.
+    ///
+    /// Changes are held back while `deferred` is set, until `commit` is called.
+    pub(crate) fn new(
+        backend: Option<Arc<dyn MarkerBackend>>,
+        active: Arc<AtomicBool>,
+        deferred: Arc<AtomicBool>,
+    ) -> Self {
+        RegularMarkers {
+            backend,
+            active,
+            deferred,
+            pending: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// The backend to apply changes to, `None` if headless or inactive.
+    fn target(&self) -> Option<&dyn MarkerBackend> {
+        self.backend.as_deref().filter(|_| self.active.load(Ordering::SeqCst))
+    }
+
+    pub(crate) fn insert(&self, name: &str, marker: Marker) {
+        if let Some(backend) = self.target() {
+            backend.insert_visual(name, marker);
+        }
+    }
+
+    pub(crate) fn erase(&self, name: &str) {
+        if let Some(backend) = self.target() {
+            backend.erase_visual(name);
+        }
+    }
+
+    pub(crate) fn apply_changes(&self) {
+        if self.deferred.load(Ordering::SeqCst) {
+            self.pending.store(true, Ordering::SeqCst);
+        } else if let Some(backend) = self.target() {
+            backend.apply_visual_changes();
+        }
+    }
+
+    /// Applies the changes held back in deferred mode, if any.
+    pub(crate) fn commit(&self) {
+        if self.pending.swap(false, Ordering::SeqCst) {
+            if let Some(backend) = self.target() {
+                backend.apply_visual_changes();
+            }
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use r2r::visualization_msgs::msg::InteractiveMarkerFeedback;
+use r2r::QosProfile;
+
+use crate::{Result, TeachingMarkerServer, NODE_ID};
+
+impl TeachingMarkerServer {
+    /// Republishes the RViz feedback the server acts upon on `<namespace>/feedback_echo`.
+    ///
+    /// # Remarks
+    ///
+    /// Only feedback that results in a published transform is echoed, after the conflict,
+    /// pause, lock, collision and validity checks, so recording the topic together with `tf`
+    /// shows exactly what drove the taught poses. The messages are echoed unchanged, the pose
+    /// may differ from the published one if constraints or snapping apply. Calling it again
+    /// recreates the publisher.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the publisher can't be created.
+    pub fn publish_feedback_echo(&self) -> Result<()> {
+        let publisher = self.node.lock().unwrap().create_publisher::<InteractiveMarkerFeedback>(
+            &format!("{}/feedback_echo", self.namespace),
+            QosProfile::default(),
+        )?;
+        *self.feedback_echo.lock().unwrap() = Some(publisher);
+        Ok(())
+    }
+
+    /// Publishes feedback on the echo topic, if enabled with `publish_feedback_echo`.
+    pub(crate) fn echo_feedback(&self, feedback: &InteractiveMarkerFeedback) {
+        if let Some(publisher) = self.feedback_echo.lock().unwrap().as_ref() {
+            if let Err(e) = publisher.publish(feedback) {
+                r2r::log_error!(NODE_ID, "Failed to echo the feedback with: '{}'.", e);
+            }
+        }
+    }
+}
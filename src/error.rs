@@ -0,0 +1,118 @@
+use std::fmt;
+
+#[derive(Debug)]
+/// Errors that can occur in the teaching marker server.
+pub enum TeachingMarkerError {
+    /// An error reported by r2r, e.g. when creating a publisher, publishing or reading the clock.
+    Ros(r2r::Error),
+    /// The channel to the TF publishing thread has been closed.
+    ChannelClosed,
+    /// No teaching marker with the given name exists.
+    MarkerNotFound(String),
+    /// A pose for the named marker contained NaN or infinite values, or a degenerate quaternion.
+    InvalidPose(String),
+    /// The server has been shut down.
+    ShutDown,
+    /// The transform of a child frame relative to a parent frame, in that order, couldn't be looked up.
+    TransformUnavailable(String, String),
+    /// The frame didn't become available in TF before the timeout.
+    FrameTimeout(String),
+    /// No marker group with the given name exists.
+    GroupNotFound(String),
+    /// The named marker isn't in the expected frame.
+    FrameMismatch(String, String),
+    /// No pose with the given name exists in the named pose library.
+    NamedPoseNotFound(String),
+    /// Reading or writing a file failed.
+    Io(std::io::Error),
+    /// A marker file couldn't be serialized or parsed.
+    Serialization(String),
+    /// A node parameter or a value parsed from it is invalid.
+    InvalidParameter(String),
+    /// The index is outside of the named waypoint sequence.
+    WaypointOutOfRange(String, usize),
+    /// A teaching marker with the given name already exists.
+    MarkerExists(String),
+    /// A URDF robot description couldn't be parsed or lacks a requested link.
+    InvalidUrdf(String),
+    /// The named marker has no visual state with the given name.
+    VisualStateNotFound(String, String),
+    /// Waiting for the frames of the named marker would block an async runtime.
+    BlockingWait(String),
+}
+
+/// A specialized `Result` type for teaching marker operations.
+pub type Result<T> = std::result::Result<T, TeachingMarkerError>;
+
+impl fmt::Display for TeachingMarkerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TeachingMarkerError::Ros(e) => write!(f, "ROS error: {}", e),
+            TeachingMarkerError::ChannelClosed => write!(f, "TF publishing channel closed"),
+            TeachingMarkerError::MarkerNotFound(name) => {
+                write!(f, "teaching marker '{}' not found", name)
+            }
+            TeachingMarkerError::InvalidPose(name) => {
+                write!(f, "invalid pose for teaching marker '{}'", name)
+            }
+            TeachingMarkerError::ShutDown => write!(f, "teaching marker server shut down"),
+            TeachingMarkerError::TransformUnavailable(parent, child) => {
+                write!(f, "transform from '{}' to '{}' not available", parent, child)
+            }
+            TeachingMarkerError::FrameTimeout(frame) => {
+                write!(f, "timed out waiting for frame '{}'", frame)
+            }
+            TeachingMarkerError::GroupNotFound(group) => {
+                write!(f, "marker group '{}' not found", group)
+            }
+            TeachingMarkerError::FrameMismatch(name, frame) => {
+                write!(f, "teaching marker '{}' is not in frame '{}'", name, frame)
+            }
+            TeachingMarkerError::NamedPoseNotFound(name) => {
+                write!(f, "named pose '{}' not found", name)
+            }
+            TeachingMarkerError::Io(e) => write!(f, "I/O error: {}", e),
+            TeachingMarkerError::Serialization(e) => {
+                write!(f, "invalid marker file: {}", e)
+            }
+            TeachingMarkerError::InvalidParameter(e) => write!(f, "invalid parameter: {}", e),
+            TeachingMarkerError::WaypointOutOfRange(sequence, index) => {
+                write!(f, "waypoint {} out of range for sequence '{}'", index, sequence)
+            }
+            TeachingMarkerError::MarkerExists(name) => {
+                write!(f, "teaching marker '{}' already exists", name)
+            }
+            TeachingMarkerError::InvalidUrdf(e) => write!(f, "invalid URDF: {}", e),
+            TeachingMarkerError::VisualStateNotFound(name, state) => {
+                write!(f, "teaching marker '{}' has no visual state '{}'", name, state)
+            }
+            TeachingMarkerError::BlockingWait(name) => write!(
+                f,
+                "waiting for the frames of teaching marker '{}' would block the async runtime, use insert_marker_async",
+                name
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TeachingMarkerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TeachingMarkerError::Ros(e) => Some(e),
+            TeachingMarkerError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<r2r::Error> for TeachingMarkerError {
+    fn from(e: r2r::Error) -> Self {
+        TeachingMarkerError::Ros(e)
+    }
+}
+
+impl From<std::io::Error> for TeachingMarkerError {
+    fn from(e: std::io::Error) -> Self {
+        TeachingMarkerError::Io(e)
+    }
+}
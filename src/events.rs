@@ -0,0 +1,94 @@
+use futures::channel::mpsc;
+use futures::{Stream, StreamExt};
+use r2r::geometry_msgs::msg::PoseStamped;
+use r2r::std_msgs::msg::String as StringMsg;
+use r2r::QosProfile;
+use serde::Serialize;
+
+use crate::{Result, TeachingMarkerServer, NODE_ID};
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+/// Teaching activity on a server, see `TeachingMarkerServer::events`.
+///
+/// Serialized as JSON with the kind in `event`, e.g.
+/// `{"event":"removed","marker":"pick"}`.
+pub enum TeachingEvent {
+    /// A marker has been inserted at its initial pose.
+    Created { marker: String, pose: PoseStamped },
+    /// A marker has been moved to a final pose programmatically, e.g. with `set_pose`.
+    Moved { marker: String, pose: PoseStamped },
+    /// The RViz user released a marker at its taught pose.
+    Released { marker: String, pose: PoseStamped },
+    /// The pose of a marker created with `require_confirmation` has been confirmed.
+    Confirmed { marker: String, pose: PoseStamped },
+    /// A marker has been removed.
+    Removed { marker: String },
+    /// The feedback of a client has been rejected because another client is dragging the marker.
+    ///
+    /// Reported once per client and drag, `sequence` counts the drags of the marker.
+    Conflict {
+        marker: String,
+        owner: String,
+        client: String,
+        sequence: u64,
+    },
+}
+
+impl TeachingMarkerServer {
+    /// Returns a stream of the teaching activity on the server.
+    ///
+    /// # Remarks
+    ///
+    /// Intermediate poses while dragging aren't reported, use `pose_updates` for those.
+    /// The stream ends when the server shuts down.
+    pub fn events(&self) -> impl Stream<Item = TeachingEvent> {
+        let (tx, rx) = mpsc::unbounded();
+        self.event_streams.lock().unwrap().push(tx);
+        rx
+    }
+
+    /// Publishes the teaching activity as JSON on `<namespace>/events`, see `TeachingEvent`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the publisher can't be created.
+    pub fn publish_events(&self) -> Result<()> {
+        let publisher = self.node.lock().unwrap().create_publisher::<StringMsg>(
+            &format!("{}/events", self.namespace),
+            QosProfile::default(),
+        )?;
+
+        let mut events = self.events();
+        self.executor.spawn(async move {
+            while let Some(event) = events.next().await {
+                let data = match serde_json::to_string(&event) {
+                    Ok(data) => data,
+                    Err(e) => {
+                        r2r::log_error!(NODE_ID, "Failed to serialize the event with: '{}'.", e);
+                        continue;
+                    }
+                };
+                if let Err(e) = publisher.publish(&StringMsg { data }) {
+                    r2r::log_error!(NODE_ID, "Failed to publish the event with: '{}'.", e);
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Sends an event to all event streams, dropping the streams whose receivers have gone away.
+    pub(crate) fn emit(&self, event: TeachingEvent) {
+        self.event_streams
+            .lock()
+            .unwrap()
+            .retain(|stream| stream.unbounded_send(event.clone()).is_ok());
+    }
+
+    /// Sends an event holding the current pose of a marker, unless the marker is gone.
+    pub(crate) fn emit_pose(&self, name: &str, event: fn(String, PoseStamped) -> TeachingEvent) {
+        if let Ok(pose) = self.pose(name) {
+            self.emit(event(name.to_string(), pose));
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use futures::future::{AbortHandle, Abortable};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::Result;
+
+/// The number of threads running the background tasks of the servers without a runtime of their own.
+const BACKGROUND_THREADS: usize = 2;
+
+/// The runtime shared by all servers running their background work on dedicated threads.
+///
+/// It is started by the first server and lives until the process exits, so it is never dropped
+/// from inside one of its tasks.
+///
+/// # Errors
+///
+/// Returns an error if the threads can't be started.
+fn background_runtime() -> Result<tokio::runtime::Handle> {
+    static RUNTIME: Mutex<Option<tokio::runtime::Runtime>> = Mutex::new(None);
+    let mut runtime = RUNTIME.lock().unwrap();
+    if let Some(runtime) = runtime.as_ref() {
+        return Ok(runtime.handle().clone());
+    }
+    let started = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(BACKGROUND_THREADS)
+        .thread_name("teaching_markers")
+        .enable_all()
+        .build()?;
+    let handle = started.handle().clone();
+    *runtime = Some(started);
+    Ok(handle)
+}
+
+#[derive(Clone)]
+/// Spawns the background tasks of a server and keeps track of them so they can be stopped.
+pub(crate) struct Executor {
+    handle: tokio::runtime::Handle,
+    tasks: Arc<Mutex<HashMap<u64, AbortHandle>>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl Executor {
+    /// An executor running the tasks on a few dedicated threads shared by all servers.
+    ///
+    /// Markers only add lightweight tasks, no matter how many overlays, waypoints or
+    /// measurements follow their poses.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the threads can't be started.
+    pub(crate) fn threads() -> Result<Self> {
+        Ok(Self::tokio(background_runtime()?))
+    }
+
+    /// An executor spawning tasks on a tokio runtime.
+    pub(crate) fn tokio(handle: tokio::runtime::Handle) -> Self {
+        Executor {
+            handle,
+            tasks: Arc::new(Mutex::new(HashMap::new())),
+            next_id: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Spawns a background task.
+    ///
+    /// # Arguments
+    ///
+    /// * `future` - The task to run.
+    pub(crate) fn spawn<F>(&self, future: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let (abort_handle, abort_registration) = AbortHandle::new_pair();
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.tasks.lock().unwrap().insert(id, abort_handle);
+
+        // Finished tasks remove themselves, so the list only holds the running ones
+        let tasks = self.tasks.clone();
+        self.handle.spawn(async move {
+            let _ = Abortable::new(future, abort_registration).await;
+            tasks.lock().unwrap().remove(&id);
+        });
+    }
+
+    /// Waits for a duration inside a task spawned on this executor, yielding to the other tasks.
+    pub(crate) async fn sleep(&self, duration: Duration) {
+        tokio::time::sleep(duration).await;
+    }
+
+    /// Stops all spawned tasks.
+    pub(crate) fn abort_all(&self) {
+        for (_, task) in self.tasks.lock().unwrap().drain() {
+            task.abort();
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use r2r::geometry_msgs::msg::PoseStamped;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::{Result, TeachingMarkerError, TeachingMarkerServer};
+
+#[derive(Serialize)]
+/// A named Cartesian target as written by `export_moveit_targets`.
+struct CartesianTarget {
+    frame_id: String,
+    position: [f64; 3],
+    orientation: [f64; 4],
+}
+
+#[derive(Serialize)]
+/// The contents of a named target file.
+struct TargetFile {
+    named_targets: BTreeMap<String, CartesianTarget>,
+}
+
+/// Escapes the characters with a meaning in XML attribute values.
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+impl TeachingMarkerServer {
+    /// Writes the current marker poses as a YAML file of named Cartesian targets.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The file to write.
+    /// * `frame` - The frame to express all targets in, e.g. the planning frame of MoveIt, or
+    ///   `None` to keep each pose relative to its marker's parent frame.
+    ///
+    /// # Remarks
+    ///
+    /// The file has a `named_targets` map from marker names to a `frame_id`, a `position` as
+    /// `[x, y, z]` and an `orientation` as `[x, y, z, w]`, e.g. for `setPoseTarget` in MoveIt.
+    /// Converting into `frame` needs a transform lookup, see `listen_tf`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::TransformUnavailable` if a pose can't be converted, or an
+    /// error if the file can't be written.
+    pub fn export_moveit_targets<P: AsRef<Path>>(&self, path: P, frame: Option<&str>) -> Result<()> {
+        let mut named_targets = BTreeMap::new();
+        for name in self.names() {
+            let pose = match self.export_pose(&name, frame)? {
+                Some(pose) => pose,
+                None => continue,
+            };
+            let (p, q) = (&pose.pose.position, &pose.pose.orientation);
+            named_targets.insert(
+                name,
+                CartesianTarget {
+                    frame_id: pose.header.frame_id.clone(),
+                    position: [p.x, p.y, p.z],
+                    orientation: [q.x, q.y, q.z, q.w],
+                },
+            );
+        }
+        let contents = serde_yaml::to_string(&TargetFile { named_targets })
+            .map_err(|e| TeachingMarkerError::Serialization(e.to_string()))?;
+        std::fs::write(path, contents)?;
+        Ok(())
+    }
+
+    /// Writes the current marker poses as SRDF group states, to paste into the SRDF of a robot.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The file to write the `<group_state>` elements to.
+    /// * `group` - The name of the planning group of the states.
+    /// * `frame` - The frame the poses are passed to `ik` in, or `None` for each marker's parent frame.
+    /// * `ik` - Solves the inverse kinematics of a pose, returning the joint names and values,
+    ///   or `None` if the pose can't be reached. E.g. a call to the `compute_ik` service of MoveIt.
+    ///
+    /// # Returns
+    ///
+    /// The names of the markers that were skipped because `ik` found no solution.
+    ///
+    /// # Remarks
+    ///
+    /// Group states are joint configurations, so each taught pose goes through `ik` first.
+    /// The states are named after the markers.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::TransformUnavailable` if a pose can't be converted, or an
+    /// error if the file can't be written.
+    pub fn export_srdf_group_states<P, F>(
+        &self,
+        path: P,
+        group: &str,
+        frame: Option<&str>,
+        ik: F,
+    ) -> Result<Vec<String>>
+    where
+        P: AsRef<Path>,
+        F: Fn(&PoseStamped) -> Option<Vec<(String, f64)>>,
+    {
+        let mut contents = String::new();
+        let mut skipped = vec![];
+        for name in self.names() {
+            let pose = match self.export_pose(&name, frame)? {
+                Some(pose) => pose,
+                None => continue,
+            };
+            let joints = match ik(&pose) {
+                Some(joints) => joints,
+                None => {
+                    skipped.push(name);
+                    continue;
+                }
+            };
+            contents.push_str(&format!(
+                "<group_state name=\"{}\" group=\"{}\">\n",
+                xml_escape(&name),
+                xml_escape(group)
+            ));
+            for (joint, value) in joints {
+                contents.push_str(&format!(
+                    "    <joint name=\"{}\" value=\"{}\"/>\n",
+                    xml_escape(&joint),
+                    value
+                ));
+            }
+            contents.push_str("</group_state>\n");
+        }
+        std::fs::write(path, contents)?;
+        Ok(skipped)
+    }
+
+    /// Returns the pose of a marker to export, expressed in `frame` if given.
+    ///
+    /// # Returns
+    ///
+    /// `None` if the marker was removed in the meantime.
+    fn export_pose(&self, name: &str, frame: Option<&str>) -> Result<Option<PoseStamped>> {
+        let mut pose = match self.pose(name) {
+            Ok(pose) => pose,
+            Err(TeachingMarkerError::MarkerNotFound(_)) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        if let Some(frame) = frame {
+            pose.pose = self
+                .pose_in_frame(frame, &pose.header.frame_id, &pose.pose)
+                .ok_or_else(|| {
+                    TeachingMarkerError::TransformUnavailable(frame.to_string(), pose.header.frame_id.clone())
+                })?;
+            pose.header.frame_id = frame.to_string();
+        }
+        Ok(Some(pose))
+    }
+}
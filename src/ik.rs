@@ -0,0 +1,126 @@
+use futures::future::BoxFuture;
+use r2r::geometry_msgs::msg::PoseStamped;
+use std::future::Future;
+use std::sync::Arc;
+
+use crate::overlay::tinted;
+use crate::{Result, TeachingMarkerError, TeachingMarkerServer};
+
+/// Color of the visual of a marker whose pose is reachable.
+const REACHABLE_RGB: [f32; 3] = [0.1, 0.8, 0.1];
+
+/// Color of the visual of a marker whose pose can't be reached.
+const UNREACHABLE_RGB: [f32; 3] = [0.9, 0.1, 0.1];
+
+/// A user check deciding whether a taught pose can be reached, e.g. by calling an IK service.
+pub(crate) type ReachabilityCheck = Arc<dyn Fn(PoseStamped) -> BoxFuture<'static, bool> + Send + Sync>;
+
+impl TeachingMarkerServer {
+    /// Colors the visual of a marker by whether its pose can be reached, checked on every release.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the marker.
+    /// * `check` - Resolves to `true` if the pose, relative to the marker's parent frame, can be
+    ///   reached. E.g. a request to the `compute_ik` service of MoveIt with an r2r client.
+    ///
+    /// # Remarks
+    ///
+    /// The visual turns green or red once the check resolves, the check runs in the background so
+    /// RViz stays responsive. Only the result of the latest release is shown. Replaces any
+    /// previous check of the marker, markers without a visual aren't colored.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::MarkerNotFound` if no marker with the given name exists.
+    pub fn check_reachability<F, Fut>(&self, name: &str, check: F) -> Result<()>
+    where
+        F: Fn(PoseStamped) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = bool> + Send + 'static,
+    {
+        let check: ReachabilityCheck = Arc::new(move |pose| Box::pin(check(pose)));
+        match self.markers.lock().unwrap().get_mut(name) {
+            Some(state) => state.reachability_check = Some(check),
+            None => return Err(TeachingMarkerError::MarkerNotFound(name.to_string())),
+        }
+        self.run_reachability_check(name);
+        Ok(())
+    }
+
+    /// Stops checking the reachability of a marker and restores its visual.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::MarkerNotFound` if no marker with the given name exists.
+    pub fn stop_checking_reachability(&self, name: &str) -> Result<()> {
+        let visual = match self.markers.lock().unwrap().get_mut(name) {
+            Some(state) => {
+                state.reachability_check = None;
+                state.reachable = None;
+                // Invalidates a check still running
+                state.reachability_runs += 1;
+                state.config.visual.clone()
+            }
+            None => return Err(TeachingMarkerError::MarkerNotFound(name.to_string())),
+        };
+        if let Some(visual) = visual {
+            self.regular_marker_server.insert(name, visual);
+            self.regular_marker_server.apply_changes();
+        }
+        Ok(())
+    }
+
+    /// Returns whether the pose of a marker was found reachable by the latest check.
+    ///
+    /// # Returns
+    ///
+    /// `None` without a check or while the first one is running.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::MarkerNotFound` if no marker with the given name exists.
+    pub fn is_reachable(&self, name: &str) -> Result<Option<bool>> {
+        match self.markers.lock().unwrap().get(name) {
+            Some(state) => Ok(state.reachable),
+            None => Err(TeachingMarkerError::MarkerNotFound(name.to_string())),
+        }
+    }
+
+    /// Checks the current pose of a marker in the background and colors its visual by the result.
+    pub(crate) fn run_reachability_check(&self, name: &str) {
+        let (check, run) = match self.markers.lock().unwrap().get_mut(name) {
+            Some(state) => match &state.reachability_check {
+                Some(check) => {
+                    state.reachability_runs += 1;
+                    (check.clone(), state.reachability_runs)
+                }
+                None => return,
+            },
+            None => return,
+        };
+        let pose = match self.pose(name) {
+            Ok(pose) => pose,
+            Err(_) => return,
+        };
+
+        let server = self.handle();
+        let name = name.to_string();
+        self.executor.spawn(async move {
+            let reachable = check(pose).await;
+            let visual = match server.markers.lock().unwrap().get_mut(&name) {
+                // A newer release or a removed check supersedes this result
+                Some(state) if state.reachability_runs == run => {
+                    state.reachable = Some(reachable);
+                    // The collision color takes precedence
+                    state.config.visual.clone().filter(|_| !state.colliding)
+                }
+                _ => return,
+            };
+            if let Some(visual) = visual {
+                let rgb = if reachable { REACHABLE_RGB } else { UNREACHABLE_RGB };
+                server.regular_marker_server.insert(&name, tinted(&visual, rgb));
+                server.regular_marker_server.apply_changes();
+            }
+        });
+    }
+}
@@ -0,0 +1,414 @@
+use futures::StreamExt;
+use r2r::geometry_msgs::msg::{Pose, TwistStamped, Vector3};
+use r2r::sensor_msgs::msg::Joy;
+use r2r::QosProfile;
+use std::time::{Duration, Instant};
+
+use crate::math::{compose, quaternion_multiply, rpy_to_quaternion};
+use crate::{
+    pose_to_transform, transform_to_pose, Result, TeachingMarkerError, TeachingMarkerServer,
+    NODE_ID,
+};
+
+/// Longest time step integrated at once, so a late message doesn't make the marker jump.
+const MAX_JOG_STEP: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+/// Enum representing the axes jogging velocities are expressed in.
+pub enum JogFrame {
+    /// The axes of the marker itself, like jogging a robot tool.
+    #[default]
+    Marker,
+    /// The axes of the parent frame of the marker, like jogging a robot in world coordinates.
+    Parent,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// How the axes of a `sensor_msgs/Joy` message map onto the velocity of a jogged marker.
+///
+/// The default suits common gamepads: the left stick moves in X and Y, the right stick moves
+/// in Z and yaws.
+pub struct JoyMapping {
+    /// The joystick axes driving the linear velocity along X, Y and Z, `None` leaves an axis still.
+    pub linear_axes: [Option<usize>; 3],
+    /// The joystick axes driving the angular velocity around X, Y and Z.
+    pub angular_axes: [Option<usize>; 3],
+    /// The linear velocity in m/s at full deflection.
+    pub linear_speed: f64,
+    /// The angular velocity in rad/s at full deflection.
+    pub angular_speed: f64,
+    /// Deflections smaller than this are treated as zero.
+    pub deadzone: f64,
+    /// The axes the velocities are expressed in.
+    pub frame: JogFrame,
+}
+
+impl Default for JoyMapping {
+    fn default() -> Self {
+        JoyMapping {
+            linear_axes: [Some(1), Some(0), Some(4)],
+            angular_axes: [None, None, Some(3)],
+            linear_speed: 0.05,
+            angular_speed: 0.5,
+            deadzone: 0.1,
+            frame: JogFrame::Marker,
+        }
+    }
+}
+
+impl JoyMapping {
+    /// Computes the linear and angular velocity commanded by a joystick message.
+    fn velocities(&self, joy: &Joy) -> (Vector3, Vector3) {
+        let axis = |index: Option<usize>, speed: f64| {
+            let value = index
+                .and_then(|i| joy.axes.get(i))
+                .map(|value| *value as f64)
+                .unwrap_or(0.0);
+            if value.abs() < self.deadzone {
+                0.0
+            } else {
+                value * speed
+            }
+        };
+        let vector = |axes: &[Option<usize>; 3], speed: f64| Vector3 {
+            x: axis(axes[0], speed),
+            y: axis(axes[1], speed),
+            z: axis(axes[2], speed),
+        };
+        (
+            vector(&self.linear_axes, self.linear_speed),
+            vector(&self.angular_axes, self.angular_speed),
+        )
+    }
+}
+
+/// Returns `true` if a vector is exactly zero.
+fn is_zero(v: &Vector3) -> bool {
+    v.x == 0.0 && v.y == 0.0 && v.z == 0.0
+}
+
+#[derive(Default)]
+/// Integrates a stream of velocity commands into the pose of the jogged marker.
+struct JogIntegrator {
+    /// When the previous command arrived.
+    last: Option<Instant>,
+    /// The marker moved by the previous command, whose final pose hasn't been published yet.
+    jogging: Option<String>,
+}
+
+impl JogIntegrator {
+    /// Applies a velocity command to a marker.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the target marker no longer exists.
+    fn apply(
+        &mut self,
+        server: &TeachingMarkerServer,
+        target: Option<String>,
+        linear: &Vector3,
+        angular: &Vector3,
+        frame: JogFrame,
+    ) -> bool {
+        let now = Instant::now();
+        let step = self
+            .last
+            .map(|last| now.duration_since(last).min(MAX_JOG_STEP))
+            .unwrap_or(Duration::ZERO);
+        self.last = Some(now);
+
+        let moving = !(is_zero(linear) && is_zero(angular));
+        // Latch the last pose when the motion stops or another marker is targeted
+        if let Some(name) = self.jogging.take() {
+            if moving && target.as_ref() == Some(&name) {
+                self.jogging = Some(name);
+            } else {
+                server.finish_jog(&name);
+            }
+        }
+        let name = match target {
+            Some(name) if moving => name,
+            _ => return false,
+        };
+        match server.jog(&name, linear, angular, frame, step) {
+            Ok(()) => {
+                self.jogging = Some(name);
+                false
+            }
+            Err(TeachingMarkerError::MarkerNotFound(_)) => true,
+            Err(e) => {
+                r2r::log_error!(NODE_ID, "Failed to jog '{}' with: '{}'.", name, e);
+                false
+            }
+        }
+    }
+}
+
+impl TeachingMarkerServer {
+    /// Selects the marker driven by the joystick, see `jog_with_joy`.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the marker, or `None` to stop jogging.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::MarkerNotFound` if no marker with the given name exists.
+    pub fn select_for_jogging(&self, name: Option<&str>) -> Result<()> {
+        if let Some(name) = name {
+            if !self.markers.lock().unwrap().contains_key(name) {
+                return Err(TeachingMarkerError::MarkerNotFound(name.to_string()));
+            }
+        }
+        *self.jog_target.lock().unwrap() = name.map(|name| name.to_string());
+        Ok(())
+    }
+
+    /// Returns the name of the marker driven by the joystick, if any.
+    pub fn selected_for_jogging(&self) -> Option<String> {
+        self.jog_target.lock().unwrap().clone()
+    }
+
+    /// Jogs the selected marker with a joystick, like a teach pendant.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The `sensor_msgs/Joy` topic, e.g. `joy` of the `joy` package.
+    /// * `mapping` - How the joystick axes map onto the velocity of the marker.
+    ///
+    /// # Remarks
+    ///
+    /// The marker is selected with `select_for_jogging` or the "Jog with joystick" entry of its
+    /// context menu, see `set_jog_menu`. Velocities are integrated between messages, so the joy
+    /// node should publish repeatedly while a stick is held, see its `autorepeat_rate`. Releasing
+    /// the sticks publishes the final pose, which can be undone with `undo`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the subscription can't be created.
+    pub fn jog_with_joy(&self, topic: &str, mapping: JoyMapping) -> Result<()> {
+        let mut subscriber = self
+            .node
+            .lock()
+            .unwrap()
+            .subscribe::<Joy>(topic, QosProfile::default())?;
+
+        let server = self.handle();
+        self.executor.spawn(async move {
+            let mut integrator = JogIntegrator::default();
+            while let Some(joy) = subscriber.next().await {
+                let (linear, angular) = mapping.velocities(&joy);
+                let target = server.selected_for_jogging();
+                if integrator.apply(&server, target, &linear, &angular, mapping.frame) {
+                    let _ = server.select_for_jogging(None);
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Drives a marker with the velocities published on a `TwistStamped` topic, e.g. by keyboard
+    /// teleop tools or a spacemouse driver.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the marker, or `None` to drive the marker selected with `select_for_jogging`.
+    /// * `topic` - The `geometry_msgs/TwistStamped` topic.
+    /// * `frame` - The axes of twists with an empty frame ID. Twists stamped with the name of the
+    ///   marker are applied in its own axes, twists stamped with its parent frame in the parent axes.
+    ///
+    /// # Remarks
+    ///
+    /// Velocities are integrated between messages. A zero twist publishes the final pose, which
+    /// can be undone with `undo`. Following stops when a named marker is removed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the marker doesn't exist or the subscription can't be created.
+    pub fn jog_with_twist(&self, name: Option<&str>, topic: &str, frame: JogFrame) -> Result<()> {
+        if let Some(name) = name {
+            if !self.markers.lock().unwrap().contains_key(name) {
+                return Err(TeachingMarkerError::MarkerNotFound(name.to_string()));
+            }
+        }
+        let mut subscriber = self
+            .node
+            .lock()
+            .unwrap()
+            .subscribe::<TwistStamped>(topic, QosProfile::default())?;
+
+        let server = self.handle();
+        let name = name.map(|name| name.to_string());
+        self.executor.spawn(async move {
+            let mut integrator = JogIntegrator::default();
+            while let Some(twist) = subscriber.next().await {
+                let target = name.clone().or_else(|| server.selected_for_jogging());
+                let parent = target.as_ref().and_then(|target| {
+                    server
+                        .markers
+                        .lock()
+                        .unwrap()
+                        .get(target)
+                        .map(|state| state.config.frame.clone())
+                });
+                let stamped = twist.header.frame_id.as_str();
+                // Unknown markers fall through, so their removal is noticed
+                let jog_frame = if stamped.is_empty() || parent.is_none() {
+                    frame
+                } else if Some(stamped) == target.as_deref() {
+                    JogFrame::Marker
+                } else if Some(stamped) == parent.as_deref() {
+                    JogFrame::Parent
+                } else {
+                    r2r::log_warn!(
+                        NODE_ID,
+                        "Ignoring twist in frame '{}', expected '{}' or '{}'.",
+                        stamped,
+                        target.unwrap_or_default(),
+                        parent.unwrap_or_default()
+                    );
+                    continue;
+                };
+                let removed = integrator.apply(
+                    &server,
+                    target,
+                    &twist.twist.linear,
+                    &twist.twist.angular,
+                    jog_frame,
+                );
+                if removed {
+                    match &name {
+                        Some(_) => return,
+                        None => {
+                            let _ = server.select_for_jogging(None);
+                        }
+                    }
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Sets whether a marker has a "Jog with joystick" entry in its context menu, selecting it
+    /// for `jog_with_joy`.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the marker.
+    /// * `enabled` - Whether to show the entry, it is added after the entries set with `set_menu`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::MarkerNotFound` if no marker with the given name exists.
+    pub fn set_jog_menu(&self, name: &str, enabled: bool) -> Result<()> {
+        match self.markers.lock().unwrap().get_mut(name) {
+            Some(state) => state.jog_menu = enabled,
+            None => return Err(TeachingMarkerError::MarkerNotFound(name.to_string())),
+        }
+        self.refresh_marker(name)
+    }
+
+    /// Moves a marker by velocities integrated over a time step, as an intermediate pose.
+    fn jog(
+        &self,
+        name: &str,
+        linear: &Vector3,
+        angular: &Vector3,
+        frame: JogFrame,
+        step: Duration,
+    ) -> Result<()> {
+        let dt = step.as_secs_f64();
+        let mut offset = Pose::default();
+        offset.position.x = linear.x * dt;
+        offset.position.y = linear.y * dt;
+        offset.position.z = linear.z * dt;
+        offset.orientation = rpy_to_quaternion(angular.x * dt, angular.y * dt, angular.z * dt);
+
+        let pose = {
+            let markers = self.markers.lock().unwrap();
+            let state = markers
+                .get(name)
+                .ok_or_else(|| TeachingMarkerError::MarkerNotFound(name.to_string()))?;
+            // Locked and paused markers aren't jogged either
+            if state.read_only || state.paused {
+                return Ok(());
+            }
+            match frame {
+                JogFrame::Marker => transform_to_pose(&compose(
+                    &pose_to_transform(&state.pose),
+                    &pose_to_transform(&offset),
+                )),
+                JogFrame::Parent => {
+                    // Rotate around the marker position rather than the parent origin
+                    let mut pose = state.pose.clone();
+                    pose.position.x += offset.position.x;
+                    pose.position.y += offset.position.y;
+                    pose.position.z += offset.position.z;
+                    pose.orientation = quaternion_multiply(&offset.orientation, &pose.orientation);
+                    pose
+                }
+            }
+        };
+        self.move_marker(name, &pose, false)
+    }
+
+    /// Publishes the final pose of a jogged marker and records it for `undo`.
+    fn finish_jog(&self, name: &str) {
+        let pose = match self.markers.lock().unwrap().get(name) {
+            Some(state) => state.pose.clone(),
+            None => return,
+        };
+        if let Err(e) = self.set_pose(name, &pose) {
+            r2r::log_error!(NODE_ID, "Failed to finish jogging '{}' with: '{}'.", name, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::assert_vector;
+
+    fn joy(axes: &[f32]) -> Joy {
+        Joy {
+            axes: axes.to_vec(),
+            ..Joy::default()
+        }
+    }
+
+    #[test]
+    fn scales_the_axes_by_the_speeds() {
+        let mapping = JoyMapping {
+            linear_speed: 2.0,
+            angular_speed: 4.0,
+            ..JoyMapping::default()
+        };
+        let (linear, angular) = mapping.velocities(&joy(&[0.5, 1.0, 0.0, -0.25, -1.0]));
+        assert_vector(&linear, 2.0, 1.0, -2.0);
+        assert_vector(&angular, 0.0, 0.0, -1.0);
+    }
+
+    #[test]
+    fn ignores_deflections_inside_the_deadzone() {
+        let mapping = JoyMapping {
+            linear_speed: 1.0,
+            ..JoyMapping::default()
+        };
+        let (linear, angular) = mapping.velocities(&joy(&[0.05, -0.09, 0.0, 0.0, 0.5]));
+        assert_vector(&linear, 0.0, 0.0, 0.5);
+        assert!(is_zero(&angular));
+    }
+
+    #[test]
+    fn missing_and_unmapped_axes_stay_still() {
+        let mapping = JoyMapping {
+            linear_axes: [Some(0), None, Some(7)],
+            angular_axes: [None, None, None],
+            linear_speed: 1.0,
+            ..JoyMapping::default()
+        };
+        let (linear, angular) = mapping.velocities(&joy(&[1.0, 1.0]));
+        assert_vector(&linear, 1.0, 0.0, 0.0);
+        assert!(is_zero(&angular));
+    }
+}
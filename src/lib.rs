@@ -1,5 +1,9 @@
-use crossbeam::channel::unbounded;
-use r2r::geometry_msgs::msg::{Point, Pose, Quaternion, Transform, TransformStamped, Vector3};
+use futures::channel::mpsc;
+use futures::{Stream, StreamExt};
+use r2r::geometry_msgs::msg::{
+    Point, PointStamped, Pose, PoseArray, PoseStamped, PoseWithCovariance, PoseWithCovarianceStamped,
+    Quaternion, Transform, TransformStamped, Vector3,
+};
 use r2r::std_msgs::msg::Header;
 use r2r::tf2_msgs::msg::TFMessage;
 use r2r::visualization_msgs::msg::{
@@ -8,7 +12,142 @@ use r2r::visualization_msgs::msg::{
 use r2r::QosProfile;
 use r2r_interactive_markers::InteractiveMarkerServer;
 use r2r_regular_markers::RegularMarkerServer;
+use r2r::builtin_interfaces::msg::Time;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+/// Emits a `tracing` error event when built with the `tracing` feature, in addition to the ROS log.
+macro_rules! trace_error {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::error!($($arg)*);
+    };
+}
+
+mod controls;
+use controls::{
+    extra_control, extra_control_axes, profile_controls, resize_controls, GROW_CONTROL,
+    SHRINK_CONTROL,
+};
+pub use controls::{Axis, AxisMask, ControlOrientation, ControlProfile, ExtraControl};
+
+mod batch;
+
+mod collision;
+use collision::{CollisionGuard, CollisionVerdict, COLLISION_RGB};
+
+mod config;
+pub use config::ServerConfig;
+
+mod conflict;
+use conflict::DragGesture;
+
+mod constraints;
+use constraints::sanitize_pose;
+pub use constraints::{HalfSpace, PoseConstraints, Workspace};
+
+mod events;
+pub use events::TeachingEvent;
+
+mod executor;
+use executor::Executor;
+
+mod diagnostics;
+
+mod display;
+use display::{InteractiveMarkers, RegularMarkers, RvizBackend};
+pub use display::{FeedbackCallback, MarkerBackend};
+
+mod echo;
+
+mod error;
+pub use error::{Result, TeachingMarkerError};
+
+mod export;
+
+mod math;
+use math::{compose, inverse, rotation_angle, rpy_to_quaternion};
+
+mod ik;
+use ik::ReachabilityCheck;
+
+mod jog;
+pub use jog::{JogFrame, JoyMapping};
+
+mod lifecycle;
+
+mod lookup;
+pub use lookup::{TfBuffer, TransformLookup};
+
+#[cfg(test)]
+mod test_util;
+
+mod tf;
+use tf::{LatestChannel, TfPublishers, TfUpdate};
+pub use tf::{TfOutput, TransformSink};
+
+mod marker;
+use marker::FrameSnap;
+pub use marker::{
+    FeedbackEvents, SpawnAt, TeachingMarker, TeachingMarkerBuilder, DEFAULT_FRAME,
+    DEFAULT_HISTORY_DEPTH, DEFAULT_SCALE,
+};
+
+mod measure;
+
+mod metrics;
+use metrics::MetricsRecorder;
+pub use metrics::Metrics;
+
+mod menu;
+pub use menu::{MenuCallback, MenuHandler};
+
+mod mirror;
+pub use mirror::MirrorPlane;
+
+mod overlay;
+use overlay::{highlighted, tinted};
+
+mod persist;
+use persist::SavedMarker;
+
+mod reachability;
+pub use reachability::{ReachabilityMap, Voxel};
+
+mod params;
+
+mod resource;
+use resource::resolve_visual;
+pub use resource::resolve_package_uri;
+
+mod sequence;
+pub use sequence::WaypointSequence;
+
+mod smoothing;
+use smoothing::PoseFilter;
+
+mod transaction;
+pub use transaction::Transaction;
+
+mod undo;
+use undo::UndoStack;
+pub use undo::DEFAULT_UNDO_DEPTH;
+
+mod urdf;
+pub use urdf::UrdfSource;
+
+mod visual_state;
+
+#[cfg(feature = "services")]
+mod services;
+
+#[cfg(feature = "services")]
+mod teach_action;
 
 /// Node identifier
 pub static NODE_ID: &'static str = "teaching_markers_server";
@@ -16,72 +155,286 @@ pub static NODE_ID: &'static str = "teaching_markers_server";
 /// Default feedback callback value
 const DEFAULT_FEEDBACK_CB: u8 = 255;
 
+/// How often the transform lookup is polled while waiting for a frame
+const FRAME_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Factor by which a click on a resize handle grows the marker, shrinking divides by it
+const RESIZE_FACTOR: f32 = 1.25;
+
+/// Smallest and largest scale reachable with the resize handles
+const RESIZE_LIMITS: (f32, f32) = (0.01, 10.0);
+
+/// Longest pause between two poses during a replay, longer pauses are shortened to this
+const MAX_REPLAY_GAP: Duration = Duration::from_secs(1);
+
+/// A user callback invoked on every feedback received for a marker.
+type FeedbackHook = Arc<dyn Fn(&InteractiveMarkerFeedback) + Send + Sync>;
+
+#[derive(Clone)]
+/// A clickable button control attached to a teaching marker.
+struct Button {
+    name: String,
+    visual: Marker,
+    callback: FeedbackHook,
+}
+
+/// Runtime state kept by the server for each inserted teaching marker.
+struct MarkerState {
+    config: TeachingMarker,
+    pose: Pose,
+    spawn_pose: Pose,
+    dragging: bool,
+    pose_publisher: Option<r2r::Publisher<PoseStamped>>,
+    covariance_publisher: Option<r2r::Publisher<PoseWithCovarianceStamped>>,
+    feedback_hooks: Vec<FeedbackHook>,
+    pose_streams: Vec<mpsc::UnboundedSender<PoseStamped>>,
+    menu: Option<MenuHandler>,
+    active_menu: Option<MenuHandler>,
+    named_pose_menu: bool,
+    buttons: Vec<Button>,
+    locked_axes: AxisMask,
+    last_published: Option<Instant>,
+    published_pose: Option<Pose>,
+    last_feedback: Option<Instant>,
+    publish_errors: u64,
+    dropped_updates: u64,
+    smoothing: Option<PoseFilter>,
+    reachability_check: Option<ReachabilityCheck>,
+    reachable: Option<bool>,
+    reachability_runs: u64,
+    reachability_map: Option<(Arc<ReachabilityMap>, f64)>,
+    collision_guard: Option<CollisionGuard>,
+    colliding: bool,
+    mirrors: Vec<(String, MirrorPlane)>,
+    visual_states: Vec<(String, Marker)>,
+    visual_state: Option<String>,
+    confirmed: bool,
+    undo: UndoStack,
+    undo_menu: bool,
+    reset_menu: bool,
+    jog_menu: bool,
+    pose_history: VecDeque<(Time, Pose)>,
+    replaying: bool,
+    hidden: bool,
+    tf_suspended: bool,
+    read_only: bool,
+    paused: bool,
+    drag_gesture: Option<DragGesture>,
+    drag_sequence: u64,
+}
+
+impl MarkerState {
+    fn new(config: TeachingMarker, pose: Pose, pose_publisher: Option<r2r::Publisher<PoseStamped>>) -> Self {
+        let undo = UndoStack::new(config.undo_depth, pose.clone());
+        let smoothing = (config.smoothing.is_some() || config.orientation_blend.is_some()).then(|| {
+            PoseFilter::new(config.smoothing.unwrap_or(Duration::ZERO), config.orientation_blend)
+        });
+        MarkerState {
+            config,
+            spawn_pose: pose.clone(),
+            pose,
+            dragging: false,
+            pose_publisher,
+            covariance_publisher: None,
+            feedback_hooks: vec![],
+            pose_streams: vec![],
+            menu: None,
+            active_menu: None,
+            named_pose_menu: false,
+            buttons: vec![],
+            locked_axes: AxisMask::NONE,
+            last_published: None,
+            published_pose: None,
+            last_feedback: None,
+            publish_errors: 0,
+            dropped_updates: 0,
+            smoothing,
+            drag_gesture: None,
+            drag_sequence: 0,
+            reachability_check: None,
+            reachable: None,
+            reachability_runs: 0,
+            reachability_map: None,
+            collision_guard: None,
+            colliding: false,
+            mirrors: vec![],
+            visual_states: vec![],
+            visual_state: None,
+            confirmed: false,
+            undo,
+            undo_menu: false,
+            reset_menu: false,
+            jog_menu: false,
+            pose_history: VecDeque::new(),
+            replaying: false,
+            hidden: false,
+            tf_suspended: false,
+            read_only: false,
+            paused: false,
+        }
+    }
+
+    /// Whether the current pose still has to be confirmed before it is published on the configured output.
+    fn is_candidate(&self) -> bool {
+        self.config.require_confirmation && !self.confirmed
+    }
+
+    /// Where the transforms of the marker are currently published.
+    ///
+    /// Candidate poses only go to `tf`, confirmed poses to the configured output.
+    fn tf_output(&self) -> &TfOutput {
+        if self.is_candidate() {
+            &TfOutput::Dynamic
+        } else {
+            &self.config.tf_output
+        }
+    }
+
+    /// Delivers transforms of the marker to its current output, with its own QoS if it has one.
+    fn send_to(&self, sink: &mut dyn TransformSink, transforms: &TFMessage, latch: bool) -> Result<()> {
+        match &self.config.tf_qos {
+            Some(qos) => sink.send_with_qos(self.tf_output(), qos, transforms, latch),
+            None => sink.send(self.tf_output(), transforms, latch),
+        }
+    }
+}
+
+/// What to do about a received feedback once the marker map is unlocked.
+struct FeedbackActions {
+    update: Result<Option<TfUpdate>>,
+    corrected_pose: Option<Pose>,
+    callbacks: Vec<FeedbackHook>,
+    warn_invalid: bool,
+    motion: Option<Transform>,
+    latch: bool,
+    highlight: Option<Marker>,
+    resize: Option<f32>,
+    snap: Option<FrameSnap>,
+    released: bool,
+}
+
+/// Markers moved rigidly together with a handle marker.
+struct MarkerGroup {
+    handle: String,
+    members: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// Policy deciding who wins when a followed topic and the RViz user move a marker at the same time.
+pub enum FollowPolicy {
+    /// Poses from the followed topic are always applied, also while the user is dragging.
+    ExternalWins,
+    /// Poses from the followed topic are ignored while the user is dragging the marker.
+    UserWins,
+}
+
+#[derive(Clone, Debug)]
+/// A snapshot of the state of a marker, see `TeachingMarkerServer::iter`.
+pub struct MarkerInfo {
+    /// The name of the marker and its child frame.
+    pub name: String,
+    /// The parent frame of the marker.
+    pub parent: String,
+    /// The current pose relative to the parent frame.
+    pub pose: Pose,
+    /// Whether the marker is locked against moves in RViz, see `TeachingMarkerServer::lock`.
+    pub locked: bool,
+    /// Whether the marker is hidden in RViz, see `TeachingMarkerServer::hide`.
+    pub hidden: bool,
+    /// Whether the marker is paused, see `TeachingMarkerServer::pause`.
+    pub paused: bool,
+}
+
 #[derive(Clone)]
 /// A struct representing a teaching marker in the interactive marker server.
 pub struct TeachingMarkerServer {
     // markers: Vec<Markers>,
-    interactive_marker_server: InteractiveMarkerServer,
-    regular_marker_server: RegularMarkerServer
+    interactive_marker_server: InteractiveMarkers,
+    regular_marker_server: RegularMarkers,
+    node: Arc<Mutex<r2r::Node>>,
+    namespace: String,
+    markers: Arc<Mutex<HashMap<String, MarkerState>>>,
+    tf_sink: Arc<Mutex<Box<dyn TransformSink>>>,
+    tf_channel: LatestChannel,
+    executor: Executor,
+    shut_down: Arc<AtomicBool>,
+    latch_on_shutdown: Arc<AtomicBool>,
+    guard: Option<Arc<ShutdownGuard>>,
+    transform_lookup: Arc<Mutex<Option<Arc<dyn TransformLookup>>>>,
+    groups: Arc<Mutex<HashMap<String, MarkerGroup>>>,
+    pose_changes: Arc<watch::Sender<u64>>,
+    named_poses: Arc<Mutex<BTreeMap<String, PoseStamped>>>,
+    measurements: Arc<Mutex<HashMap<(String, String), String>>>,
+    jog_target: Arc<Mutex<Option<String>>>,
+    metrics: Arc<Mutex<MetricsRecorder>>,
+    event_streams: Arc<Mutex<Vec<mpsc::UnboundedSender<TeachingEvent>>>>,
+    active: Arc<AtomicBool>,
+    deferred: Arc<AtomicBool>,
+    tf_batch: Arc<Mutex<Option<Vec<TfUpdate>>>>,
+    feedback_echo: Arc<Mutex<Option<r2r::Publisher<InteractiveMarkerFeedback>>>>,
+    config: Arc<ServerConfig>,
         // More fields can be added here if needed
 }
 
-#[derive(PartialEq)]
-/// Enum representing the axes X, Y, and Z.
-enum Axis {
-    X,
-    Y,
-    Z,
+/// Shuts the server down once the last user-held clone of it is dropped.
+///
+/// Callbacks and tasks only hold handles without the guard, so they don't keep the server alive.
+struct ShutdownGuard {
+    server: TeachingMarkerServer,
 }
 
-/// Normalizes the quaternion in place.
-///
-/// # Arguments
-///
-/// * `quaternion` - A mutable reference to the quaternion to normalize.
-fn normalize_quaternion(quaternion: &mut Quaternion) {
-    let norm = quaternion.x * quaternion.x
-        + quaternion.y * quaternion.y
-        + quaternion.z * quaternion.z
-        + quaternion.w * quaternion.w;
-    let s = norm.powf(-0.5);
-    quaternion.x *= s;
-    quaternion.y *= s;
-    quaternion.z *= s;
-    quaternion.w *= s;
+impl Drop for ShutdownGuard {
+    fn drop(&mut self) {
+        if let Err(e) = self.server.shutdown() {
+            r2r::log_error!(NODE_ID, "Failed to shut down the teaching marker server with: '{}'.", e);
+        }
+    }
 }
 
-/// Prepares an interactive marker control with the specified parameters.
-///
-/// # Arguments
-///
-/// * `name` - The name of the control.
-/// * `interaction_mode` - The interaction mode for the control.
-/// * `axis` - The axis along which the control operates.
-///
-/// # Returns
-///
-/// An `InteractiveMarkerControl` configured with the given parameters.
-fn prepare_control(
-    name: &str,
-    interaction_mode: u8,
-    axis: Axis,
-    // marker: Option<Marker>,
-) -> InteractiveMarkerControl {
-    let mut control = InteractiveMarkerControl::default();
-    control.orientation = Quaternion {
-        w: 1.0,
-        x: if axis == Axis::X { 1.0 } else { 0.0 },
-        y: if axis == Axis::Y { 1.0 } else { 0.0 },
-        z: if axis == Axis::Z { 1.0 } else { 0.0 },
-    };
-    control.always_visible = true;
-    normalize_quaternion(&mut control.orientation);
-    control.name = name.to_string();
-    control.interaction_mode = interaction_mode;
-    // if let Some(marker) = marker {
-    //     control.markers.push(marker);
-    // }
-    control
+/// Returns the name of the interactive marker control of a button.
+fn button_control_name(button: &str) -> String {
+    format!("button_{button}")
+}
+
+/// Returns the identity pose.
+fn identity_pose() -> Pose {
+    Pose {
+        position: Point {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+        },
+        orientation: Quaternion {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+            w: 1.0,
+        },
+    }
+}
+
+/// Converts a pose into the equivalent transform.
+fn pose_to_transform(pose: &Pose) -> Transform {
+    Transform {
+        translation: Vector3 {
+            x: pose.position.x,
+            y: pose.position.y,
+            z: pose.position.z,
+        },
+        rotation: pose.orientation.clone(),
+    }
+}
+
+/// Converts a transform into the equivalent pose.
+fn transform_to_pose(transform: &Transform) -> Pose {
+    Pose {
+        position: Point {
+            x: transform.translation.x,
+            y: transform.translation.y,
+            z: transform.translation.z,
+        },
+        orientation: transform.rotation.clone(),
+    }
 }
 
 impl TeachingMarkerServer {
@@ -95,217 +448,3210 @@ impl TeachingMarkerServer {
     /// # Remarks
     ///
     /// This function initializes the interactive marker server and sets up publishers.
-    pub fn new(name: &str, node: Arc<Mutex<r2r::Node>>) -> Self {
-
-        let arc_node_clone = node.clone();
-        let interactive_marker_server = InteractiveMarkerServer::new(name, arc_node_clone);
-        let arc_node_clone = node.clone();
-        let regular_marker_server = RegularMarkerServer::new("teaching_marker_server", name, arc_node_clone);
+    /// Background work runs on a few dedicated threads shared by all servers, see `new_with_runtime`
+    /// to run it on an existing tokio runtime instead.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the background threads can't be started or the publishers can't be created.
+    pub fn new(name: &str, node: Arc<Mutex<r2r::Node>>) -> Result<Self> {
+        Self::with_executor(ServerConfig::new(name), node, Executor::threads()?, None, None)
+    }
 
-        TeachingMarkerServer {
-            interactive_marker_server,
-            regular_marker_server
-        }
+    /// Creates a new `TeachingMarkerServer` with custom topic names.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The namespace and topics of the server.
+    /// * `node` - A shared reference to the ROS node.
+    ///
+    /// # Remarks
+    ///
+    /// Background work runs on dedicated threads as with `new`.
+    pub fn new_with_config(config: ServerConfig, node: Arc<Mutex<r2r::Node>>) -> Result<Self> {
+        Self::with_executor(config, node, Executor::threads()?, None, None)
     }
 
-    pub fn insert(&self, name: String, spawn_at: String, spawn_at_pose: Option<Pose>, regular_marker: Option<Marker>, node: Arc<Mutex<r2r::Node>>) {
-        // Create the interactive marker
-        let marker = Self::create_marker(&name, &spawn_at, spawn_at_pose.clone());
+    /// Creates a new `TeachingMarkerServer` that only publishes transforms, without anything in RViz.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - A topic namespace for the teaching marker server.
+    /// * `node` - A shared reference to the ROS node.
+    ///
+    /// # Remarks
+    ///
+    /// No interactive or regular markers are published, markers are created and moved only
+    /// programmatically, e.g. with `insert_marker` and `set_pose`. Transforms, pose topics and
+    /// streams are published as usual, which suits robots without a GUI and tests of the nodes
+    /// consuming the taught frames. Background work runs on dedicated threads as with `new`.
+    pub fn new_headless(name: &str, node: Arc<Mutex<r2r::Node>>) -> Result<Self> {
+        Self::with_executor(ServerConfig::new(name).headless(true), node, Executor::threads()?, None, None)
+    }
 
-        // Set up a publisher for the TF messages with transient local QoS
-        let arc_node_clone = node.clone();
-        let publisher = arc_node_clone
-            .lock()
-            .unwrap()
-            .create_publisher::<TFMessage>(
-                "tf_static",
-                QosProfile::transient_local(QosProfile::default()),
-            )
-            .unwrap();
+    /// Creates a new `TeachingMarkerServer` delivering the marker transforms to a custom sink.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - A topic namespace for the teaching marker server.
+    /// * `node` - A shared reference to the ROS node.
+    /// * `sink` - Receives the transforms instead of the topics of the markers' `TfOutput`,
+    ///   e.g. a `TfBuffer` or an adapter to a transform buffer of the application.
+    ///
+    /// # Remarks
+    ///
+    /// Background work runs on dedicated threads as with `new`. Pose topics and streams are
+    /// published as usual.
+    pub fn new_with_sink<S>(name: &str, node: Arc<Mutex<r2r::Node>>, sink: S) -> Result<Self>
+    where
+        S: TransformSink + 'static,
+    {
+        Self::with_executor(ServerConfig::new(name), node, Executor::threads()?, Some(Box::new(sink)), None)
+    }
 
-        // Publish the initial transform before waiting for the feedback from RViz
-        // let mut init_transform = TransformStamped::default();
-        let mut init_transform = match spawn_at_pose {
-            Some(p) => {
-                let mut t = TransformStamped::default();
-                t.transform = Transform {
-                    translation: Vector3 { 
-                        x: p.position.x, 
-                        y: p.position.y, 
-                        z: p.position.z 
-                    },
-                    rotation: Quaternion { 
-                        x: p.orientation.x, 
-                        y: p.orientation.y, 
-                        z: p.orientation.z, 
-                        w: p.orientation.w 
-                    },
-                };
-                t
-            },
-            None => TransformStamped::default()
-        };
-        init_transform.child_frame_id = name.to_string();
-        init_transform.header.frame_id = spawn_at.to_string();
-        publisher.publish(
-            &TFMessage { transforms: vec!(
-                init_transform
-            ) }
-        ).unwrap();
+    /// Creates a new `TeachingMarkerServer` whose background work runs as tokio tasks.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - A topic namespace for the teaching marker server.
+    /// * `node` - A shared reference to the ROS node.
+    /// * `runtime` - A handle to the runtime the TF publishing and topic following tasks are spawned on.
+    ///
+    /// # Remarks
+    ///
+    /// The tasks stop together with the runtime, e.g. when `#[tokio::main]` returns.
+    pub fn new_with_runtime(
+        name: &str,
+        node: Arc<Mutex<r2r::Node>>,
+        runtime: tokio::runtime::Handle,
+    ) -> Result<Self> {
+        Self::with_executor(ServerConfig::new(name), node, Executor::tokio(runtime), None, None)
+    }
 
-        // Create an unbounded channel for communication
-        let (tx, rx) = unbounded();
+    /// Creates a new `TeachingMarkerServer` showing its markers with a custom backend instead of RViz.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The topics of the server, see `ServerConfig`.
+    /// * `node` - A shared reference to the ROS node.
+    /// * `backend` - Shows the markers and reports the feedback of the user, see `MarkerBackend`.
+    ///
+    /// # Remarks
+    ///
+    /// Transforms, pose topics and streams are published as usual, a headless configuration
+    /// still shows nothing. Background work runs on dedicated threads as with `new`.
+    pub fn new_with_backend(
+        config: ServerConfig,
+        node: Arc<Mutex<r2r::Node>>,
+        backend: Arc<dyn MarkerBackend>,
+    ) -> Result<Self> {
+        Self::with_executor(config, node, Executor::threads()?, None, Some(backend))
+    }
 
-        // Start a thread to handle publishing the TF messages
-        std::thread::spawn(move || {
-            for data in rx.iter() {
-                publisher.publish(&data).unwrap();
-            }
+    /// Creates a server, publishing the transforms on the topics of the configuration unless a sink
+    /// is given, and showing the markers in RViz unless a backend is given.
+    fn with_executor(
+        config: ServerConfig,
+        node: Arc<Mutex<r2r::Node>>,
+        executor: Executor,
+        sink: Option<Box<dyn TransformSink>>,
+        backend: Option<Arc<dyn MarkerBackend>>,
+    ) -> Result<Self> {
+        let name = config.topic(&config.namespace);
+        let sink = sink.unwrap_or_else(|| {
+            Box::new(TfPublishers::new(
+                &config.topic(&config.tf_topic),
+                &config.topic(&config.tf_static_topic),
+            ))
         });
 
-        // Insert the marker into the server
-        self.interactive_marker_server.insert(marker);
+        // A headless server doesn't even create the marker topics
+        let active = Arc::new(AtomicBool::new(true));
+        let backend = if config.headless {
+            None
+        } else if backend.is_some() {
+            backend
+        } else {
+            let interactive_namespace =
+                config.topic(config.interactive_namespace.as_ref().unwrap_or(&config.namespace));
+            let arc_node_clone = node.clone();
+            let interactive = InteractiveMarkerServer::new(&interactive_namespace, arc_node_clone);
+            let regular = if config.regular_markers {
+                let regular_namespace =
+                    config.topic(config.regular_namespace.as_ref().unwrap_or(&config.namespace));
+                let arc_node_clone = node.clone();
+                Some(RegularMarkerServer::new("teaching_marker_server", &regular_namespace, arc_node_clone))
+            } else {
+                None
+            };
+            Some(Arc::new(RvizBackend { interactive, regular }) as Arc<dyn MarkerBackend>)
+        };
+        let deferred = Arc::new(AtomicBool::new(false));
+        let interactive_marker_server = InteractiveMarkers::new(backend.clone(), active.clone(), deferred.clone());
+        let regular_marker_server = RegularMarkers::new(backend, active.clone(), deferred.clone());
 
-        // Clone variables for the feedback callback
-        let name_clone = name.clone();
-        let tx_clone = tx.clone();
+        let markers = Arc::new(Mutex::new(HashMap::new()));
+        let tf_sink = Arc::new(Mutex::new(sink));
 
-        // Define the feedback callback
-        let feedback_cb = Arc::new(move |feedback: InteractiveMarkerFeedback| {
-            let data = Self::process_feedback(&name_clone, &spawn_at, feedback);
-            tx_clone.send(data).unwrap();
-        });
+        // Start a single task publishing the TF messages and the poses of all markers
+        let tf_channel = LatestChannel::default();
+        let (pose_changes, _) = watch::channel(0);
+        let pose_changes = Arc::new(pose_changes);
+        let metrics = Arc::new(Mutex::new(MetricsRecorder::default()));
+        executor.spawn(Self::run_tf_worker(
+            tf_channel.clone(),
+            tf_sink.clone(),
+            markers.clone(),
+            pose_changes.clone(),
+            metrics.clone(),
+        ));
 
-        // Set the feedback callback for the marker
-        self.interactive_marker_server.set_callback(&name, Some(feedback_cb.clone()), DEFAULT_FEEDBACK_CB);
+        let mut server = TeachingMarkerServer {
+            interactive_marker_server,
+            regular_marker_server,
+            node,
+            namespace: name,
+            markers,
+            tf_sink,
+            tf_channel,
+            executor,
+            shut_down: Arc::new(AtomicBool::new(false)),
+            latch_on_shutdown: Arc::new(AtomicBool::new(false)),
+            guard: None,
+            transform_lookup: Arc::new(Mutex::new(None)),
+            groups: Arc::new(Mutex::new(HashMap::new())),
+            pose_changes,
+            named_poses: Arc::new(Mutex::new(BTreeMap::new())),
+            measurements: Arc::new(Mutex::new(HashMap::new())),
+            jog_target: Arc::new(Mutex::new(None)),
+            metrics,
+            event_streams: Arc::new(Mutex::new(vec![])),
+            active,
+            deferred,
+            tf_batch: Arc::new(Mutex::new(None)),
+            feedback_echo: Arc::new(Mutex::new(None)),
+            config: Arc::new(config),
+        };
+        server.guard = Some(Arc::new(ShutdownGuard {
+            server: server.handle(),
+        }));
+        Ok(server)
+    }
 
-        // Apply changes to publish updates
-        self.interactive_marker_server.apply_changes();
+    /// Returns a clone of the server that doesn't keep it from shutting down on drop.
+    fn handle(&self) -> Self {
+        let mut handle = self.clone();
+        handle.guard = None;
+        handle
+    }
 
-        // If a marker is provided visualize it
-        if let Some(marker) = regular_marker {
-            self.regular_marker_server.insert(&name, marker);
-            self.regular_marker_server.apply_changes();
+    /// Sets whether `shutdown` publishes the last poses of all markers on `tf_static`,
+    /// so the taught frames survive the server.
+    ///
+    /// # Arguments
+    ///
+    /// * `latch` - Whether to publish the final transforms, disabled by default.
+    pub fn latch_on_shutdown(&self, latch: bool) {
+        self.latch_on_shutdown.store(latch, Ordering::SeqCst);
+    }
+
+    /// Shuts the server down.
+    ///
+    /// Publishes the transform updates still pending, removes all markers, stops the background tasks
+    /// and, if enabled with `latch_on_shutdown`, publishes the last poses of all markers on `tf_static`.
+    /// Called automatically when the last clone of the server is dropped. Calling it more than once
+    /// has no effect.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the final transforms can't be published, the server is shut down regardless.
+    pub fn shutdown(&self) -> Result<()> {
+        if self.shut_down.swap(true, Ordering::SeqCst) {
+            return Ok(());
         }
 
+        let states: Vec<(String, MarkerState)> = {
+            let mut markers = self.markers.lock().unwrap();
+            // The worker takes its updates under this lock, so none are lost in between
+            self.tf_channel.close();
+            for update in self.tf_channel.take() {
+                if let Some(state) = markers.get_mut(&update.name) {
+                    Self::publish_update(&self.tf_sink, state, &update);
+                }
+            }
+            markers.drain().collect()
+        };
+        let result = if self.latch_on_shutdown.load(Ordering::SeqCst) {
+            self.publish_final_transforms(&states)
+        } else {
+            Ok(())
+        };
+
+        // The markers disappear from RViz even in deferred mode
+        self.set_deferred(false);
+        self.erase_markers(&states);
+
+        // Ends the event streams
+        self.event_streams.lock().unwrap().clear();
+        self.executor.abort_all();
+
+        result
+    }
+
+    /// Returns the names of all markers, sorted.
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.markers.lock().unwrap().keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Iterates over a snapshot of all markers, sorted by name.
+    ///
+    /// # Remarks
+    ///
+    /// The snapshot is taken when this is called, changes made while iterating aren't seen.
+    pub fn iter(&self) -> impl Iterator<Item = MarkerInfo> {
+        let mut infos: Vec<MarkerInfo> = self
+            .markers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, state)| MarkerInfo {
+                name: name.clone(),
+                parent: state.config.frame.clone(),
+                pose: state.pose.clone(),
+                locked: state.read_only,
+                hidden: state.hidden,
+                paused: state.paused,
+            })
+            .collect();
+        infos.sort_by(|a, b| a.name.cmp(&b.name));
+        infos.into_iter()
     }
 
-    /// Creates an `InteractiveMarker` with controls for rotation and translation along all axes.
+    /// Removes a marker.
     ///
     /// # Arguments
     ///
     /// * `name` - The name of the marker.
-    /// * `spawn_at` - The frame ID where the marker is to be spawned.
-    /// * `spawn_at_pose` - The pose where we want to spawn the item at.
     ///
-    /// # Returns
+    /// # Remarks
     ///
-    /// An `InteractiveMarker` configured with controls.
-    fn create_marker(name: &str, spawn_at: &str, spawn_at_pose: Option<Pose>) -> InteractiveMarker {
-        let mut int_marker = InteractiveMarker::default();
-        int_marker.header.frame_id = spawn_at.to_string();
-        int_marker.name = format!("{name}");
-        int_marker.description = format!("{name}");
-        int_marker.scale = 0.3;
-        int_marker.pose = match spawn_at_pose {
-            Some(pose ) => pose,
-            None => Pose {
-                position: Point {
-                    x: 0.0,
-                    y: 0.0,
-                    z: 0.0,
-                },
-                orientation: Quaternion {
-                    x: 0.0,
-                    y: 0.0,
-                    z: 0.0,
-                    w: 1.0,
-                },
+    /// The marker is also removed from all groups, groups it is the handle of are deleted.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::MarkerNotFound` if no marker with the given name exists.
+    pub fn remove(&self, name: &str) -> Result<()> {
+        let state = self
+            .markers
+            .lock()
+            .unwrap()
+            .remove(name)
+            .ok_or_else(|| TeachingMarkerError::MarkerNotFound(name.to_string()))?;
+        {
+            let mut groups = self.groups.lock().unwrap();
+            groups.retain(|_, group| group.handle != name);
+            for group in groups.values_mut() {
+                group.members.retain(|member| member != name);
             }
-        }; 
-        
-        // Add controls for rotation and movement along each axis
-        for (name, interaction_mode, axis) in [
-            (
-                "rotate_x",
-                InteractiveMarkerControl::ROTATE_AXIS as u8,
-                Axis::X,
-            ),
-            ("move_x", InteractiveMarkerControl::MOVE_AXIS as u8, Axis::X),
-            (
-                "rotate_y",
-                InteractiveMarkerControl::ROTATE_AXIS as u8,
-                Axis::Y,
-            ),
-            ("move_y", InteractiveMarkerControl::MOVE_AXIS as u8, Axis::Y),
-            (
-                "rotate_z",
-                InteractiveMarkerControl::ROTATE_AXIS as u8,
-                Axis::Z,
-            ),
-            ("move_z", InteractiveMarkerControl::MOVE_AXIS as u8, Axis::Z),
-        ] {
-            int_marker.controls.push(prepare_control(
-                name,
-                interaction_mode,
-                axis,
-            ))
         }
-
-        int_marker
+        self.erase_markers(&[(name.to_string(), state)]);
+        self.forget_transforms([name]);
+        Ok(())
     }
 
-    /// Processes feedback from the interactive marker and generates a TF message.
+    /// Renames a marker together with its child frame, keeping its pose and configuration.
     ///
     /// # Arguments
     ///
-    /// * `name` - The name of the marker.
-    /// * `spawn_at` - The frame ID where the marker is spawned.
-    /// * `feedback` - The feedback received from the interactive marker.
+    /// * `old` - The current name of the marker.
+    /// * `new` - The new name of the marker.
     ///
-    /// # Returns
+    /// # Remarks
     ///
-    /// A `TFMessage` containing the updated transform based on the marker's feedback.
+    /// Groups, mirrors, the jog selection and markers attached to the marker follow the new name,
+    /// and the pose is published on the new child frame. Streams from `pose_updates` end and
+    /// followed topics are dropped, as for a removed marker. A transform latched on `tf_static`
+    /// for the old frame can't be taken back and stays until the server restarts.
     ///
-    /// # Remarks
+    /// # Errors
     ///
-    /// Currently, this function publishes directly to `/tf`. In future implementations,
-    /// it can be modified to directly use a transform buffer like sms or r2r_transforms.
-    fn process_feedback(
-        name: &str,
-        spawn_at: &str,
-        feedback: InteractiveMarkerFeedback,
-    ) -> TFMessage {
-        // Get the current time
-        let mut clock = r2r::Clock::create(r2r::ClockType::RosTime).unwrap();
-        let now = clock.get_now().unwrap();
-        let time_stamp = r2r::Clock::to_builtin_time(&now);
+    /// Returns `TeachingMarkerError::MarkerNotFound` if the marker doesn't exist,
+    /// `TeachingMarkerError::MarkerExists` if the new name is taken, or an error if the pose
+    /// publisher or the transform under the new name can't be created.
+    pub fn rename(&self, old: &str, new: &str) -> Result<()> {
+        let (children, pose, visual, overlays, reachability_map) = {
+            let mut markers = self.markers.lock().unwrap();
+            if markers.contains_key(new) {
+                return Err(TeachingMarkerError::MarkerExists(new.to_string()));
+            }
+            let mut state = markers
+                .remove(old)
+                .ok_or_else(|| TeachingMarkerError::MarkerNotFound(old.to_string()))?;
+            state.config.name = new.to_string();
+            // Visuals drawn in the marker's own frame move along to the new frame
+            let states = state.visual_states.iter_mut().map(|(_, visual)| visual);
+            for visual in state.config.visual.iter_mut().chain(states) {
+                if visual.header.frame_id == old {
+                    visual.header.frame_id = new.to_string();
+                }
+            }
+            // The tasks reading the poses look the marker up by its old name
+            state.pose_streams.clear();
+            let reachability_map = state.reachability_map.take();
+            if state.pose_publisher.is_some() {
+                state.pose_publisher = Some(self.node.lock().unwrap().create_publisher::<PoseStamped>(
+                    &format!("{}/{}/pose", self.namespace, new),
+                    state.config.pose_qos.clone().unwrap_or_default(),
+                )?);
+            }
+            if state.covariance_publisher.is_some() {
+                state.covariance_publisher =
+                    Some(self.node.lock().unwrap().create_publisher::<PoseWithCovarianceStamped>(
+                        &format!("{}/{}/pose_with_covariance", self.namespace, new),
+                        state.config.pose_qos.clone().unwrap_or_default(),
+                    )?);
+            }
+            let (pose, visual, overlays) = (
+                state.pose.clone(),
+                state.config.visual.clone(),
+                state.config.has_overlays(),
+            );
+            markers.insert(new.to_string(), state);
 
-        let mut transforms = vec![];
+            let mut children = vec![];
+            for (name, state) in markers.iter_mut() {
+                for (mirror, _) in &mut state.mirrors {
+                    if mirror == old {
+                        *mirror = new.to_string();
+                    }
+                }
+                if state.config.parent_marker && state.config.frame == old {
+                    state.config.frame = new.to_string();
+                    children.push((name.clone(), state.pose.clone()));
+                }
+            }
+            (children, pose, visual, overlays, reachability_map)
+        };
+        {
+            let mut groups = self.groups.lock().unwrap();
+            for group in groups.values_mut() {
+                if group.handle == old {
+                    group.handle = new.to_string();
+                }
+                for member in &mut group.members {
+                    if member == old {
+                        *member = new.to_string();
+                    }
+                }
+            }
+        }
+        {
+            let mut jog_target = self.jog_target.lock().unwrap();
+            if jog_target.as_deref() == Some(old) {
+                *jog_target = Some(new.to_string());
+            }
+        }
 
-        // Create a TransformStamped message based on the feedback
-        transforms.push(TransformStamped {
-            header: Header {
-                stamp: time_stamp.clone(),
-                frame_id: spawn_at.to_string(),
-            },
-            child_frame_id: name.to_string(),
-            transform: Transform {
-                translation: Vector3 {
-                    x: feedback.pose.position.x,
-                    y: feedback.pose.position.y,
-                    z: feedback.pose.position.z,
-                },
-                rotation: Quaternion {
-                    x: feedback.pose.orientation.x,
-                    y: feedback.pose.orientation.y,
-                    z: feedback.pose.orientation.z,
-                    w: feedback.pose.orientation.w,
-                },
-            },
-        });
+        self.interactive_marker_server.erase(old);
+        self.regular_marker_server.erase(old);
+        self.erase_overlays(old);
+        self.forget_transforms([old]);
+        if let Some(visual) = visual {
+            self.regular_marker_server.insert(new, visual);
+        }
+        self.regular_marker_server.apply_changes();
+        self.refresh_marker(new)?;
+        self.move_marker(new, &pose, true)?;
+        if overlays {
+            self.track_overlays(new)?;
+        }
+        if let Some((map, radius)) = reachability_map {
+            self.track_reachability_map(new, map, radius)?;
+        }
+
+        // Attached markers are rebuilt in the new frame and republished with the new parent
+        for (child, pose) in children {
+            self.refresh_marker(&child)?;
+            self.move_marker(&child, &pose, true)?;
+        }
+        Ok(())
+    }
+
+    /// Removes all markers and groups at once.
+    ///
+    /// The interactive and regular markers are erased in a single batch, pose publishers and
+    /// streams of the markers are dropped and pending transform updates are discarded.
+    pub fn clear(&self) {
+        self.groups.lock().unwrap().clear();
+        let states: Vec<(String, MarkerState)> = self.markers.lock().unwrap().drain().collect();
+        self.erase_markers(&states);
+        self.forget_transforms(states.iter().map(|(name, _)| name.as_str()));
+    }
 
-        TFMessage { transforms }
+    /// Erases removed markers from RViz and applies the changes once.
+    fn erase_markers(&self, states: &[(String, MarkerState)]) {
+        for (name, _) in states {
+            self.interactive_marker_server.erase(name);
+            self.regular_marker_server.erase(name);
+            self.erase_overlays(name);
+        }
+        self.interactive_marker_server.apply_changes();
+        self.regular_marker_server.apply_changes();
+        for (name, _) in states {
+            self.emit(TeachingEvent::Removed { marker: name.clone() });
+        }
     }
 
+    /// Drops the latched transforms of removed markers, so late subscribers don't get them.
+    fn forget_transforms<'a>(&self, names: impl IntoIterator<Item = &'a str>) {
+        let mut tf_sink = self.tf_sink.lock().unwrap();
+        for name in names {
+            if let Err(e) = tf_sink.forget(name) {
+                r2r::log_error!(NODE_ID, "Failed to forget the transform of '{}' with: '{}'.", name, e);
+            }
+        }
+    }
+
+    /// Publishes the current poses of markers on `tf_static` in a single message.
+    fn publish_final_transforms(&self, states: &[(String, MarkerState)]) -> Result<()> {
+        let mut transforms = vec![];
+        // Unconfirmed poses and suspended markers are never latched
+        for (name, state) in states
+            .iter()
+            .filter(|(_, state)| !state.is_candidate() && !state.tf_suspended)
+        {
+            transforms.extend(Self::make_tf_message(name, &state.config.frame, &state.pose)?.transforms);
+        }
+        let mut tf_sink = self.tf_sink.lock().unwrap();
+        tf_sink.prepare(&mut self.node.lock().unwrap(), &TfOutput::Static)?;
+        tf_sink.send(&TfOutput::Static, &TFMessage { transforms }, true)
+    }
+
+    /// Publishes the transform updates of all markers until the channel is closed.
+    ///
+    /// # Arguments
+    ///
+    /// * `tf_channel` - The channel holding the newest update of each marker.
+    /// * `tf_sink` - The shared transform sink.
+    /// * `markers` - The marker states, holding the output target, pose publisher and pose streams.
+    /// * `pose_changes` - Counts the published batches, to wake up the on-change pose array publisher.
+    /// * `metrics` - Records the latency of the updates from RViz feedback.
+    async fn run_tf_worker(
+        tf_channel: LatestChannel,
+        tf_sink: Arc<Mutex<Box<dyn TransformSink>>>,
+        markers: Arc<Mutex<HashMap<String, MarkerState>>>,
+        pose_changes: Arc<watch::Sender<u64>>,
+        metrics: Arc<Mutex<MetricsRecorder>>,
+    ) {
+        while tf_channel.ready().await {
+            {
+                // Taking the updates under the lock lets shutdown publish everything not taken yet
+                let mut markers = markers.lock().unwrap();
+                for update in tf_channel.take() {
+                    let state = match markers.get_mut(&update.name) {
+                        Some(state) => state,
+                        None => continue,
+                    };
+                    Self::publish_update(&tf_sink, state, &update);
+                    if let Some(received) = update.received {
+                        metrics.lock().unwrap().published(received);
+                    }
+                }
+            }
+            pose_changes.send_modify(|count| *count = count.wrapping_add(1));
+        }
+    }
+
+    /// Publishes a transform update and forwards the resulting pose to the marker's pose outputs.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(marker = %update.name, latch = update.latch))
+    )]
+    fn publish_update(tf_sink: &Mutex<Box<dyn TransformSink>>, state: &mut MarkerState, update: &TfUpdate) {
+        if !state.tf_suspended {
+            if let Err(e) = state.send_to(&mut **tf_sink.lock().unwrap(), &update.data, update.latch) {
+                state.publish_errors += 1;
+                trace_error!(error = %e, "failed to publish the marker transform");
+                r2r::log_error!(NODE_ID, "Failed to publish the marker transform with: '{}'.", e);
+            }
+        }
+        for t in &update.data.transforms {
+            let pose_stamped = PoseStamped {
+                header: t.header.clone(),
+                pose: transform_to_pose(&t.transform),
+            };
+            state.published_pose = Some(pose_stamped.pose.clone());
+            // Replayed poses are already in the history
+            if state.config.history_depth > 0 && !state.replaying {
+                state
+                    .pose_history
+                    .push_back((t.header.stamp.clone(), pose_stamped.pose.clone()));
+                while state.pose_history.len() > state.config.history_depth {
+                    state.pose_history.pop_front();
+                }
+            }
+            if let Some(pose_publisher) = &state.pose_publisher {
+                if let Err(e) = pose_publisher.publish(&pose_stamped) {
+                    state.publish_errors += 1;
+                    trace_error!(error = %e, "failed to publish the marker pose");
+                    r2r::log_error!(NODE_ID, "Failed to publish the marker pose with: '{}'.", e);
+                }
+            }
+            if let (Some(publisher), Some(covariance)) = (&state.covariance_publisher, &state.config.pose_covariance) {
+                let pose_with_covariance = PoseWithCovarianceStamped {
+                    header: pose_stamped.header.clone(),
+                    pose: PoseWithCovariance {
+                        pose: pose_stamped.pose.clone(),
+                        covariance: covariance.to_vec(),
+                    },
+                };
+                if let Err(e) = publisher.publish(&pose_with_covariance) {
+                    state.publish_errors += 1;
+                    trace_error!(error = %e, "failed to publish the marker pose covariance");
+                    r2r::log_error!(NODE_ID, "Failed to publish the marker pose covariance with: '{}'.", e);
+                }
+            }
+            // Drop the streams whose receivers have gone away
+            state
+                .pose_streams
+                .retain(|stream| stream.unbounded_send(pose_stamped.clone()).is_ok());
+        }
+    }
+
+    /// Inserts a teaching marker.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the marker.
+    /// * `spawn_at` - The frame ID where the marker is to be spawned.
+    /// * `spawn_at_pose` - The pose where we want to spawn the item at.
+    /// * `regular_marker` - An optional marker to visualize inside the teaching controls.
+    /// * `_node` - Kept for compatibility, the server uses the node it was created with.
+    ///
+    /// # Remarks
+    ///
+    /// This is a shorthand for building a `TeachingMarker` and calling `insert_marker`.
+    pub fn insert(&self, name: String, spawn_at: String, spawn_at_pose: Option<Pose>, regular_marker: Option<Marker>, _node: Arc<Mutex<r2r::Node>>) -> Result<()> {
+        let mut builder = TeachingMarker::builder(&name).frame(&spawn_at);
+        if let Some(pose) = spawn_at_pose {
+            builder = builder.pose(pose);
+        }
+        if let Some(marker) = regular_marker {
+            builder = builder.with_visual(marker);
+        }
+        self.insert_marker(builder.build())
+    }
+
+    /// Inserts a teaching marker built with `TeachingMarker::builder`.
+    ///
+    /// # Arguments
+    ///
+    /// * `teaching_marker` - The description of the marker to insert. The spawn pose is passed
+    ///   through its constraints.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::MarkerExists` if a marker with the same name exists, or an
+    /// error if the server has been shut down, the parent marker doesn't exist,
+    /// the frames don't become available in time, the spawn frame can't be looked up,
+    /// the TF publisher can't be created or the initial transform can't be published.
+    pub fn insert_marker(&self, teaching_marker: TeachingMarker) -> Result<()> {
+        self.stage_insert(teaching_marker)?;
+        self.interactive_marker_server.apply_changes();
+        self.regular_marker_server.apply_changes();
+        Ok(())
+    }
+
+    /// Inserts several teaching markers, publishing the changes to RViz once for all of them.
+    ///
+    /// # Arguments
+    ///
+    /// * `teaching_markers` - The descriptions of the markers to insert.
+    ///
+    /// # Remarks
+    ///
+    /// Prefer this over repeated calls to `insert_marker` for many markers, e.g. a set of pick
+    /// points, RViz then receives a single update. If a marker fails to insert, the ones before
+    /// it are kept and still published.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error of `insert_marker` for any of the markers.
+    pub fn insert_many(&self, teaching_markers: Vec<TeachingMarker>) -> Result<()> {
+        let result = teaching_markers
+            .into_iter()
+            .try_for_each(|teaching_marker| self.stage_insert(teaching_marker));
+        self.interactive_marker_server.apply_changes();
+        self.regular_marker_server.apply_changes();
+        result
+    }
+
+    /// Inserts a teaching marker without applying the changes of the marker servers.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(marker = %teaching_marker.name), err)
+    )]
+    fn stage_insert(&self, mut teaching_marker: TeachingMarker) -> Result<()> {
+        if self.shut_down.load(Ordering::SeqCst) {
+            return Err(TeachingMarkerError::ShutDown);
+        }
+        // Meshes from ROS packages are resolved on the machine the server runs on
+        if let Some(visual) = &mut teaching_marker.visual {
+            resolve_visual(visual);
+        }
+        for (_, visual) in &mut teaching_marker.control_visuals {
+            resolve_visual(visual);
+        }
+        let name = teaching_marker.name.clone();
+        let spawn_at = teaching_marker.frame.clone();
+        // Replacing a marker would leave its tasks running against the new one
+        if self.markers.lock().unwrap().contains_key(&name) {
+            return Err(TeachingMarkerError::MarkerExists(name));
+        }
+        if teaching_marker.parent_marker && !self.markers.lock().unwrap().contains_key(&spawn_at) {
+            return Err(TeachingMarkerError::MarkerNotFound(spawn_at));
+        }
+        if let Some(timeout) = teaching_marker.wait_for_frame {
+            self.wait_for_frames(&teaching_marker, timeout)?;
+        }
+        let initial_pose = teaching_marker.constraints.apply(&self.resolve_spawn_pose(&teaching_marker)?);
+
+        // Set up the publishers for the TF messages
+        let arc_node_clone = self.node.clone();
+        {
+            let mut tf_sink = self.tf_sink.lock().unwrap();
+            let mut node = arc_node_clone.lock().unwrap();
+            let mut prepare = |output: &TfOutput| match &teaching_marker.tf_qos {
+                Some(qos) => tf_sink.prepare_with_qos(&mut node, output, qos),
+                None => tf_sink.prepare(&mut node, output),
+            };
+            prepare(&teaching_marker.tf_output)?;
+            // Candidate poses are published on tf until they are confirmed
+            if teaching_marker.require_confirmation {
+                prepare(&TfOutput::Dynamic)?;
+            }
+        }
+
+        // Set up a publisher for the marker pose if requested
+        let pose_publisher = if teaching_marker.publish_pose {
+            Some(
+                arc_node_clone
+                    .lock()
+                    .unwrap()
+                    .create_publisher::<PoseStamped>(
+                        &format!("{}/{}/pose", self.namespace, name),
+                        teaching_marker.pose_qos.clone().unwrap_or_default(),
+                    )?,
+            )
+        } else {
+            None
+        };
+
+        let covariance_publisher = match teaching_marker.pose_covariance {
+            Some(_) => Some(
+                arc_node_clone
+                    .lock()
+                    .unwrap()
+                    .create_publisher::<PoseWithCovarianceStamped>(
+                        &format!("{}/{}/pose_with_covariance", self.namespace, name),
+                        teaching_marker.pose_qos.clone().unwrap_or_default(),
+                    )?,
+            ),
+            None => None,
+        };
+
+        // Publish the initial transform before waiting for the feedback from RViz
+        let regular_marker = teaching_marker.visual.clone();
+        let mut state = MarkerState::new(teaching_marker, initial_pose.clone(), pose_publisher);
+        state.covariance_publisher = covariance_publisher;
+        if self.is_active() {
+            let update = TfUpdate {
+                name: name.clone(),
+                data: Self::make_tf_message(&name, &spawn_at, &initial_pose)?,
+                latch: true,
+                received: None,
+            };
+            // Inside a transaction the transform is published together with the others
+            if let Some(update) = self.hold_tf(update) {
+                state.send_to(&mut **self.tf_sink.lock().unwrap(), &update.data, true)?;
+            }
+        }
+
+        // Insert the marker into the server
+        let overlays = state.config.has_overlays();
+        match self.markers.lock().unwrap().entry(name.clone()) {
+            // Inserted concurrently while the frames were awaited
+            Entry::Occupied(_) => return Err(TeachingMarkerError::MarkerExists(name)),
+            Entry::Vacant(entry) => entry.insert(state),
+        };
+        self.stage_marker(&name)?;
+
+        // If a marker is provided visualize it
+        if let Some(marker) = regular_marker {
+            self.regular_marker_server.insert(&name, marker);
+        }
+
+        if overlays {
+            self.track_overlays(&name)?;
+        }
+
+        self.emit_pose(&name, |marker, pose| TeachingEvent::Created { marker, pose });
+        Ok(())
+    }
+
+    /// Sets the transform lookup used to resolve `SpawnAt::Frame`, replacing any previous one.
+    ///
+    /// # Arguments
+    ///
+    /// * `lookup` - The transform source, e.g. an existing TF buffer of the application.
+    pub fn set_transform_lookup<L>(&self, lookup: L)
+    where
+        L: TransformLookup + 'static,
+    {
+        *self.transform_lookup.lock().unwrap() = Some(Arc::new(lookup));
+    }
+
+    /// Starts listening to `tf` and `tf_static` and uses the received transforms as the transform lookup.
+    ///
+    /// # Returns
+    ///
+    /// The buffer the transforms are stored in, which can also be queried directly.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the subscriptions can't be created.
+    pub fn listen_tf(&self) -> Result<TfBuffer> {
+        let buffer = TfBuffer::new();
+        let topics = [
+            (&self.config.tf_topic, QosProfile::default()),
+            (&self.config.tf_static_topic, QosProfile::transient_local(QosProfile::default())),
+        ];
+        for (topic, qos) in topics {
+            let mut subscriber = self
+                .node
+                .lock()
+                .unwrap()
+                .subscribe::<TFMessage>(&self.config.topic(topic), qos)?;
+            let buffer = buffer.clone();
+            self.executor.spawn(async move {
+                while let Some(message) = subscriber.next().await {
+                    for transform in &message.transforms {
+                        buffer.insert(transform);
+                    }
+                }
+            });
+        }
+        self.set_transform_lookup(buffer.clone());
+        Ok(buffer)
+    }
+
+    /// Inserts a teaching marker, waiting for its frames without blocking the async runtime.
+    ///
+    /// # Arguments
+    ///
+    /// * `teaching_marker` - The description of the marker to insert.
+    ///
+    /// # Remarks
+    ///
+    /// Use this instead of `insert_marker` for markers built with `wait_for_frame` when calling
+    /// from async code, e.g. on the runtime given to `new_with_runtime`. The `listen_tf` tasks
+    /// keep running while the frames are awaited.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of `insert_marker`.
+    pub async fn insert_marker_async(&self, mut teaching_marker: TeachingMarker) -> Result<()> {
+        if let Some(timeout) = teaching_marker.wait_for_frame.take() {
+            let deadline = Instant::now() + timeout;
+            while let Some(frame) = self.missing_frame(&teaching_marker) {
+                if Instant::now() >= deadline {
+                    return Err(TeachingMarkerError::FrameTimeout(frame));
+                }
+                self.executor.sleep(FRAME_POLL_INTERVAL).await;
+            }
+        }
+        self.insert_marker(teaching_marker)
+    }
+
+    /// Blocks until the parent frame of a marker, and its spawn frame if any, are available.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::FrameTimeout` with the missing frame if the timeout elapses,
+    /// or `TeachingMarkerError::BlockingWait` if called on an async runtime, where sleeping would
+    /// starve the tasks delivering the frames.
+    fn wait_for_frames(&self, teaching_marker: &TeachingMarker, timeout: Duration) -> Result<()> {
+        let deadline = Instant::now() + timeout;
+        while let Some(frame) = self.missing_frame(teaching_marker) {
+            if tokio::runtime::Handle::try_current().is_ok() {
+                return Err(TeachingMarkerError::BlockingWait(teaching_marker.name.clone()));
+            }
+            if Instant::now() >= deadline {
+                return Err(TeachingMarkerError::FrameTimeout(frame));
+            }
+            std::thread::sleep(FRAME_POLL_INTERVAL);
+        }
+        Ok(())
+    }
+
+    /// Returns the parent or spawn frame of a marker that isn't available yet, if any.
+    fn missing_frame(&self, teaching_marker: &TeachingMarker) -> Option<String> {
+        let lookup = self.transform_lookup.lock().unwrap().clone();
+        match &lookup {
+            Some(lookup) if !lookup.has_frame(&teaching_marker.frame) => Some(teaching_marker.frame.clone()),
+            Some(lookup) => match &teaching_marker.spawn_at {
+                SpawnAt::Frame(frame) if lookup.lookup_transform(&teaching_marker.frame, frame).is_none() => {
+                    Some(frame.clone())
+                }
+                _ => None,
+            },
+            None => Some(teaching_marker.frame.clone()),
+        }
+    }
+
+    /// Computes the initial pose of a marker relative to its frame.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::TransformUnavailable` if the spawn frame can't be looked up.
+    fn resolve_spawn_pose(&self, teaching_marker: &TeachingMarker) -> Result<Pose> {
+        match &teaching_marker.spawn_at {
+            SpawnAt::Origin => Ok(identity_pose()),
+            SpawnAt::Pose(pose) => Ok(pose.clone()),
+            SpawnAt::Frame(frame) => {
+                let lookup = self.transform_lookup.lock().unwrap().clone();
+                lookup
+                    .and_then(|lookup| lookup.lookup_transform(&teaching_marker.frame, frame))
+                    .map(|transform| transform_to_pose(&transform))
+                    .ok_or_else(|| {
+                        TeachingMarkerError::TransformUnavailable(
+                            teaching_marker.frame.clone(),
+                            frame.clone(),
+                        )
+                    })
+            }
+        }
+    }
+
+    /// Inserts the teaching markers declared in the parameters of the node.
+    ///
+    /// # Arguments
+    ///
+    /// * `prefix` - The parameter prefix, e.g. `markers` for parameters like `markers.names` and
+    ///   `markers.<name>.frame`, `markers.<name>.xyz`, `markers.<name>.rpy` and `markers.<name>.profile`.
+    ///
+    /// # Returns
+    ///
+    /// The names of the inserted markers, empty if `<prefix>.names` isn't set.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a parameter is invalid or a marker can't be inserted.
+    pub fn insert_from_parameters(&self, prefix: &str) -> Result<Vec<String>> {
+        let markers = params::markers_from_params(&self.node.lock().unwrap(), prefix)?;
+        let names = markers.iter().map(|marker| marker.name.clone()).collect();
+        self.insert_many(markers)?;
+        Ok(names)
+    }
+
+    /// Inserts a new marker wherever the "Publish Point" tool of RViz is clicked.
+    ///
+    /// # Arguments
+    ///
+    /// * `template` - The configuration of the new markers. Its name is used as a prefix, the
+    ///   markers are named `<name>_1`, `<name>_2` and so on, and its frame as their parent frame.
+    ///
+    /// # Remarks
+    ///
+    /// Listens on `clicked_point`. The markers get the default orientation. Points clicked in
+    /// another frame than the template's are converted with the transform lookup, see `listen_tf`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the subscription can't be created.
+    pub fn spawn_at_clicked_point(&self, template: TeachingMarker) -> Result<()> {
+        let mut subscriber = self
+            .node
+            .lock()
+            .unwrap()
+            .subscribe::<PointStamped>(
+                &self.config.topic(&self.config.clicked_point_topic),
+                QosProfile::default(),
+            )?;
+
+        let server = self.handle();
+        self.executor.spawn(async move {
+            let mut next_id = 1;
+            while let Some(clicked) = subscriber.next().await {
+                let mut pose = identity_pose();
+                pose.position = clicked.point;
+                let pose = match server.pose_in_frame(&template.frame, &clicked.header.frame_id, &pose) {
+                    Some(pose) => pose,
+                    None => {
+                        r2r::log_warn!(
+                            NODE_ID,
+                            "Ignoring clicked point in frame '{}', it can't be converted into '{}'.",
+                            clicked.header.frame_id,
+                            template.frame
+                        );
+                        continue;
+                    }
+                };
+                // Skip names taken by markers inserted otherwise
+                let name = loop {
+                    let name = format!("{}_{}", template.name, next_id);
+                    next_id += 1;
+                    if !server.markers.lock().unwrap().contains_key(&name) {
+                        break name;
+                    }
+                };
+                let mut config = template.clone();
+                config.name = name.clone();
+                config.spawn_at = SpawnAt::Pose(pose);
+                if let Err(e) = server.insert_marker_async(config).await {
+                    r2r::log_error!(NODE_ID, "Failed to insert '{}' at the clicked point with: '{}'.", name, e);
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Places a marker with the "2D Pose Estimate" tool of RViz.
+    ///
+    /// # Arguments
+    ///
+    /// * `template` - The configuration of the marker. It is inserted at the first pose set in
+    ///   RViz, and moved to every following one.
+    ///
+    /// # Remarks
+    ///
+    /// Listens on `initialpose`. The tool gives a position on the ground plane and a yaw, which
+    /// pairs well with a `planar` marker. Poses are passed through the marker's constraints, as with
+    /// `set_pose`, and each move can be undone with `undo`. Poses set in another frame than the template's are
+    /// converted with the transform lookup, see `listen_tf`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the subscription can't be created.
+    pub fn teach_from_initial_pose(&self, template: TeachingMarker) -> Result<()> {
+        let mut subscriber = self
+            .node
+            .lock()
+            .unwrap()
+            .subscribe::<PoseWithCovarianceStamped>(
+                &self.config.topic(&self.config.initial_pose_topic),
+                QosProfile::default(),
+            )?;
+
+        let server = self.handle();
+        self.executor.spawn(async move {
+            let name = template.name.clone();
+            while let Some(estimate) = subscriber.next().await {
+                let frame = &estimate.header.frame_id;
+                let pose = match server.pose_in_frame(&template.frame, frame, &estimate.pose.pose) {
+                    Some(pose) => pose,
+                    None => {
+                        r2r::log_warn!(
+                            NODE_ID,
+                            "Ignoring initial pose in frame '{}', it can't be converted into '{}'.",
+                            frame,
+                            template.frame
+                        );
+                        continue;
+                    }
+                };
+                let inserted = server.markers.lock().unwrap().contains_key(&name);
+                let result = if inserted {
+                    server.set_pose(&name, &pose)
+                } else {
+                    let mut config = template.clone();
+                    config.spawn_at = SpawnAt::Pose(pose);
+                    server.insert_marker_async(config).await
+                };
+                if let Err(e) = result {
+                    r2r::log_error!(NODE_ID, "Failed to place '{}' at the initial pose with: '{}'.", name, e);
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Registers a callback that is invoked on every feedback received for a marker.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the marker.
+    /// * `callback` - The callback, invoked after the built-in TF processing.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::MarkerNotFound` if no marker with the given name exists.
+    pub fn on_feedback<F>(&self, name: &str, callback: F) -> Result<()>
+    where
+        F: Fn(&InteractiveMarkerFeedback) + Send + Sync + 'static,
+    {
+        match self.markers.lock().unwrap().get_mut(name) {
+            Some(state) => {
+                state.feedback_hooks.push(Arc::new(callback));
+                Ok(())
+            }
+            None => Err(TeachingMarkerError::MarkerNotFound(name.to_string())),
+        }
+    }
+
+    /// Returns a stream of pose updates of a marker.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the marker.
+    ///
+    /// # Returns
+    ///
+    /// A `Stream` yielding a `PoseStamped` in the marker's parent frame every time the
+    /// marker's transform is published. The stream ends when the marker is removed.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::MarkerNotFound` if no marker with the given name exists.
+    pub fn pose_updates(&self, name: &str) -> Result<impl Stream<Item = PoseStamped>> {
+        match self.markers.lock().unwrap().get_mut(name) {
+            Some(state) => {
+                let (tx, rx) = mpsc::unbounded();
+                state.pose_streams.push(tx);
+                Ok(rx)
+            }
+            None => Err(TeachingMarkerError::MarkerNotFound(name.to_string())),
+        }
+    }
+
+    /// Rebuilds the interactive marker from its state and publishes it to RViz.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the marker.
+    fn refresh_marker(&self, name: &str) -> Result<()> {
+        self.stage_marker(name)?;
+        self.interactive_marker_server.apply_changes();
+        Ok(())
+    }
+
+    /// Rebuilds the interactive marker from its state without applying the changes.
+    fn stage_marker(&self, name: &str) -> Result<()> {
+        let marker = match self.markers.lock().unwrap().get_mut(name) {
+            Some(state) => {
+                state.active_menu = self.active_menu(name, state);
+                // Hidden markers are rebuilt by `show`
+                if state.hidden {
+                    return Ok(());
+                }
+                Self::create_marker(state)
+            }
+            None => return Err(TeachingMarkerError::MarkerNotFound(name.to_string())),
+        };
+
+        // Insert the marker into the server
+        self.interactive_marker_server.insert(marker);
+
+        // Define the feedback callback
+        let server = self.handle();
+        let name_clone = name.to_string();
+        let feedback_cb = Arc::new(move |feedback: InteractiveMarkerFeedback| {
+            server.handle_feedback(&name_clone, feedback);
+        });
+
+        // Set the feedback callback for the marker
+        self.interactive_marker_server.set_callback(name, Some(feedback_cb), DEFAULT_FEEDBACK_CB);
+
+        Ok(())
+    }
+
+    /// Combines the user menu of a marker with the menus provided by the server.
+    ///
+    /// Runs while the marker map is locked.
+    fn active_menu(&self, name: &str, state: &MarkerState) -> Option<MenuHandler> {
+        if !state.named_pose_menu
+            && !state.config.require_confirmation
+            && !state.undo_menu
+            && !state.reset_menu
+            && !state.jog_menu
+            && state.config.nudge_steps.is_none()
+            && state.visual_states.is_empty()
+        {
+            return state.menu.clone();
+        }
+        let mut menu = state.menu.clone().unwrap_or_default();
+        if state.undo_menu {
+            let server = self.handle();
+            let marker = name.to_string();
+            menu.insert("Undo", move |_| {
+                if let Err(e) = server.undo(&marker) {
+                    r2r::log_error!(NODE_ID, "Failed to undo the movement of '{}' with: '{}'.", marker, e);
+                }
+            });
+            let server = self.handle();
+            let marker = name.to_string();
+            menu.insert("Redo", move |_| {
+                if let Err(e) = server.redo(&marker) {
+                    r2r::log_error!(NODE_ID, "Failed to redo the movement of '{}' with: '{}'.", marker, e);
+                }
+            });
+        }
+        if state.reset_menu {
+            let server = self.handle();
+            let marker = name.to_string();
+            menu.insert("Reset", move |_| {
+                if let Err(e) = server.reset(&marker) {
+                    r2r::log_error!(NODE_ID, "Failed to reset '{}' with: '{}'.", marker, e);
+                }
+            });
+        }
+        if state.config.require_confirmation {
+            let server = self.handle();
+            let marker = name.to_string();
+            menu.insert("Confirm", move |_| {
+                if let Err(e) = server.confirm(&marker) {
+                    r2r::log_error!(NODE_ID, "Failed to confirm '{}' with: '{}'.", marker, e);
+                }
+            });
+        }
+        if state.jog_menu {
+            let server = self.handle();
+            let marker = name.to_string();
+            menu.insert("Jog with joystick", move |_| {
+                if let Err(e) = server.select_for_jogging(Some(&marker)) {
+                    r2r::log_error!(NODE_ID, "Failed to select '{}' for jogging with: '{}'.", marker, e);
+                }
+            });
+        }
+        if let Some((translation_step, rotation_step)) = state.config.nudge_steps {
+            let parent = menu.insert_submenu("Nudge");
+            for (title, axes, offset) in Self::nudge_offsets(translation_step, rotation_step) {
+                // Locked axes can't be nudged either
+                if state.read_only || state.locked_axes.intersects(axes) {
+                    continue;
+                }
+                let server = self.handle();
+                let marker = name.to_string();
+                menu.insert_child(parent, &title, move |_| {
+                    if let Err(e) = server.nudge(&marker, &offset) {
+                        r2r::log_error!(NODE_ID, "Failed to nudge '{}' with: '{}'.", marker, e);
+                    }
+                });
+            }
+        }
+        if !state.visual_states.is_empty() {
+            let parent = menu.insert_submenu("Visual state");
+            for (visual_state, _) in &state.visual_states {
+                let server = self.handle();
+                let marker = name.to_string();
+                let visual_state = visual_state.clone();
+                let title = visual_state.clone();
+                menu.insert_child(parent, &title, move |_| {
+                    if let Err(e) = server.set_visual_state(&marker, &visual_state) {
+                        r2r::log_error!(NODE_ID, "Failed to show '{}' of '{}' with: '{}'.", visual_state, marker, e);
+                    }
+                });
+            }
+        }
+        let named_poses = self.named_poses.lock().unwrap();
+        if state.named_pose_menu && !named_poses.is_empty() {
+            let parent = menu.insert_submenu("Named poses");
+            for pose_name in named_poses.keys() {
+                let server = self.handle();
+                let marker = name.to_string();
+                let pose = pose_name.clone();
+                menu.insert_child(parent, pose_name, move |_| {
+                    if let Err(e) = server.recall_named_pose(&marker, &pose) {
+                        r2r::log_error!(NODE_ID, "Failed to recall pose '{}' with: '{}'.", pose, e);
+                    }
+                });
+            }
+        }
+        Some(menu)
+    }
+
+    /// Confirms the current pose of a marker created with `require_confirmation`.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the marker.
+    ///
+    /// # Remarks
+    ///
+    /// The pose is published on the marker's configured output, e.g. latched on `tf_static`,
+    /// until the marker is moved again. Also available as "Confirm" in the marker's context menu.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the marker doesn't exist or the transform can't be published.
+    pub fn confirm(&self, name: &str) -> Result<()> {
+        let (frame, pose) = {
+            let mut markers = self.markers.lock().unwrap();
+            let state = markers
+                .get_mut(name)
+                .ok_or_else(|| TeachingMarkerError::MarkerNotFound(name.to_string()))?;
+            state.confirmed = true;
+            (state.config.frame.clone(), state.pose.clone())
+        };
+        self.send_tf(TfUpdate {
+            name: name.to_string(),
+            data: Self::make_tf_message(name, &frame, &pose)?,
+            latch: true,
+            received: None,
+        })?;
+        self.emit_pose(name, |marker, pose| TeachingEvent::Confirmed { marker, pose });
+        Ok(())
+    }
+
+    /// Returns whether the current pose of a marker is confirmed, always `true` for markers
+    /// that don't require confirmation.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::MarkerNotFound` if no marker with the given name exists.
+    pub fn is_confirmed(&self, name: &str) -> Result<bool> {
+        match self.markers.lock().unwrap().get(name) {
+            Some(state) => Ok(!state.is_candidate()),
+            None => Err(TeachingMarkerError::MarkerNotFound(name.to_string())),
+        }
+    }
+
+    /// Changes the text shown above a marker in RViz.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the marker.
+    /// * `description` - The text to show, e.g. `"Pre-grasp for part A"`. The name of the marker
+    ///   and its frame stay unchanged.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::MarkerNotFound` if no marker with the given name exists.
+    pub fn set_description(&self, name: &str, description: &str) -> Result<()> {
+        match self.markers.lock().unwrap().get_mut(name) {
+            Some(state) => state.config.description = Some(description.to_string()),
+            None => return Err(TeachingMarkerError::MarkerNotFound(name.to_string())),
+        }
+        self.refresh_marker(name)
+    }
+
+    /// Changes the size of the controls of a marker.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the marker.
+    /// * `scale` - The new scale of the controls, e.g. `0.05` for small parts or `1.0` for mobile robot goals.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::MarkerNotFound` if no marker with the given name exists.
+    pub fn set_scale(&self, name: &str, scale: f32) -> Result<()> {
+        let overlays = match self.markers.lock().unwrap().get_mut(name) {
+            Some(state) => {
+                state.config.scale = scale;
+                state.config.has_overlays()
+            }
+            None => return Err(TeachingMarkerError::MarkerNotFound(name.to_string())),
+        };
+        self.refresh_marker(name)?;
+        if overlays {
+            self.draw_overlays(name);
+        }
+        Ok(())
+    }
+
+    /// Replaces the regular marker visualized inside the teaching controls.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the marker.
+    /// * `visual` - The new visual, or `None` to remove it. A `package://` mesh resource is
+    ///   resolved through the ament index.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::MarkerNotFound` if no marker with the given name exists.
+    pub fn set_visual(&self, name: &str, mut visual: Option<Marker>) -> Result<()> {
+        if let Some(visual) = &mut visual {
+            resolve_visual(visual);
+        }
+        let hidden = match self.markers.lock().unwrap().get_mut(name) {
+            Some(state) => {
+                state.config.visual = visual.clone();
+                state.hidden
+            }
+            None => return Err(TeachingMarkerError::MarkerNotFound(name.to_string())),
+        };
+        // Hidden markers get their visual when they are shown again
+        match visual {
+            Some(visual) if !hidden => self.regular_marker_server.insert(name, visual),
+            _ => self.regular_marker_server.erase(name),
+        }
+        self.regular_marker_server.apply_changes();
+        Ok(())
+    }
+
+    /// Hides a marker in RViz without removing it.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the marker.
+    /// * `suspend_tf` - Whether to also stop publishing the transforms of the marker until it is shown.
+    ///
+    /// # Remarks
+    ///
+    /// The marker keeps its pose, configuration and callbacks and can still be moved programmatically.
+    /// A suspended transform that was already latched on `tf_static` stays known to late subscribers.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::MarkerNotFound` if no marker with the given name exists.
+    pub fn hide(&self, name: &str, suspend_tf: bool) -> Result<()> {
+        match self.markers.lock().unwrap().get_mut(name) {
+            Some(state) => {
+                state.hidden = true;
+                state.tf_suspended = suspend_tf;
+                state.dragging = false;
+            }
+            None => return Err(TeachingMarkerError::MarkerNotFound(name.to_string())),
+        }
+        self.interactive_marker_server.erase(name);
+        self.interactive_marker_server.apply_changes();
+        self.regular_marker_server.erase(name);
+        self.erase_overlays(name);
+        self.regular_marker_server.apply_changes();
+        Ok(())
+    }
+
+    /// Shows a marker hidden with `hide` again, resuming its transforms if they were suspended.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the marker doesn't exist or its transform can't be published.
+    pub fn show(&self, name: &str) -> Result<()> {
+        let (visual, overlays, resume) = match self.markers.lock().unwrap().get_mut(name) {
+            Some(state) if state.hidden => {
+                state.hidden = false;
+                let resume = state.tf_suspended.then(|| (state.config.frame.clone(), state.pose.clone()));
+                state.tf_suspended = false;
+                (state.config.visual.clone(), state.config.has_overlays(), resume)
+            }
+            Some(_) => return Ok(()),
+            None => return Err(TeachingMarkerError::MarkerNotFound(name.to_string())),
+        };
+        self.refresh_marker(name)?;
+        if let Some(visual) = visual {
+            self.regular_marker_server.insert(name, visual);
+            self.regular_marker_server.apply_changes();
+        }
+        if overlays {
+            self.draw_overlays(name);
+        }
+        match resume {
+            Some((frame, pose)) => self.send_tf(TfUpdate {
+                name: name.to_string(),
+                data: Self::make_tf_message(name, &frame, &pose)?,
+                latch: true,
+                received: None,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns whether a marker is hidden with `hide`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::MarkerNotFound` if no marker with the given name exists.
+    pub fn is_hidden(&self, name: &str) -> Result<bool> {
+        match self.markers.lock().unwrap().get(name) {
+            Some(state) => Ok(state.hidden),
+            None => Err(TeachingMarkerError::MarkerNotFound(name.to_string())),
+        }
+    }
+
+    /// Attaches a context menu to a marker, replacing any previous menu.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the marker.
+    /// * `menu` - The menu to attach.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::MarkerNotFound` if no marker with the given name exists.
+    pub fn set_menu(&self, name: &str, menu: MenuHandler) -> Result<()> {
+        match self.markers.lock().unwrap().get_mut(name) {
+            Some(state) => state.menu = Some(menu),
+            None => return Err(TeachingMarkerError::MarkerNotFound(name.to_string())),
+        }
+        self.refresh_marker(name)
+    }
+
+    /// Adds a clickable button control to a marker.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the marker.
+    /// * `button` - The name of the button, replacing any existing button with the same name.
+    /// * `visual` - The marker shown as the button, its pose is relative to the teaching marker.
+    /// * `callback` - The callback invoked when the button is clicked.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::MarkerNotFound` if no marker with the given name exists.
+    pub fn add_button<F>(&self, name: &str, button: &str, mut visual: Marker, callback: F) -> Result<()>
+    where
+        F: Fn(&InteractiveMarkerFeedback) + Send + Sync + 'static,
+    {
+        resolve_visual(&mut visual);
+        match self.markers.lock().unwrap().get_mut(name) {
+            Some(state) => {
+                state.buttons.retain(|b| b.name != button);
+                state.buttons.push(Button {
+                    name: button.to_string(),
+                    visual,
+                    callback: Arc::new(callback),
+                });
+            }
+            None => return Err(TeachingMarkerError::MarkerNotFound(name.to_string())),
+        }
+        self.refresh_marker(name)
+    }
+
+    /// Removes a button control from a marker.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the marker.
+    /// * `button` - The name of the button.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::MarkerNotFound` if no marker with the given name exists.
+    pub fn remove_button(&self, name: &str, button: &str) -> Result<()> {
+        match self.markers.lock().unwrap().get_mut(name) {
+            Some(state) => state.buttons.retain(|b| b.name != button),
+            None => return Err(TeachingMarkerError::MarkerNotFound(name.to_string())),
+        }
+        self.refresh_marker(name)
+    }
+
+    /// Locks axes of a marker by removing their controls.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the marker.
+    /// * `axes` - The axes to lock, in addition to the already locked ones.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::MarkerNotFound` if no marker with the given name exists.
+    pub fn lock_axes(&self, name: &str, axes: AxisMask) -> Result<()> {
+        match self.markers.lock().unwrap().get_mut(name) {
+            Some(state) => state.locked_axes = state.locked_axes | axes,
+            None => return Err(TeachingMarkerError::MarkerNotFound(name.to_string())),
+        }
+        self.refresh_marker(name)
+    }
+
+    /// Unlocks axes of a marker, restoring their controls.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the marker.
+    /// * `axes` - The axes to unlock.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::MarkerNotFound` if no marker with the given name exists.
+    pub fn unlock_axes(&self, name: &str, axes: AxisMask) -> Result<()> {
+        match self.markers.lock().unwrap().get_mut(name) {
+            Some(state) => state.locked_axes = state.locked_axes.difference(axes),
+            None => return Err(TeachingMarkerError::MarkerNotFound(name.to_string())),
+        }
+        self.refresh_marker(name)
+    }
+
+    /// Stops processing the feedback of a marker, freezing its transform.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the marker.
+    ///
+    /// # Remarks
+    ///
+    /// Useful while the robot moves to the taught frame. The controls stay visible, but feedback
+    /// from RViz is ignored, including feedback callbacks, menus and buttons, and the marker
+    /// snaps back to its frozen pose when dragged. Programmatic moves still apply.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::MarkerNotFound` if no marker with the given name exists.
+    pub fn pause(&self, name: &str) -> Result<()> {
+        match self.markers.lock().unwrap().get_mut(name) {
+            Some(state) => {
+                state.paused = true;
+                state.dragging = false;
+                Ok(())
+            }
+            None => Err(TeachingMarkerError::MarkerNotFound(name.to_string())),
+        }
+    }
+
+    /// Resumes processing the feedback of a marker paused with `pause`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::MarkerNotFound` if no marker with the given name exists.
+    pub fn resume(&self, name: &str) -> Result<()> {
+        match self.markers.lock().unwrap().get_mut(name) {
+            Some(state) => {
+                state.paused = false;
+                Ok(())
+            }
+            None => Err(TeachingMarkerError::MarkerNotFound(name.to_string())),
+        }
+    }
+
+    /// Returns whether the feedback of a marker is paused with `pause`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::MarkerNotFound` if no marker with the given name exists.
+    pub fn is_paused(&self, name: &str) -> Result<bool> {
+        match self.markers.lock().unwrap().get(name) {
+            Some(state) => Ok(state.paused),
+            None => Err(TeachingMarkerError::MarkerNotFound(name.to_string())),
+        }
+    }
+
+    /// Makes a marker read-only by removing all controls that move or resize it.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the marker.
+    ///
+    /// # Remarks
+    ///
+    /// The marker stays visible with its buttons and context menu, and its transform keeps being
+    /// published. Feedback moving the marker is ignored until it is unlocked, while it can still
+    /// be moved programmatically, e.g. with `set_pose`. The locked axes are restored by `unlock`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::MarkerNotFound` if no marker with the given name exists.
+    pub fn lock(&self, name: &str) -> Result<()> {
+        self.set_read_only(name, true)
+    }
+
+    /// Restores the controls of a marker locked with `lock`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::MarkerNotFound` if no marker with the given name exists.
+    pub fn unlock(&self, name: &str) -> Result<()> {
+        self.set_read_only(name, false)
+    }
+
+    /// Returns whether a marker is locked with `lock`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::MarkerNotFound` if no marker with the given name exists.
+    pub fn is_locked(&self, name: &str) -> Result<bool> {
+        match self.markers.lock().unwrap().get(name) {
+            Some(state) => Ok(state.read_only),
+            None => Err(TeachingMarkerError::MarkerNotFound(name.to_string())),
+        }
+    }
+
+    /// Sets whether a marker is read-only and rebuilds its controls.
+    fn set_read_only(&self, name: &str, read_only: bool) -> Result<()> {
+        match self.markers.lock().unwrap().get_mut(name) {
+            Some(state) => {
+                state.read_only = read_only;
+                state.dragging = false;
+            }
+            None => return Err(TeachingMarkerError::MarkerNotFound(name.to_string())),
+        }
+        self.refresh_marker(name)
+    }
+
+    /// Creates an `InteractiveMarker` with the controls of the marker's control profile.
+    ///
+    /// # Arguments
+    ///
+    /// * `state` - The state of the marker, holding its configuration and current pose.
+    ///
+    /// # Returns
+    ///
+    /// An `InteractiveMarker` configured with controls.
+    fn create_marker(state: &MarkerState) -> InteractiveMarker {
+        let name = &state.config.name;
+        let mut int_marker = InteractiveMarker::default();
+        int_marker.header.frame_id = state.config.frame.to_string();
+        int_marker.name = format!("{name}");
+        int_marker.description = state
+            .config
+            .description
+            .clone()
+            .unwrap_or_else(|| format!("{name}"));
+        int_marker.scale = state.config.scale;
+        int_marker.pose = state.pose.clone();
+
+        // Add the controls of the marker's control profile
+        // Free controls are left out as soon as any of their axes is locked
+        let locked_axes = if state.read_only {
+            AxisMask::ALL
+        } else if state.config.constraints.gravity_aligned {
+            state.locked_axes | AxisMask::ROTATE_X | AxisMask::ROTATE_Y
+        } else {
+            state.locked_axes
+        };
+        int_marker.controls = profile_controls(
+            state.config.profile,
+            locked_axes,
+            &state.config.control_visuals,
+            &state.config.control_orientations,
+        );
+        for extra in &state.config.extra_controls {
+            if !locked_axes.intersects(extra_control_axes(*extra)) {
+                int_marker.controls.push(extra_control(*extra, state.config.scale));
+            }
+        }
+
+        // Add a button control for each button
+        for button in &state.buttons {
+            let mut control = InteractiveMarkerControl::default();
+            control.name = button_control_name(&button.name);
+            control.interaction_mode = InteractiveMarkerControl::BUTTON as u8;
+            control.always_visible = true;
+            control.markers.push(button.visual.clone());
+            int_marker.controls.push(control);
+        }
+
+        if state.config.resize_handles && !state.read_only {
+            int_marker.controls.extend(resize_controls(state.config.scale));
+        }
+
+        // Add a menu control if the marker has a context menu
+        if let Some(menu) = state.active_menu.as_ref().filter(|menu| !menu.is_empty()) {
+            int_marker.menu_entries = menu.entries();
+            let mut control = InteractiveMarkerControl::default();
+            control.name = "menu".to_string();
+            control.interaction_mode = InteractiveMarkerControl::MENU as u8;
+            control.always_visible = true;
+            int_marker.controls.push(control);
+        }
+
+        int_marker
+    }
+
+    /// Processes feedback from the interactive marker and generates a TF message.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the marker.
+    /// * `spawn_at` - The frame ID where the marker is spawned.
+    /// * `constraints` - The constraints applied to the feedback pose.
+    /// * `feedback` - The feedback received from the interactive marker.
+    ///
+    /// # Returns
+    ///
+    /// The validated and constrained pose and a `TFMessage` containing the corresponding transform,
+    /// or an error if the pose is invalid or the current time can't be read.
+    ///
+    /// # Remarks
+    ///
+    /// The transform is delivered to the `TransformSink` of the server, which publishes it on
+    /// the marker's `TfOutput` unless another sink was given to `new_with_sink`.
+    fn process_feedback(
+        name: &str,
+        spawn_at: &str,
+        constraints: &PoseConstraints,
+        feedback: &InteractiveMarkerFeedback,
+    ) -> Result<(Pose, TFMessage)> {
+        let pose = sanitize_pose(&feedback.pose)
+            .ok_or_else(|| TeachingMarkerError::InvalidPose(name.to_string()))?;
+        let pose = constraints.apply(&pose);
+        let data = Self::make_tf_message(name, spawn_at, &pose)?;
+        Ok((pose, data))
+    }
+
+    /// Creates a TF message with the current time stamp for a marker pose.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the marker, used as the child frame ID.
+    /// * `parent` - The parent frame ID.
+    /// * `pose` - The pose of the marker relative to the parent frame.
+    fn make_tf_message(name: &str, parent: &str, pose: &Pose) -> Result<TFMessage> {
+        let transforms = vec![TransformStamped {
+            header: Header {
+                stamp: Self::now()?,
+                frame_id: parent.to_string(),
+            },
+            child_frame_id: name.to_string(),
+            transform: pose_to_transform(pose),
+        }];
+
+        Ok(TFMessage { transforms })
+    }
+
+    /// Returns the current ROS time.
+    fn now() -> Result<Time> {
+        let mut clock = r2r::Clock::create(r2r::ClockType::RosTime)?;
+        let now = clock.get_now()?;
+        Ok(r2r::Clock::to_builtin_time(&now))
+    }
+
+    /// Handles feedback received from RViz for a marker.
+    ///
+    /// Tracks the interaction state, publishes the updated transform and runs the user hooks.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(marker = name, event = feedback.event_type))
+    )]
+    fn handle_feedback(&self, name: &str, feedback: InteractiveMarkerFeedback) {
+        // Feedback still in flight when the server was deactivated
+        if !self.is_active() {
+            return;
+        }
+        self.metrics.lock().unwrap().feedback(Instant::now());
+        // The collision check is a user callback, so it runs before the markers are locked
+        let collision = self.collision_verdict(name, &feedback);
+        // Only the client owning the current drag moves the marker
+        let claim = match self.markers.lock().unwrap().get_mut(name) {
+            Some(state) => state
+                .claim_drag(&feedback)
+                .map(|()| Self::feedback_actions(name, state, &feedback, collision)),
+            None => return,
+        };
+        let actions = match claim {
+            Ok(actions) => actions,
+            Err(conflict) => return self.reject_feedback(name, conflict),
+        };
+
+        if let Some(visual) = actions.highlight {
+            self.regular_marker_server.insert(name, visual);
+            self.regular_marker_server.apply_changes();
+        }
+
+        // Push the constrained pose back so RViz shows where the marker actually is
+        if let Some(pose) = actions.corrected_pose {
+            self.interactive_marker_server.set_pose(name, pose, None);
+            self.interactive_marker_server.apply_changes();
+        }
+
+        // Record exactly the feedback that drives the published transforms
+        if matches!(actions.update, Ok(Some(_))) {
+            self.echo_feedback(&feedback);
+        }
+        let result = match actions.update {
+            Ok(Some(update)) => self.send_tf(update),
+            Ok(None) => Ok(()),
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(()) => (),
+            Err(TeachingMarkerError::InvalidPose(_)) if !actions.warn_invalid => (),
+            Err(e @ TeachingMarkerError::InvalidPose(_)) => {
+                r2r::log_warn!(NODE_ID, "Ignoring feedback with: '{}'.", e);
+            }
+            Err(e) => {
+                trace_error!(error = %e, "failed to process feedback");
+                r2r::log_error!(NODE_ID, "Failed to process feedback for '{}' with: '{}'.", name, e);
+            }
+        }
+
+        if let Some(snap) = &actions.snap {
+            if let Err(e) = self.snap_to_nearest_frame(name, snap) {
+                r2r::log_error!(NODE_ID, "Failed to snap '{}' to a frame with: '{}'.", name, e);
+            }
+        }
+
+        // Check and report the pose the marker settled at, after snapping
+        if actions.released {
+            self.run_reachability_check(name);
+            self.emit_pose(name, |marker, pose| TeachingEvent::Released { marker, pose });
+        }
+
+        if let Some(scale) = actions.resize {
+            if let Err(e) = self.set_scale(name, scale) {
+                r2r::log_error!(NODE_ID, "Failed to resize '{}' with: '{}'.", name, e);
+            }
+        }
+
+        // Drag the members of the groups this marker is the handle of along
+        if let Some(motion) = &actions.motion {
+            self.move_group_members(name, motion, actions.latch);
+            self.update_mirrors(name, actions.latch);
+        }
+
+        // Run the user callbacks outside of the lock so they can call back into the server
+        for callback in actions.callbacks {
+            callback(&feedback);
+        }
+    }
+
+    /// Updates the state of a marker with received feedback and decides what to do about it.
+    ///
+    /// Runs while the marker map is locked, so it must not call back into the server. The collision
+    /// check has been run beforehand, see `collision_verdict`.
+    fn feedback_actions(
+        name: &str,
+        state: &mut MarkerState,
+        feedback: &InteractiveMarkerFeedback,
+        collision: Option<CollisionVerdict>,
+    ) -> FeedbackActions {
+        state.last_feedback = Some(Instant::now());
+        // Paused markers drop all feedback and snap back to their frozen pose
+        if state.paused {
+            return FeedbackActions {
+                update: Ok(None),
+                corrected_pose: (feedback.pose != state.pose).then(|| state.pose.clone()),
+                callbacks: vec![],
+                warn_invalid: false,
+                motion: None,
+                latch: true,
+                highlight: None,
+                resize: None,
+                snap: None,
+                released: false,
+            };
+        }
+
+        let was_dragging = state.dragging;
+        if feedback.event_type == InteractiveMarkerFeedback::MOUSE_DOWN as u8 {
+            state.dragging = true;
+        } else if feedback.event_type == InteractiveMarkerFeedback::MOUSE_UP as u8 {
+            state.dragging = false;
+        }
+        let was_colliding = state.colliding;
+        // Intermediate poses are published while dragging, everything else is final
+        let latch = !state.dragging;
+
+        let processed = Self::process_feedback(
+            name,
+            &state.config.frame,
+            &state.config.constraints,
+            feedback,
+        );
+        let mut corrected_pose = None;
+        let mut motion = None;
+        let update = processed.map(|(pose, mut data)| {
+            // Locked markers stay put, a client with stale controls is shown the locked pose
+            if state.read_only {
+                if pose != state.pose {
+                    corrected_pose = Some(state.pose.clone());
+                }
+                return None;
+            }
+            if let Some(guard) = state.collision_guard.as_ref().filter(|_| pose != state.pose) {
+                // A check replaced since the verdict was taken leaves the pose unchecked
+                let colliding = guard.verdict(collision.as_ref(), &pose);
+                // Vetoed poses are never taken, the marker stays at its last free pose
+                if guard.veto && colliding != Some(false) {
+                    corrected_pose = Some(state.pose.clone());
+                    return None;
+                }
+                if let Some(colliding) = colliding {
+                    state.colliding = colliding;
+                }
+            }
+            if pose != feedback.pose {
+                corrected_pose = Some(pose.clone());
+            }
+            if pose != state.pose {
+                // The rigid motion taking the previous pose to the new one, in the parent frame
+                motion = Some(compose(
+                    &pose_to_transform(&pose),
+                    &inverse(&pose_to_transform(&state.pose)),
+                ));
+                // A moved marker has to be confirmed again
+                state.confirmed = false;
+            }
+            state.pose = pose;
+            if latch {
+                state.undo.settle(&state.pose);
+            }
+
+            // The pose is kept, but only the selected event types publish it
+            if !state.config.tf_events.intersects(FeedbackEvents::of(feedback)) {
+                return None;
+            }
+
+            // Throttle intermediate poses, final poses are always published
+            let now = Instant::now();
+            let throttled = match (state.config.max_rate, state.last_published) {
+                (Some(rate), Some(last)) if !latch && rate > 0.0 => {
+                    now.duration_since(last).as_secs_f64() < 1.0 / rate
+                }
+                _ => false,
+            };
+            // Drags are smoothed, the final pose is published as it is
+            if let Some(filter) = &mut state.smoothing {
+                if latch {
+                    filter.reset();
+                } else {
+                    let smoothed = pose_to_transform(&filter.apply(&state.pose, now));
+                    for t in &mut data.transforms {
+                        t.transform = smoothed.clone();
+                    }
+                }
+            }
+            let within_deadband = match (state.config.deadband, &state.published_pose) {
+                (Some((translation, rotation)), Some(published)) if !latch => {
+                    let (p, q) = (&published.position, &state.pose.position);
+                    let (dx, dy, dz) = (q.x - p.x, q.y - p.y, q.z - p.z);
+                    (dx * dx + dy * dy + dz * dz).sqrt() <= translation
+                        && rotation_angle(&published.orientation, &state.pose.orientation) <= rotation
+                }
+                _ => false,
+            };
+            if throttled || within_deadband {
+                None
+            } else {
+                state.last_published = Some(now);
+                Some(TfUpdate {
+                    name: name.to_string(),
+                    data,
+                    latch,
+                    received: state.last_feedback,
+                })
+            }
+        });
+
+        // Colliding markers are red, markers being dragged are highlighted
+        let highlight_changed = state.config.highlight && state.dragging != was_dragging;
+        let highlight = match &state.config.visual {
+            Some(visual) if highlight_changed || state.colliding != was_colliding => Some(if state.colliding {
+                tinted(visual, COLLISION_RGB)
+            } else if state.config.highlight && state.dragging {
+                highlighted(visual)
+            } else {
+                visual.clone()
+            }),
+            _ => None,
+        };
+
+        // Snap once the user lets go of the marker
+        let released = was_dragging && !state.dragging && !state.read_only;
+        let snap = if released {
+            state.config.frame_snap.clone()
+        } else {
+            None
+        };
+
+        let mut resize = None;
+        let mut callbacks = vec![];
+        if feedback.event_type == InteractiveMarkerFeedback::BUTTON_CLICK as u8
+            && (feedback.control_name == GROW_CONTROL || feedback.control_name == SHRINK_CONTROL)
+        {
+            let factor = if feedback.control_name == GROW_CONTROL {
+                RESIZE_FACTOR
+            } else {
+                1.0 / RESIZE_FACTOR
+            };
+            resize = Some((state.config.scale * factor).clamp(RESIZE_LIMITS.0, RESIZE_LIMITS.1));
+        } else if feedback.event_type == InteractiveMarkerFeedback::MENU_SELECT as u8 {
+            if let Some(callback) = state
+                .active_menu
+                .as_ref()
+                .and_then(|menu| menu.callback(feedback.menu_entry_id))
+            {
+                callbacks.push(callback);
+            }
+        } else if feedback.event_type == InteractiveMarkerFeedback::BUTTON_CLICK as u8 {
+            if let Some(button) = state
+                .buttons
+                .iter()
+                .find(|b| button_control_name(&b.name) == feedback.control_name)
+            {
+                callbacks.push(button.callback.clone());
+            }
+        }
+        callbacks.extend(state.feedback_hooks.iter().cloned());
+
+        FeedbackActions {
+            update,
+            corrected_pose,
+            callbacks,
+            warn_invalid: state.config.warn_on_invalid_pose,
+            motion,
+            latch,
+            highlight,
+            resize,
+            snap,
+            released,
+        }
+    }
+
+    /// Attaches a marker to a different parent frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the marker.
+    /// * `frame` - The new parent frame ID.
+    ///
+    /// # Remarks
+    ///
+    /// The pose relative to the parent is kept, so the marker moves along with the new frame.
+    /// RViz and the published transforms are updated immediately.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the marker doesn't exist or the transform can't be published.
+    pub fn set_parent(&self, name: &str, frame: &str) -> Result<()> {
+        self.reparent(name, frame, None)
+    }
+
+    /// Attaches a marker to a different parent frame without moving it in space.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the marker.
+    /// * `frame` - The new parent frame ID.
+    ///
+    /// # Remarks
+    ///
+    /// The pose relative to the new parent is computed from the transform between the old and
+    /// the new parent, which requires a transform lookup, see `listen_tf`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the marker doesn't exist, the transform between the parents can't be
+    /// looked up or the new transform can't be published.
+    pub fn set_parent_keep_world(&self, name: &str, frame: &str) -> Result<()> {
+        let (old_frame, pose) = {
+            let markers = self.markers.lock().unwrap();
+            let state = markers
+                .get(name)
+                .ok_or_else(|| TeachingMarkerError::MarkerNotFound(name.to_string()))?;
+            (state.config.frame.clone(), state.pose.clone())
+        };
+        let lookup = self.transform_lookup.lock().unwrap().clone();
+        let new_to_old = lookup
+            .and_then(|lookup| lookup.lookup_transform(frame, &old_frame))
+            .ok_or_else(|| TeachingMarkerError::TransformUnavailable(frame.to_string(), old_frame))?;
+        let pose = transform_to_pose(&compose(&new_to_old, &pose_to_transform(&pose)));
+        self.reparent(name, frame, Some(pose))
+    }
+
+    /// Changes the parent frame of a marker, optionally together with its pose, and publishes the result.
+    fn reparent(&self, name: &str, frame: &str, pose: Option<Pose>) -> Result<()> {
+        let pose = {
+            let mut markers = self.markers.lock().unwrap();
+            let state = markers
+                .get_mut(name)
+                .ok_or_else(|| TeachingMarkerError::MarkerNotFound(name.to_string()))?;
+            state.config.frame = frame.to_string();
+            if let Some(pose) = pose {
+                state.pose = pose;
+                state.spawn_pose = state.pose.clone();
+                state.undo.reset(&state.pose);
+            }
+            state.pose.clone()
+        };
+        self.refresh_marker(name)?;
+
+        self.send_tf(TfUpdate {
+            name: name.to_string(),
+            data: Self::make_tf_message(name, frame, &pose)?,
+            latch: true,
+            received: None,
+        })
+    }
+
+    /// Returns the names of the markers whose parent frame is the given marker.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the parent marker.
+    pub fn children(&self, name: &str) -> Vec<String> {
+        self.markers
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, state)| state.config.frame == name)
+            .map(|(child, _)| child.clone())
+            .collect()
+    }
+
+    /// Inserts a tool center point marker as an offset of a flange marker.
+    ///
+    /// # Arguments
+    ///
+    /// * `flange` - The name of the marker of the taught flange pose.
+    /// * `tcp` - The configuration of the TCP marker, its pose is the offset relative to the flange.
+    ///
+    /// # Remarks
+    ///
+    /// The TCP is attached like a marker built with `relative_to`: it moves along with the
+    /// flange and its offset stays editable on its own. Both frames are published, the TCP as a
+    /// child of the flange. See `resolved_pose` for the TCP pose relative to the flange's parent.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::MarkerNotFound` if the flange doesn't exist, or an error if
+    /// the TCP marker can't be inserted.
+    pub fn insert_tcp(&self, flange: &str, tcp: TeachingMarker) -> Result<()> {
+        let mut tcp = tcp;
+        tcp.frame = flange.to_string();
+        tcp.parent_marker = true;
+        self.insert_marker(tcp)
+    }
+
+    /// Returns the pose of a marker relative to the first frame up its chain that isn't a marker.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the marker.
+    ///
+    /// # Remarks
+    ///
+    /// For a marker attached with `relative_to` or `insert_tcp` the poses of its parent markers are
+    /// composed, e.g. giving the TCP pose in the frame the flange is taught in. For other markers
+    /// this is the same as `pose`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::MarkerNotFound` if no marker with the given name exists.
+    pub fn resolved_pose(&self, name: &str) -> Result<PoseStamped> {
+        let markers = self.markers.lock().unwrap();
+        let mut state = markers
+            .get(name)
+            .ok_or_else(|| TeachingMarkerError::MarkerNotFound(name.to_string()))?;
+        let mut transform = pose_to_transform(&state.pose);
+        // Bounded by the number of markers, in case of a cycle
+        for _ in 0..markers.len() {
+            match markers.get(&state.config.frame) {
+                Some(parent) if state.config.parent_marker => {
+                    transform = compose(&pose_to_transform(&parent.pose), &transform);
+                    state = parent;
+                }
+                _ => break,
+            }
+        }
+        Ok(PoseStamped {
+            header: Header {
+                frame_id: state.config.frame.clone(),
+                ..Header::default()
+            },
+            pose: transform_to_pose(&transform),
+        })
+    }
+
+    /// Creates a group of markers that move rigidly together when its handle marker is dragged.
+    ///
+    /// # Arguments
+    ///
+    /// * `group` - The name of the group, replacing any existing group with the same name.
+    /// * `handle` - The name of the marker that drags the group along.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::MarkerNotFound` if the handle marker doesn't exist.
+    pub fn create_group(&self, group: &str, handle: &str) -> Result<()> {
+        if !self.markers.lock().unwrap().contains_key(handle) {
+            return Err(TeachingMarkerError::MarkerNotFound(handle.to_string()));
+        }
+        self.groups.lock().unwrap().insert(
+            group.to_string(),
+            MarkerGroup {
+                handle: handle.to_string(),
+                members: vec![],
+            },
+        );
+        Ok(())
+    }
+
+    /// Adds a marker to a group.
+    ///
+    /// # Arguments
+    ///
+    /// * `group` - The name of the group.
+    /// * `name` - The name of the marker, which must have the same parent frame as the group handle.
+    ///
+    /// # Remarks
+    ///
+    /// The member can still be dragged on its own, which doesn't move the rest of the group.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the group or the marker doesn't exist, or the marker isn't in the
+    /// parent frame of the handle.
+    pub fn add_to_group(&self, group: &str, name: &str) -> Result<()> {
+        let mut groups = self.groups.lock().unwrap();
+        let marker_group = groups
+            .get_mut(group)
+            .ok_or_else(|| TeachingMarkerError::GroupNotFound(group.to_string()))?;
+        {
+            let markers = self.markers.lock().unwrap();
+            let frame_of = |marker: &str| {
+                markers
+                    .get(marker)
+                    .map(|state| state.config.frame.clone())
+                    .ok_or_else(|| TeachingMarkerError::MarkerNotFound(marker.to_string()))
+            };
+            let handle_frame = frame_of(&marker_group.handle)?;
+            if frame_of(name)? != handle_frame {
+                return Err(TeachingMarkerError::FrameMismatch(name.to_string(), handle_frame));
+            }
+        }
+        if name != marker_group.handle && !marker_group.members.iter().any(|m| m == name) {
+            marker_group.members.push(name.to_string());
+        }
+        Ok(())
+    }
+
+    /// Applies the motion of a handle marker to the members of its groups.
+    ///
+    /// # Arguments
+    ///
+    /// * `handle` - The name of the dragged marker.
+    /// * `motion` - The rigid motion of the handle in its parent frame.
+    /// * `latch` - Whether the handle pose is final.
+    fn move_group_members(&self, handle: &str, motion: &Transform, latch: bool) {
+        let members: Vec<String> = self
+            .groups
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|group| group.handle == handle)
+            .flat_map(|group| group.members.iter().cloned())
+            .collect();
+        if members.is_empty() {
+            return;
+        }
+
+        let mut moved = vec![];
+        {
+            let mut markers = self.markers.lock().unwrap();
+            let handle_frame = match markers.get(handle) {
+                Some(state) => state.config.frame.clone(),
+                None => return,
+            };
+            for member in members {
+                let state = match markers.get_mut(&member) {
+                    Some(state) => state,
+                    None => continue,
+                };
+                // Members reparented since joining the group can't be moved in the handle frame
+                if state.config.frame != handle_frame {
+                    r2r::log_warn!(
+                        NODE_ID,
+                        "Not moving '{}' with '{}', it is no longer in frame '{}'.",
+                        member,
+                        handle,
+                        handle_frame
+                    );
+                    continue;
+                }
+                state.pose = transform_to_pose(&compose(motion, &pose_to_transform(&state.pose)));
+                if latch {
+                    state.undo.reset(&state.pose);
+                }
+                moved.push((member, handle_frame.clone(), state.pose.clone()));
+            }
+        }
+
+        for (member, _, pose) in &moved {
+            self.interactive_marker_server.set_pose(member, pose.clone(), None);
+        }
+        self.interactive_marker_server.apply_changes();
+
+        for (member, frame, pose) in moved {
+            let result = Self::make_tf_message(&member, &frame, &pose).and_then(|data| {
+                self.send_tf(TfUpdate {
+                    name: member.clone(),
+                    data,
+                    latch,
+                    received: None,
+                })
+            });
+            if let Err(e) = result {
+                r2r::log_error!(NODE_ID, "Failed to move '{}' with its group with: '{}'.", member, e);
+            }
+            self.update_mirrors(&member, latch);
+        }
+    }
+
+    /// Saves the current pose of a marker in the named pose library.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the marker.
+    /// * `pose_name` - The name of the pose, e.g. `"pre_grasp"`, replacing any pose with the same name.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::MarkerNotFound` if no marker with the given name exists.
+    pub fn save_named_pose(&self, name: &str, pose_name: &str) -> Result<()> {
+        let pose_stamped = self.pose(name)?;
+        self.named_poses
+            .lock()
+            .unwrap()
+            .insert(pose_name.to_string(), pose_stamped);
+        self.refresh_named_pose_menus()
+    }
+
+    /// Moves a marker to a pose from the named pose library.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the marker.
+    /// * `pose_name` - The name of the pose.
+    ///
+    /// # Remarks
+    ///
+    /// Poses saved in another frame than the marker's are converted with the transform lookup.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the marker or the pose doesn't exist, or the pose can't be converted
+    /// into the marker's frame.
+    pub fn recall_named_pose(&self, name: &str, pose_name: &str) -> Result<()> {
+        let pose_stamped = self
+            .named_poses
+            .lock()
+            .unwrap()
+            .get(pose_name)
+            .cloned()
+            .ok_or_else(|| TeachingMarkerError::NamedPoseNotFound(pose_name.to_string()))?;
+        let frame = match self.markers.lock().unwrap().get(name) {
+            Some(state) => state.config.frame.clone(),
+            None => return Err(TeachingMarkerError::MarkerNotFound(name.to_string())),
+        };
+
+        let pose_frame = pose_stamped.header.frame_id;
+        let pose = if pose_frame == frame {
+            pose_stamped.pose
+        } else {
+            let lookup = self.transform_lookup.lock().unwrap().clone();
+            let frame_to_pose_frame = lookup
+                .and_then(|lookup| lookup.lookup_transform(&frame, &pose_frame))
+                .ok_or_else(|| TeachingMarkerError::TransformUnavailable(frame, pose_frame))?;
+            transform_to_pose(&compose(
+                &frame_to_pose_frame,
+                &pose_to_transform(&pose_stamped.pose),
+            ))
+        };
+        self.set_pose(name, &pose)
+    }
+
+    /// Returns the names of the poses in the named pose library, in alphabetical order.
+    pub fn list_named_poses(&self) -> Vec<String> {
+        self.named_poses.lock().unwrap().keys().cloned().collect()
+    }
+
+    /// Sets whether a marker has a "Named poses" submenu in its context menu to recall saved poses.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the marker.
+    /// * `enabled` - Whether to show the submenu, it is added after the entries set with `set_menu`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::MarkerNotFound` if no marker with the given name exists.
+    pub fn set_named_pose_menu(&self, name: &str, enabled: bool) -> Result<()> {
+        match self.markers.lock().unwrap().get_mut(name) {
+            Some(state) => state.named_pose_menu = enabled,
+            None => return Err(TeachingMarkerError::MarkerNotFound(name.to_string())),
+        }
+        self.refresh_marker(name)
+    }
+
+    /// Rebuilds the markers showing the named pose menu after the library changed.
+    fn refresh_named_pose_menus(&self) -> Result<()> {
+        let names: Vec<String> = self
+            .markers
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, state)| state.named_pose_menu)
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in names {
+            match self.refresh_marker(&name) {
+                Ok(()) | Err(TeachingMarkerError::MarkerNotFound(_)) => (),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Saves the name, parent frame, pose and control profile of all markers to a file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The file to write, as YAML if its extension is `.yaml` or `.yml` and as JSON otherwise.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can't be written.
+    pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut saved: Vec<SavedMarker> = self
+            .markers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, state)| SavedMarker {
+                name: name.clone(),
+                frame: state.config.frame.clone(),
+                pose: state.pose.clone(),
+                profile: state.config.profile,
+            })
+            .collect();
+        saved.sort_by(|a, b| a.name.cmp(&b.name));
+        persist::write(path.as_ref(), saved)
+    }
+
+    /// Restores markers from a file written by `save_to_file`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The file to read, as YAML if its extension is `.yaml` or `.yml` and as JSON otherwise.
+    ///
+    /// # Remarks
+    ///
+    /// Markers that don't exist are inserted with the stored frame, pose and control profile.
+    /// Existing markers are moved to the stored frame and pose.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can't be read or parsed, or a marker can't be restored.
+    pub fn load_from_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        for saved in persist::read(path.as_ref())? {
+            let existing = self
+                .markers
+                .lock()
+                .unwrap()
+                .get(&saved.name)
+                .map(|state| state.config.frame.clone());
+            match existing {
+                Some(frame) => {
+                    if frame != saved.frame {
+                        self.set_parent(&saved.name, &saved.frame)?;
+                    }
+                    self.move_marker(&saved.name, &saved.pose, true)?;
+                }
+                None => self.insert_marker(
+                    TeachingMarker::builder(&saved.name)
+                        .frame(&saved.frame)
+                        .pose(saved.pose)
+                        .profile(saved.profile)
+                        .build(),
+                )?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Inserts a copy of an existing marker.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the marker to copy.
+    /// * `new_name` - The name of the copy.
+    /// * `offset` - An optional offset of the copy, expressed in the frame of the copied marker.
+    ///
+    /// # Remarks
+    ///
+    /// The copy gets the configuration of the original, i.e. its controls, visual and constraints,
+    /// as well as its menu, buttons and locked axes. Feedback callbacks, pose streams and
+    /// followed topics are not copied.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the marker doesn't exist or the copy can't be inserted.
+    pub fn duplicate(&self, name: &str, new_name: &str, offset: Option<Pose>) -> Result<()> {
+        let (mut config, menu, named_pose_menu, buttons, locked_axes) = {
+            let markers = self.markers.lock().unwrap();
+            let state = markers
+                .get(name)
+                .ok_or_else(|| TeachingMarkerError::MarkerNotFound(name.to_string()))?;
+            let pose = match &offset {
+                Some(offset) => transform_to_pose(&compose(
+                    &pose_to_transform(&state.pose),
+                    &pose_to_transform(offset),
+                )),
+                None => state.pose.clone(),
+            };
+            let mut config = state.config.clone();
+            config.spawn_at = SpawnAt::Pose(pose);
+            (
+                config,
+                state.menu.clone(),
+                state.named_pose_menu,
+                state.buttons.clone(),
+                state.locked_axes,
+            )
+        };
+        config.name = new_name.to_string();
+        config.wait_for_frame = None;
+        config.parent_marker = false;
+
+        self.insert_marker(config)?;
+        match self.markers.lock().unwrap().get_mut(new_name) {
+            Some(state) => {
+                state.menu = menu;
+                state.named_pose_menu = named_pose_menu;
+                state.buttons = buttons;
+                state.locked_axes = locked_axes;
+            }
+            None => return Err(TeachingMarkerError::MarkerNotFound(new_name.to_string())),
+        }
+        self.refresh_marker(new_name)
+    }
+
+    /// Inserts a marker that always sits at the reflection of another marker across a plane.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The name of the marker to mirror.
+    /// * `name` - The name of the mirrored marker.
+    /// * `plane` - The mirror plane in the parent frame of the source.
+    ///
+    /// # Remarks
+    ///
+    /// The mirrored marker copies the configuration of the source, but has no controls of its own.
+    /// It follows the source live while it is dragged or moved.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the source doesn't exist or the mirrored marker can't be inserted.
+    pub fn insert_mirrored(&self, source: &str, name: &str, plane: MirrorPlane) -> Result<()> {
+        let mut config = {
+            let mut markers = self.markers.lock().unwrap();
+            let state = markers
+                .get_mut(source)
+                .ok_or_else(|| TeachingMarkerError::MarkerNotFound(source.to_string()))?;
+            state.mirrors.retain(|(mirror, _)| mirror != name);
+            state.mirrors.push((name.to_string(), plane.clone()));
+            let mut config = state.config.clone();
+            config.spawn_at = SpawnAt::Pose(plane.reflect(&state.pose));
+            config
+        };
+        config.name = name.to_string();
+        config.wait_for_frame = None;
+        config.parent_marker = false;
+
+        let result = self
+            .insert_marker(config)
+            .and_then(|()| self.lock_axes(name, AxisMask::ALL));
+        if result.is_err() {
+            if let Some(state) = self.markers.lock().unwrap().get_mut(source) {
+                state.mirrors.retain(|(mirror, _)| mirror != name);
+            }
+        }
+        result
+    }
+
+    /// Moves the mirrors of a marker to the reflections of its current pose.
+    fn update_mirrors(&self, name: &str, latch: bool) {
+        let reflected: Vec<(String, Pose)> = match self.markers.lock().unwrap().get(name) {
+            Some(state) => state
+                .mirrors
+                .iter()
+                .map(|(mirror, plane)| (mirror.clone(), plane.reflect(&state.pose)))
+                .collect(),
+            None => return,
+        };
+        for (mirror, pose) in reflected {
+            match self.move_marker(&mirror, &pose, latch) {
+                Ok(()) | Err(TeachingMarkerError::MarkerNotFound(_)) => (),
+                Err(e) => {
+                    r2r::log_error!(NODE_ID, "Failed to update the mirror '{}' with: '{}'.", mirror, e);
+                }
+            }
+        }
+    }
+
+    /// Moves a marker to a pose.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the marker.
+    /// * `pose` - The new pose relative to the marker's parent frame.
+    ///
+    /// # Remarks
+    ///
+    /// The pose is passed through the marker's constraints like a pose dragged in RViz, then
+    /// shown and its transform is published like a pose released by the user. The move can be
+    /// undone with `undo`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the marker doesn't exist, the pose is invalid or the transform can't be published.
+    pub fn set_pose(&self, name: &str, pose: &Pose) -> Result<()> {
+        let pose = sanitize_pose(pose).ok_or_else(|| TeachingMarkerError::InvalidPose(name.to_string()))?;
+        let pose = match self.markers.lock().unwrap().get_mut(name) {
+            Some(state) => {
+                let pose = state.config.constraints.apply(&pose);
+                state.undo.settle(&pose);
+                pose
+            }
+            None => return Err(TeachingMarkerError::MarkerNotFound(name.to_string())),
+        };
+        self.move_marker(name, &pose, true)
+    }
+
+    /// Returns the recorded poses of a marker, oldest first.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the marker.
+    ///
+    /// # Returns
+    ///
+    /// The time stamps and poses of the published transforms, relative to the marker's parent
+    /// frame at the time. At most `history_depth` entries are kept.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::MarkerNotFound` if no marker with the given name exists.
+    pub fn history(&self, name: &str) -> Result<Vec<(Time, Pose)>> {
+        match self.markers.lock().unwrap().get(name) {
+            Some(state) => Ok(state.pose_history.iter().cloned().collect()),
+            None => Err(TeachingMarkerError::MarkerNotFound(name.to_string())),
+        }
+    }
+
+    /// Returns the recorded pose of a marker at a point in time.
+    ///
+    /// # Returns
+    ///
+    /// The newest recorded pose published at or before `time`, or `None` if there is none.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::MarkerNotFound` if no marker with the given name exists.
+    pub fn pose_at(&self, name: &str, time: &Time) -> Result<Option<Pose>> {
+        let key = |t: &Time| (t.sec, t.nanosec);
+        match self.markers.lock().unwrap().get(name) {
+            Some(state) => Ok(state
+                .pose_history
+                .iter()
+                .rev()
+                .find(|(stamp, _)| key(stamp) <= key(time))
+                .map(|(_, pose)| pose.clone())),
+            None => Err(TeachingMarkerError::MarkerNotFound(name.to_string())),
+        }
+    }
+
+    /// Animates a marker through its recorded pose history.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the marker.
+    /// * `speed` - The playback speed, `1.0` replays in real time and `2.0` twice as fast.
+    ///
+    /// # Remarks
+    ///
+    /// The replay runs in the background, moving the marker and publishing its transforms.
+    /// Pauses longer than a second are shortened to one second. The replay stops if the
+    /// marker is removed and ends at the newest recorded pose.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::MarkerNotFound` if no marker with the given name exists.
+    pub fn replay(&self, name: &str, speed: f64) -> Result<()> {
+        let history = self.history(name)?;
+        if history.is_empty() {
+            return Ok(());
+        }
+        let speed = if speed > 0.0 { speed } else { 1.0 };
+        let seconds = |t: &Time| t.sec as f64 + t.nanosec as f64 * 1e-9;
+
+        let server = self.handle();
+        let name = name.to_string();
+        self.executor.spawn(async move {
+            if let Some(state) = server.markers.lock().unwrap().get_mut(&name) {
+                state.replaying = true;
+            }
+            let mut previous: Option<f64> = None;
+            for (i, (stamp, pose)) in history.iter().enumerate() {
+                if let Some(previous) = previous {
+                    let gap = ((seconds(stamp) - previous) / speed).max(0.0);
+                    server
+                        .executor
+                        .sleep(Duration::from_secs_f64(gap).min(MAX_REPLAY_GAP))
+                        .await;
+                }
+                previous = Some(seconds(stamp));
+                let last = i + 1 == history.len();
+                match server.move_marker(&name, pose, last) {
+                    Ok(()) => (),
+                    Err(TeachingMarkerError::MarkerNotFound(_)) => return,
+                    Err(e) => {
+                        r2r::log_error!(NODE_ID, "Failed to replay the pose of '{}' with: '{}'.", name, e);
+                    }
+                }
+            }
+            if let Some(state) = server.markers.lock().unwrap().get_mut(&name) {
+                state.replaying = false;
+            }
+        });
+        Ok(())
+    }
+
+    /// Moves a marker back to where it was before its last movement.
+    ///
+    /// # Returns
+    ///
+    /// `false` if there was nothing to undo.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the marker doesn't exist or the transform can't be published.
+    pub fn undo(&self, name: &str) -> Result<bool> {
+        let pose = match self.markers.lock().unwrap().get_mut(name) {
+            Some(state) => state.undo.undo(),
+            None => return Err(TeachingMarkerError::MarkerNotFound(name.to_string())),
+        };
+        match pose {
+            Some(pose) => self.move_marker(name, &pose, true).map(|()| true),
+            None => Ok(false),
+        }
+    }
+
+    /// Repeats a movement reverted with `undo`.
+    ///
+    /// # Returns
+    ///
+    /// `false` if there was nothing to redo.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the marker doesn't exist or the transform can't be published.
+    pub fn redo(&self, name: &str) -> Result<bool> {
+        let pose = match self.markers.lock().unwrap().get_mut(name) {
+            Some(state) => state.undo.redo(),
+            None => return Err(TeachingMarkerError::MarkerNotFound(name.to_string())),
+        };
+        match pose {
+            Some(pose) => self.move_marker(name, &pose, true).map(|()| true),
+            None => Ok(false),
+        }
+    }
+
+    /// Sets whether a marker has "Undo" and "Redo" entries in its context menu.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the marker.
+    /// * `enabled` - Whether to show the entries, they are added after the entries set with `set_menu`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::MarkerNotFound` if no marker with the given name exists.
+    pub fn set_undo_menu(&self, name: &str, enabled: bool) -> Result<()> {
+        match self.markers.lock().unwrap().get_mut(name) {
+            Some(state) => state.undo_menu = enabled,
+            None => return Err(TeachingMarkerError::MarkerNotFound(name.to_string())),
+        }
+        self.refresh_marker(name)
+    }
+
+    /// Moves a marker to where a TF frame currently is, e.g. the tool flange of the robot.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the marker.
+    /// * `target_frame` - The frame to move to, looked up relative to the marker's parent frame.
+    ///
+    /// # Remarks
+    ///
+    /// Requires a transform lookup, see `listen_tf`. The move can be undone with `undo`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the marker doesn't exist, the target frame can't be looked up or
+    /// the transform can't be published.
+    pub fn align_to_frame(&self, name: &str, target_frame: &str) -> Result<()> {
+        let frame = match self.markers.lock().unwrap().get(name) {
+            Some(state) => state.config.frame.clone(),
+            None => return Err(TeachingMarkerError::MarkerNotFound(name.to_string())),
+        };
+        let lookup = self.transform_lookup.lock().unwrap().clone();
+        let pose = lookup
+            .and_then(|lookup| lookup.lookup_transform(&frame, target_frame))
+            .map(|transform| transform_to_pose(&transform))
+            .ok_or_else(|| TeachingMarkerError::TransformUnavailable(frame, target_frame.to_string()))?;
+        self.set_pose(name, &pose)
+    }
+
+    /// Moves a marker onto the nearest candidate frame within the snapping radius, if any.
+    fn snap_to_nearest_frame(&self, name: &str, snap: &FrameSnap) -> Result<()> {
+        let (frame, position) = match self.markers.lock().unwrap().get(name) {
+            Some(state) => (state.config.frame.clone(), state.pose.position.clone()),
+            None => return Err(TeachingMarkerError::MarkerNotFound(name.to_string())),
+        };
+        let lookup = match self.transform_lookup.lock().unwrap().clone() {
+            Some(lookup) => lookup,
+            None => return Ok(()),
+        };
+        let nearest = snap
+            .frames
+            .iter()
+            .filter_map(|candidate| lookup.lookup_transform(&frame, candidate))
+            .map(|transform| {
+                let t = &transform.translation;
+                let (dx, dy, dz) = (t.x - position.x, t.y - position.y, t.z - position.z);
+                ((dx * dx + dy * dy + dz * dz).sqrt(), transform)
+            })
+            .filter(|(distance, _)| *distance <= snap.radius)
+            .min_by(|a, b| a.0.total_cmp(&b.0));
+        match nearest {
+            Some((_, transform)) => self.set_pose(name, &transform_to_pose(&transform)),
+            None => Ok(()),
+        }
+    }
+
+    /// Moves a marker by an offset expressed in its own frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the marker.
+    /// * `offset` - The motion relative to the current pose, e.g. a translation of 1 mm along X.
+    ///
+    /// # Remarks
+    ///
+    /// The nudge can be undone with `undo`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the marker doesn't exist or the transform can't be published.
+    pub fn nudge(&self, name: &str, offset: &Pose) -> Result<()> {
+        let pose = match self.markers.lock().unwrap().get(name) {
+            Some(state) => transform_to_pose(&compose(
+                &pose_to_transform(&state.pose),
+                &pose_to_transform(offset),
+            )),
+            None => return Err(TeachingMarkerError::MarkerNotFound(name.to_string())),
+        };
+        self.set_pose(name, &pose)
+    }
+
+    /// Returns the entries of the nudge menu with the axes they move and their offsets.
+    fn nudge_offsets(translation_step: f64, rotation_step: f64) -> Vec<(String, AxisMask, Pose)> {
+        let mut offsets = vec![];
+        for (axis, label) in [(Axis::X, "X"), (Axis::Y, "Y"), (Axis::Z, "Z")] {
+            for sign in [1.0, -1.0] {
+                let step = sign * translation_step;
+                let mut offset = identity_pose();
+                match axis {
+                    Axis::X => offset.position.x = step,
+                    Axis::Y => offset.position.y = step,
+                    Axis::Z => offset.position.z = step,
+                }
+                let title = format!("{}{} {:.1} mm", if sign > 0.0 { "+" } else { "-" }, label, translation_step * 1000.0);
+                offsets.push((title, AxisMask::moving(axis), offset));
+            }
+        }
+        for (axis, label) in [(Axis::X, "Roll"), (Axis::Y, "Pitch"), (Axis::Z, "Yaw")] {
+            for sign in [1.0, -1.0] {
+                let step = sign * rotation_step;
+                let mut offset = identity_pose();
+                offset.orientation = match axis {
+                    Axis::X => rpy_to_quaternion(step, 0.0, 0.0),
+                    Axis::Y => rpy_to_quaternion(0.0, step, 0.0),
+                    Axis::Z => rpy_to_quaternion(0.0, 0.0, step),
+                };
+                let title = format!("{}{} {:.1}°", if sign > 0.0 { "+" } else { "-" }, label, rotation_step.to_degrees());
+                offsets.push((title, AxisMask::rotating(axis), offset));
+            }
+        }
+        offsets
+    }
+
+    /// Moves a marker back to the pose it was inserted with.
+    ///
+    /// # Remarks
+    ///
+    /// The reset can be undone with `undo`. A marker reparented with `set_parent_keep_world`
+    /// is reset to its pose at the time, since its spawn pose was relative to the old parent.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the marker doesn't exist or the transform can't be published.
+    pub fn reset(&self, name: &str) -> Result<()> {
+        let pose = match self.markers.lock().unwrap().get(name) {
+            Some(state) => state.spawn_pose.clone(),
+            None => return Err(TeachingMarkerError::MarkerNotFound(name.to_string())),
+        };
+        self.set_pose(name, &pose)
+    }
+
+    /// Sets whether a marker has a "Reset" entry in its context menu, moving it back to its spawn pose.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the marker.
+    /// * `enabled` - Whether to show the entry, it is added after the entries set with `set_menu`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::MarkerNotFound` if no marker with the given name exists.
+    pub fn set_reset_menu(&self, name: &str, enabled: bool) -> Result<()> {
+        match self.markers.lock().unwrap().get_mut(name) {
+            Some(state) => state.reset_menu = enabled,
+            None => return Err(TeachingMarkerError::MarkerNotFound(name.to_string())),
+        }
+        self.refresh_marker(name)
+    }
+
+    /// Returns the current pose of a marker in its parent frame.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::MarkerNotFound` if no marker with the given name exists.
+    pub fn pose(&self, name: &str) -> Result<PoseStamped> {
+        match self.markers.lock().unwrap().get(name) {
+            Some(state) => Ok(PoseStamped {
+                header: Header {
+                    frame_id: state.config.frame.clone(),
+                    ..Header::default()
+                },
+                pose: state.pose.clone(),
+            }),
+            None => Err(TeachingMarkerError::MarkerNotFound(name.to_string())),
+        }
+    }
+
+    /// Publishes the poses of all markers in a common frame as a `PoseArray` on `<namespace>/poses`.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - The frame the poses are expressed in.
+    /// * `period` - How often to publish, or `None` to publish every time a marker moves.
+    ///
+    /// # Remarks
+    ///
+    /// The poses are ordered by marker name. Markers in other frames are converted with the
+    /// transform lookup, see `listen_tf`, and left out while their frame can't be looked up.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the publisher can't be created.
+    pub fn publish_pose_array(&self, frame: &str, period: Option<Duration>) -> Result<()> {
+        let publisher = self.node.lock().unwrap().create_publisher::<PoseArray>(
+            &format!("{}/poses", self.namespace),
+            QosProfile::default(),
+        )?;
+
+        let server = self.handle();
+        let frame = frame.to_string();
+        let mut changes = self.pose_changes.subscribe();
+        self.executor.spawn(async move {
+            loop {
+                match period {
+                    Some(period) => server.executor.sleep(period).await,
+                    None => {
+                        if changes.changed().await.is_err() {
+                            return;
+                        }
+                    }
+                }
+                if !server.is_active() {
+                    continue;
+                }
+                let result = server
+                    .pose_array(&frame)
+                    .and_then(|poses| publisher.publish(&poses).map_err(TeachingMarkerError::from));
+                if let Err(e) = result {
+                    r2r::log_error!(NODE_ID, "Failed to publish the marker poses with: '{}'.", e);
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Collects the poses of all markers in a common frame, ordered by marker name.
+    fn pose_array(&self, frame: &str) -> Result<PoseArray> {
+        let mut poses: Vec<(String, String, Pose)> = self
+            .markers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, state)| (name.clone(), state.config.frame.clone(), state.pose.clone()))
+            .collect();
+        poses.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let poses = poses
+            .into_iter()
+            .filter_map(|(_, parent, pose)| self.pose_in_frame(frame, &parent, &pose))
+            .collect();
+
+        Ok(PoseArray {
+            header: Header {
+                stamp: Self::now()?,
+                frame_id: frame.to_string(),
+            },
+            poses,
+        })
+    }
+
+    /// Expresses a pose given relative to `parent` in `frame`.
+    ///
+    /// # Returns
+    ///
+    /// The converted pose, or `None` if the frames differ and the transform can't be looked up.
+    fn pose_in_frame(&self, frame: &str, parent: &str, pose: &Pose) -> Option<Pose> {
+        if parent == frame {
+            return Some(pose.clone());
+        }
+        let lookup = self.transform_lookup.lock().unwrap().clone()?;
+        let frame_to_parent = lookup.lookup_transform(frame, parent)?;
+        Some(transform_to_pose(&compose(
+            &frame_to_parent,
+            &pose_to_transform(pose),
+        )))
+    }
+
+    /// Makes a marker follow the poses published on a `PoseStamped` topic.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the marker.
+    /// * `topic` - The topic to subscribe to. Poses must be expressed in the marker's frame.
+    /// * `policy` - Decides who wins when the user drags the marker while poses arrive.
+    ///
+    /// # Remarks
+    ///
+    /// The user can still grab and adjust the marker in RViz, the next received pose
+    /// moves it again. Following stops when the marker is removed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the marker doesn't exist or the subscription can't be created.
+    pub fn follow_topic(&self, name: &str, topic: &str, policy: FollowPolicy) -> Result<()> {
+        if !self.markers.lock().unwrap().contains_key(name) {
+            return Err(TeachingMarkerError::MarkerNotFound(name.to_string()));
+        }
+
+        let mut subscriber = self
+            .node
+            .lock()
+            .unwrap()
+            .subscribe::<PoseStamped>(topic, QosProfile::default())?;
+
+        let server = self.handle();
+        let name = name.to_string();
+        self.executor.spawn(async move {
+            while let Some(pose_stamped) = subscriber.next().await {
+                match server.apply_external_pose(&name, pose_stamped, policy) {
+                    Ok(()) => (),
+                    Err(TeachingMarkerError::MarkerNotFound(_)) => break,
+                    Err(e) => {
+                        r2r::log_error!(
+                            NODE_ID,
+                            "Failed to follow the pose of '{}' with: '{}'.",
+                            name,
+                            e
+                        );
+                    }
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Moves a marker to a pose received from a followed topic.
+    fn apply_external_pose(
+        &self,
+        name: &str,
+        pose_stamped: PoseStamped,
+        policy: FollowPolicy,
+    ) -> Result<()> {
+        {
+            let markers = self.markers.lock().unwrap();
+            let state = markers
+                .get(name)
+                .ok_or_else(|| TeachingMarkerError::MarkerNotFound(name.to_string()))?;
+            if policy == FollowPolicy::UserWins && state.dragging {
+                return Ok(());
+            }
+            let pose_frame = &pose_stamped.header.frame_id;
+            if !pose_frame.is_empty() && *pose_frame != state.config.frame {
+                r2r::log_warn!(
+                    NODE_ID,
+                    "Ignoring followed pose for '{}' in frame '{}', expected '{}'.",
+                    name,
+                    pose_frame,
+                    state.config.frame
+                );
+                return Ok(());
+            }
+        }
+
+        self.move_marker(name, &pose_stamped.pose, true)
+    }
+
+    /// Moves a marker programmatically, updating RViz and publishing its transform.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the marker.
+    /// * `pose` - The new pose relative to the marker's parent frame, passed through the
+    ///   marker's constraints.
+    /// * `latch` - Whether the pose is final, as opposed to an intermediate pose during a drag.
+    fn move_marker(&self, name: &str, pose: &Pose, latch: bool) -> Result<()> {
+        let pose = sanitize_pose(pose).ok_or_else(|| TeachingMarkerError::InvalidPose(name.to_string()))?;
+        let (pose, frame, hidden) = {
+            let mut markers = self.markers.lock().unwrap();
+            let state = markers
+                .get_mut(name)
+                .ok_or_else(|| TeachingMarkerError::MarkerNotFound(name.to_string()))?;
+            state.pose = state.config.constraints.apply(&pose);
+            if latch {
+                state.undo.reset(&state.pose);
+            }
+            (state.pose.clone(), state.config.frame.clone(), state.hidden)
+        };
+
+        // Hidden markers get their pose when they are shown again
+        if !hidden {
+            self.interactive_marker_server.set_pose(name, pose.clone(), None);
+            self.interactive_marker_server.apply_changes();
+        }
+
+        self.send_tf(TfUpdate {
+            name: name.to_string(),
+            data: Self::make_tf_message(name, &frame, &pose)?,
+            latch,
+            received: None,
+        })?;
+        self.update_mirrors(name, latch);
+        if latch {
+            self.emit_pose(name, |marker, pose| TeachingEvent::Moved { marker, pose });
+        }
+        Ok(())
+    }
+
+    /// Sends a transform update to the TF publishing thread.
+    ///
+    /// Updates are dropped while the server is inactive, `activate` publishes the latest poses.
+    fn send_tf(&self, update: TfUpdate) -> Result<()> {
+        if !self.is_active() {
+            return Ok(());
+        }
+        let update = match self.hold_tf(update) {
+            Some(update) => update,
+            None => return Ok(()),
+        };
+        let name = update.name.clone();
+        if self.tf_channel.send(update)? {
+            if let Some(state) = self.markers.lock().unwrap().get_mut(&name) {
+                state.dropped_updates += 1;
+            }
+        }
+        Ok(())
+    }
 }
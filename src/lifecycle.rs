@@ -0,0 +1,112 @@
+use std::sync::atomic::Ordering;
+
+use crate::tf::TfUpdate;
+use crate::{Result, TeachingMarker, TeachingMarkerServer};
+
+impl TeachingMarkerServer {
+    /// Brings the server up dormant and inserts its markers without publishing anything.
+    ///
+    /// # Arguments
+    ///
+    /// * `markers` - The markers to insert, see `insert_many`.
+    ///
+    /// # Remarks
+    ///
+    /// Deactivates the server as with `deactivate`, so the markers only appear in RViz and TF
+    /// once `activate` is called. Markers inserted later while inactive stay dormant as well.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a marker can't be inserted.
+    pub fn configure(&self, markers: Vec<TeachingMarker>) -> Result<()> {
+        self.deactivate();
+        self.insert_many(markers)
+    }
+
+    /// Starts publishing transforms and serving the interaction in RViz.
+    ///
+    /// # Remarks
+    ///
+    /// The markers are rebuilt in RViz and the current pose of every marker is published, including
+    /// the moves made while inactive. Servers are active from the start unless `configure` or
+    /// `deactivate` is called. Calling it on an active server has no effect.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a marker can't be rebuilt or its transform can't be published.
+    pub fn activate(&self) -> Result<()> {
+        if self.active.swap(true, Ordering::SeqCst) {
+            return Ok(());
+        }
+        let markers: Vec<_> = self
+            .markers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, state)| {
+                (
+                    name.clone(),
+                    state.config.frame.clone(),
+                    state.pose.clone(),
+                    state.config.visual.clone().filter(|_| !state.hidden),
+                    state.config.has_overlays() && !state.hidden,
+                    state.tf_suspended,
+                )
+            })
+            .collect();
+        for (name, _, _, visual, _, _) in &markers {
+            self.stage_marker(name)?;
+            if let Some(visual) = visual {
+                self.regular_marker_server.insert(name, visual.clone());
+            }
+        }
+        self.interactive_marker_server.apply_changes();
+        self.regular_marker_server.apply_changes();
+
+        for (name, frame, pose, _, overlays, tf_suspended) in markers {
+            if overlays {
+                self.draw_overlays(&name);
+            }
+            if !tf_suspended {
+                self.send_tf(TfUpdate {
+                    name: name.clone(),
+                    data: Self::make_tf_message(&name, &frame, &pose)?,
+                    latch: true,
+                    received: None,
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Freezes the server: the markers disappear from RViz and no transforms are published.
+    ///
+    /// # Remarks
+    ///
+    /// The markers keep their poses and configuration and can still be inserted, moved and
+    /// removed programmatically, the changes show once the server is activated again.
+    /// Transforms already latched on `tf_static` stay known to late subscribers. Calling it on
+    /// an inactive server has no effect.
+    pub fn deactivate(&self) {
+        if !self.is_active() {
+            return;
+        }
+        let names: Vec<String> = self.markers.lock().unwrap().keys().cloned().collect();
+        for name in &names {
+            self.interactive_marker_server.erase(name);
+            self.regular_marker_server.erase(name);
+            self.erase_overlays(name);
+        }
+        self.interactive_marker_server.apply_changes();
+        self.regular_marker_server.apply_changes();
+        // Pending changes of the deferred mode would be dropped as well
+        self.commit();
+        // Changes to RViz and TF are dropped from here on
+        self.active.store(false, Ordering::SeqCst);
+    }
+
+    /// Returns whether the server publishes transforms and serves the interaction in RViz.
+    pub fn is_active(&self) -> bool {
+        self.active.load(Ordering::SeqCst)
+    }
+}
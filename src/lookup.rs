@@ -0,0 +1,174 @@
+use r2r::geometry_msgs::msg::{Transform, TransformStamped};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::math::{compose, identity_transform, inverse};
+
+/// Maximum number of frames walked up the tree, guarding against cycles.
+const MAX_DEPTH: usize = 256;
+
+/// A source of transforms between frames, e.g. a TF buffer.
+///
+/// Implement this to let the server resolve poses against your own transform buffer,
+/// or use `TfBuffer` which is filled from `tf` and `tf_static` by `TeachingMarkerServer::listen_tf`.
+pub trait TransformLookup: Send + Sync {
+    /// Looks up the transform of `child` relative to `parent`.
+    ///
+    /// # Returns
+    ///
+    /// The newest known transform, or `None` if the frames aren't connected.
+    fn lookup_transform(&self, parent: &str, child: &str) -> Option<Transform>;
+
+    /// Returns `true` if the frame is known.
+    ///
+    /// The default implementation looks up the frame relative to itself, override it if your
+    /// buffer answers that lookup for unknown frames as well.
+    fn has_frame(&self, frame: &str) -> bool {
+        self.lookup_transform(frame, frame).is_some()
+    }
+}
+
+#[derive(Clone, Default)]
+/// A minimal TF buffer keeping the newest transform of each frame to its parent.
+///
+/// No history is kept, lookups always use the latest known transforms.
+pub struct TfBuffer {
+    frames: Arc<Mutex<HashMap<String, (String, Transform)>>>,
+}
+
+impl TfBuffer {
+    /// Creates an empty buffer.
+    pub fn new() -> Self {
+        TfBuffer::default()
+    }
+
+    /// Stores a transform, replacing the previous transform of its child frame.
+    pub fn insert(&self, transform: &TransformStamped) {
+        self.frames.lock().unwrap().insert(
+            transform.child_frame_id.clone(),
+            (transform.header.frame_id.clone(), transform.transform.clone()),
+        );
+    }
+
+    /// Walks from a frame up to the root of its tree.
+    ///
+    /// # Returns
+    ///
+    /// The root frame and the transform of `frame` relative to it, or `None` on a cycle.
+    fn to_root(
+        frames: &HashMap<String, (String, Transform)>,
+        frame: &str,
+    ) -> Option<(String, Transform)> {
+        let mut current = frame.to_string();
+        let mut transform = identity_transform();
+        for _ in 0..MAX_DEPTH {
+            match frames.get(&current) {
+                Some((parent, parent_to_current)) => {
+                    transform = compose(parent_to_current, &transform);
+                    current = parent.clone();
+                }
+                None => return Some((current, transform)),
+            }
+        }
+        None
+    }
+}
+
+impl TransformLookup for TfBuffer {
+    fn lookup_transform(&self, parent: &str, child: &str) -> Option<Transform> {
+        let frames = self.frames.lock().unwrap();
+        let (child_root, root_to_child) = Self::to_root(&frames, child)?;
+        let (parent_root, root_to_parent) = Self::to_root(&frames, parent)?;
+        if child_root != parent_root {
+            return None;
+        }
+        Some(compose(&inverse(&root_to_parent), &root_to_child))
+    }
+
+    fn has_frame(&self, frame: &str) -> bool {
+        let frames = self.frames.lock().unwrap();
+        frames.contains_key(frame) || frames.values().any(|(parent, _)| parent == frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::rpy_to_quaternion;
+    use crate::test_util::assert_vector;
+    use r2r::geometry_msgs::msg::Vector3;
+    use std::f64::consts::FRAC_PI_2;
+
+    fn transform(parent: &str, child: &str, x: f64, y: f64, yaw: f64) -> TransformStamped {
+        let mut stamped = TransformStamped {
+            child_frame_id: child.to_string(),
+            transform: Transform {
+                translation: Vector3 { x, y, z: 0.0 },
+                rotation: rpy_to_quaternion(0.0, 0.0, yaw),
+            },
+            ..TransformStamped::default()
+        };
+        stamped.header.frame_id = parent.to_string();
+        stamped
+    }
+
+    #[test]
+    fn looks_up_a_chain_of_frames() {
+        let buffer = TfBuffer::new();
+        buffer.insert(&transform("world", "base", 1.0, 0.0, FRAC_PI_2));
+        buffer.insert(&transform("base", "tool", 1.0, 0.0, 0.0));
+        let world_to_tool = buffer.lookup_transform("world", "tool").unwrap();
+        assert_vector(&world_to_tool.translation, 1.0, 1.0, 0.0);
+    }
+
+    #[test]
+    fn looks_up_across_branches() {
+        let buffer = TfBuffer::new();
+        buffer.insert(&transform("world", "a", 1.0, 0.0, 0.0));
+        buffer.insert(&transform("world", "b", 0.0, 2.0, 0.0));
+        let a_to_b = buffer.lookup_transform("a", "b").unwrap();
+        assert_vector(&a_to_b.translation, -1.0, 2.0, 0.0);
+    }
+
+    #[test]
+    fn looks_up_the_inverse() {
+        let buffer = TfBuffer::new();
+        buffer.insert(&transform("world", "base", 1.0, 0.0, FRAC_PI_2));
+        let base_to_world = buffer.lookup_transform("base", "world").unwrap();
+        assert_vector(&base_to_world.translation, 0.0, 1.0, 0.0);
+    }
+
+    #[test]
+    fn replaces_the_transform_of_a_frame() {
+        let buffer = TfBuffer::new();
+        buffer.insert(&transform("world", "base", 1.0, 0.0, 0.0));
+        buffer.insert(&transform("world", "base", 3.0, 0.0, 0.0));
+        let world_to_base = buffer.lookup_transform("world", "base").unwrap();
+        assert_vector(&world_to_base.translation, 3.0, 0.0, 0.0);
+    }
+
+    #[test]
+    fn disconnected_frames_have_no_transform() {
+        let buffer = TfBuffer::new();
+        buffer.insert(&transform("world", "a", 1.0, 0.0, 0.0));
+        buffer.insert(&transform("map", "b", 1.0, 0.0, 0.0));
+        assert!(buffer.lookup_transform("a", "b").is_none());
+    }
+
+    #[test]
+    fn cycles_have_no_transform() {
+        let buffer = TfBuffer::new();
+        buffer.insert(&transform("a", "b", 1.0, 0.0, 0.0));
+        buffer.insert(&transform("b", "a", 1.0, 0.0, 0.0));
+        assert!(buffer.lookup_transform("a", "b").is_none());
+    }
+
+    #[test]
+    fn knows_parents_and_children() {
+        let buffer = TfBuffer::new();
+        buffer.insert(&transform("world", "base", 1.0, 0.0, 0.0));
+        assert!(buffer.has_frame("world"));
+        assert!(buffer.has_frame("base"));
+        assert!(!buffer.has_frame("tool"));
+    }
+}
@@ -0,0 +1,563 @@
+use r2r::geometry_msgs::msg::{Point, Pose, Vector3};
+use r2r::visualization_msgs::msg::{InteractiveMarkerFeedback, Marker};
+use r2r::QosProfile;
+use std::ops::BitOr;
+use std::time::Duration;
+
+use crate::{
+    AxisMask, ControlOrientation, ControlProfile, ExtraControl, HalfSpace, PoseConstraints, TfOutput, Workspace,
+    DEFAULT_UNDO_DEPTH,
+};
+
+/// Default frame in which teaching markers are spawned.
+pub const DEFAULT_FRAME: &str = "world";
+
+/// Default scale of the interactive marker controls.
+pub const DEFAULT_SCALE: f32 = 0.3;
+
+/// Default number of published poses recorded per marker.
+pub const DEFAULT_HISTORY_DEPTH: usize = 1000;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+/// Enum representing where a teaching marker is initially placed.
+pub enum SpawnAt {
+    /// At the origin of the parent frame.
+    #[default]
+    Origin,
+    /// At a pose relative to the parent frame.
+    Pose(Pose),
+    /// At the current location of a frame, looked up from TF when the marker is inserted.
+    ///
+    /// Requires a transform lookup, see `TeachingMarkerServer::listen_tf`.
+    Frame(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// A set of feedback event types of an interactive marker, used to select which ones publish transforms.
+pub struct FeedbackEvents(u8);
+
+impl FeedbackEvents {
+    /// No events.
+    pub const NONE: FeedbackEvents = FeedbackEvents(0);
+    /// A pose update while the marker is dragged or moved.
+    pub const POSE_UPDATE: FeedbackEvents = FeedbackEvents(1 << 0);
+    /// The user grabs the marker.
+    pub const MOUSE_DOWN: FeedbackEvents = FeedbackEvents(1 << 1);
+    /// The user releases the marker, carrying the final pose of a drag.
+    pub const MOUSE_UP: FeedbackEvents = FeedbackEvents(1 << 2);
+    /// A click on a button control.
+    pub const BUTTON_CLICK: FeedbackEvents = FeedbackEvents(1 << 3);
+    /// A selection in the context menu.
+    pub const MENU_SELECT: FeedbackEvents = FeedbackEvents(1 << 4);
+    /// A periodic keep-alive sent by RViz.
+    pub const KEEP_ALIVE: FeedbackEvents = FeedbackEvents(1 << 5);
+    /// All events.
+    pub const ALL: FeedbackEvents = FeedbackEvents(0b111111);
+    /// The events moving the marker, `POSE_UPDATE` and `MOUSE_UP`, the default of `tf_on_events`.
+    ///
+    /// Keep-alives, clicks and menu selections carry the unchanged pose and would republish it
+    /// as a final pose every time.
+    pub const POSE_CHANGES: FeedbackEvents = FeedbackEvents(0b000101);
+
+    /// Returns the set containing the event type of a feedback, empty for unknown types.
+    pub fn of(feedback: &InteractiveMarkerFeedback) -> FeedbackEvents {
+        match feedback.event_type {
+            t if t == InteractiveMarkerFeedback::POSE_UPDATE as u8 => FeedbackEvents::POSE_UPDATE,
+            t if t == InteractiveMarkerFeedback::MOUSE_DOWN as u8 => FeedbackEvents::MOUSE_DOWN,
+            t if t == InteractiveMarkerFeedback::MOUSE_UP as u8 => FeedbackEvents::MOUSE_UP,
+            t if t == InteractiveMarkerFeedback::BUTTON_CLICK as u8 => FeedbackEvents::BUTTON_CLICK,
+            t if t == InteractiveMarkerFeedback::MENU_SELECT as u8 => FeedbackEvents::MENU_SELECT,
+            t if t == InteractiveMarkerFeedback::KEEP_ALIVE as u8 => FeedbackEvents::KEEP_ALIVE,
+            _ => FeedbackEvents::NONE,
+        }
+    }
+
+    /// Returns `true` if any event of `other` is in the set.
+    pub fn intersects(self, other: FeedbackEvents) -> bool {
+        self.0 & other.0 != 0
+    }
+}
+
+impl Default for FeedbackEvents {
+    fn default() -> Self {
+        FeedbackEvents::POSE_CHANGES
+    }
+}
+
+impl BitOr for FeedbackEvents {
+    type Output = FeedbackEvents;
+
+    fn bitor(self, rhs: FeedbackEvents) -> FeedbackEvents {
+        FeedbackEvents(self.0 | rhs.0)
+    }
+}
+
+#[derive(Clone, Debug)]
+/// Frames a marker snaps to when it is released close to one of them.
+pub(crate) struct FrameSnap {
+    pub(crate) frames: Vec<String>,
+    pub(crate) radius: f64,
+}
+
+#[derive(Clone, Debug)]
+/// A description of a teaching marker to be inserted into the `TeachingMarkerServer`.
+///
+/// Use `TeachingMarker::builder` to construct one.
+pub struct TeachingMarker {
+    pub(crate) name: String,
+    pub(crate) frame: String,
+    pub(crate) spawn_at: SpawnAt,
+    pub(crate) scale: f32,
+    pub(crate) visual: Option<Marker>,
+    pub(crate) publish_pose: bool,
+    pub(crate) pose_covariance: Option<[f64; 36]>,
+    pub(crate) profile: ControlProfile,
+    pub(crate) extra_controls: Vec<ExtraControl>,
+    pub(crate) constraints: PoseConstraints,
+    pub(crate) warn_on_invalid_pose: bool,
+    pub(crate) tf_output: TfOutput,
+    pub(crate) tf_qos: Option<QosProfile>,
+    pub(crate) pose_qos: Option<QosProfile>,
+    pub(crate) max_rate: Option<f64>,
+    pub(crate) tf_events: FeedbackEvents,
+    pub(crate) deadband: Option<(f64, f64)>,
+    pub(crate) smoothing: Option<Duration>,
+    pub(crate) orientation_blend: Option<f64>,
+    pub(crate) wait_for_frame: Option<Duration>,
+    pub(crate) parent_marker: bool,
+    pub(crate) require_confirmation: bool,
+    pub(crate) undo_depth: usize,
+    pub(crate) history_depth: usize,
+    pub(crate) description: Option<String>,
+    pub(crate) pose_label: bool,
+    pub(crate) axis_triad: bool,
+    pub(crate) highlight: bool,
+    pub(crate) control_visuals: Vec<(AxisMask, Marker)>,
+    pub(crate) control_orientations: Vec<(AxisMask, ControlOrientation)>,
+    pub(crate) resize_handles: bool,
+    pub(crate) frame_snap: Option<FrameSnap>,
+    pub(crate) nudge_steps: Option<(f64, f64)>,
+}
+
+impl TeachingMarker {
+    /// Starts building a teaching marker with the given name.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the marker, also used as the child frame ID of the published transform.
+    pub fn builder(name: &str) -> TeachingMarkerBuilder {
+        TeachingMarkerBuilder {
+            marker: TeachingMarker {
+                name: name.to_string(),
+                frame: DEFAULT_FRAME.to_string(),
+                spawn_at: SpawnAt::Origin,
+                scale: DEFAULT_SCALE,
+                visual: None,
+                publish_pose: true,
+                pose_covariance: None,
+                profile: ControlProfile::default(),
+                extra_controls: vec![],
+                constraints: PoseConstraints::default(),
+                warn_on_invalid_pose: true,
+                tf_output: TfOutput::default(),
+                tf_qos: None,
+                pose_qos: None,
+                max_rate: None,
+                tf_events: FeedbackEvents::POSE_CHANGES,
+                deadband: None,
+                smoothing: None,
+                orientation_blend: None,
+                wait_for_frame: None,
+                parent_marker: false,
+                require_confirmation: false,
+                undo_depth: DEFAULT_UNDO_DEPTH,
+                history_depth: DEFAULT_HISTORY_DEPTH,
+                description: None,
+                pose_label: false,
+                axis_triad: false,
+                highlight: true,
+                control_visuals: vec![],
+                control_orientations: vec![],
+                resize_handles: false,
+                frame_snap: None,
+                nudge_steps: None,
+            },
+        }
+    }
+
+    /// The name of the marker.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The frame ID in which the marker is spawned.
+    pub fn frame(&self) -> &str {
+        &self.frame
+    }
+
+    /// Whether any regular markers are drawn around the marker that follow its pose.
+    pub(crate) fn has_overlays(&self) -> bool {
+        self.pose_label || self.axis_triad
+    }
+}
+
+/// A builder for `TeachingMarker`.
+pub struct TeachingMarkerBuilder {
+    marker: TeachingMarker,
+}
+
+impl TeachingMarkerBuilder {
+    /// Sets the frame ID where the marker is to be spawned.
+    pub fn frame(mut self, frame: &str) -> Self {
+        self.marker.frame = frame.to_string();
+        self
+    }
+
+    /// Spawns the marker relative to another teaching marker of the same server.
+    ///
+    /// Moving the parent drags this marker along through the TF chain, while its pose relative to
+    /// the parent stays editable. The parent must be inserted first.
+    pub fn relative_to(mut self, marker: &str) -> Self {
+        self.marker.frame = marker.to_string();
+        self.marker.parent_marker = true;
+        self
+    }
+
+    /// Sets the initial pose of the marker relative to its frame.
+    pub fn pose(mut self, pose: Pose) -> Self {
+        self.marker.spawn_at = SpawnAt::Pose(pose);
+        self
+    }
+
+    /// Sets where the marker is initially placed, e.g. `SpawnAt::Frame("gripper_tip".into())`
+    /// to spawn it where the tool currently is.
+    pub fn spawn_at(mut self, spawn_at: SpawnAt) -> Self {
+        self.marker.spawn_at = spawn_at;
+        self
+    }
+
+    /// Sets the scale of the interactive marker controls.
+    pub fn scale(mut self, scale: f32) -> Self {
+        self.marker.scale = scale;
+        self
+    }
+
+    /// Attaches a regular marker to be visualized inside the teaching controls.
+    ///
+    /// A `package://` mesh resource is resolved through the ament index when the marker is inserted.
+    pub fn with_visual(mut self, marker: Marker) -> Self {
+        self.marker.visual = Some(marker);
+        self
+    }
+
+    /// Sets whether the visual is brightened while the marker is dragged, showing which marker is being moved.
+    ///
+    /// Enabled by default.
+    pub fn highlight_on_drag(mut self, highlight: bool) -> Self {
+        self.marker.highlight = highlight;
+        self
+    }
+
+    /// Sets the text shown above the marker in RViz instead of its name.
+    pub fn description(mut self, description: &str) -> Self {
+        self.marker.description = Some(description.to_string());
+        self
+    }
+
+    /// Sets whether the marker pose is published as a `PoseStamped` on `<namespace>/<name>/pose`.
+    ///
+    /// Enabled by default.
+    pub fn publish_pose(mut self, publish_pose: bool) -> Self {
+        self.marker.publish_pose = publish_pose;
+        self
+    }
+
+    /// Also publishes the marker pose as a `PoseWithCovarianceStamped` on
+    /// `<namespace>/<name>/pose_with_covariance`, e.g. as a prior for an estimator.
+    ///
+    /// The covariance is the row-major 6x6 matrix over x, y, z and the rotations about them,
+    /// attached unchanged to every published pose. Disabled by default.
+    pub fn pose_covariance(mut self, covariance: [f64; 36]) -> Self {
+        self.marker.pose_covariance = Some(covariance);
+        self
+    }
+
+    /// Sets the set of controls the marker is created with.
+    ///
+    /// Defaults to `ControlProfile::Full6D`.
+    pub fn profile(mut self, profile: ControlProfile) -> Self {
+        self.marker.profile = profile;
+        self
+    }
+
+    /// Replaces the default RViz rings and arrows of controls with a custom visual, e.g. a gripper mesh.
+    ///
+    /// # Arguments
+    ///
+    /// * `axes` - The controls the visual is used for, e.g. `AxisMask::MOVE_Z` for the Z arrow
+    ///   or `AxisMask::ROTATE` for all rings. The first matching visual wins.
+    /// * `visual` - The marker drawn as the handle, its pose is relative to the control, whose
+    ///   X axis points along the axis of the control.
+    pub fn control_visual(mut self, axes: AxisMask, visual: Marker) -> Self {
+        self.marker.control_visuals.push((axes, visual));
+        self
+    }
+
+    /// Sets how the handles of controls are oriented, e.g. `ControlOrientation::Fixed` for
+    /// handles aligned with the parent frame instead of rotating with the marker.
+    ///
+    /// # Arguments
+    ///
+    /// * `axes` - The controls the mode is used for, e.g. `AxisMask::MOVE` for all arrows.
+    ///   The first matching mode wins.
+    /// * `orientation` - The orientation mode.
+    pub fn control_orientation(mut self, axes: AxisMask, orientation: ControlOrientation) -> Self {
+        self.marker.control_orientations.push((axes, orientation));
+        self
+    }
+
+    /// Adds a "Nudge" submenu moving the marker in small steps along and around its own axes,
+    /// for adjustments too fine to drag.
+    ///
+    /// # Arguments
+    ///
+    /// * `translation_step` - The step in meters, e.g. `0.001`.
+    /// * `rotation_step` - The step in radians, e.g. `1f64.to_radians()`.
+    pub fn nudge_menu(mut self, translation_step: f64, rotation_step: f64) -> Self {
+        self.marker.nudge_steps = Some((translation_step, rotation_step));
+        self
+    }
+
+    /// Adds two corner handles to grow and shrink the controls in RViz, for switching between
+    /// coarse and fine manipulation.
+    ///
+    /// Each click on the green handle grows the marker by a quarter, the red handle shrinks it.
+    /// The new scale is applied like `TeachingMarkerServer::set_scale`. Disabled by default.
+    pub fn resize_handles(mut self, enabled: bool) -> Self {
+        self.marker.resize_handles = enabled;
+        self
+    }
+
+    /// Adds a plane or free 3D control on top of the control profile.
+    pub fn with_control(mut self, control: ExtraControl) -> Self {
+        self.marker.extra_controls.push(control);
+        self
+    }
+
+    /// Snaps the marker translation to a grid with the given step in meters, e.g. `0.005`.
+    pub fn snap_translation(mut self, step: f64) -> Self {
+        self.marker.constraints.translation_step = Some(step);
+        self
+    }
+
+    /// Snaps the marker roll, pitch and yaw to multiples of the given step in radians,
+    /// e.g. `15f64.to_radians()`.
+    pub fn snap_rotation(mut self, step: f64) -> Self {
+        self.marker.constraints.rotation_step = Some(step);
+        self
+    }
+
+    /// Snaps the marker onto the nearest of a set of TF frames when it is released, e.g. known fixture positions.
+    ///
+    /// # Arguments
+    ///
+    /// * `frames` - The candidate frames.
+    /// * `radius` - The largest distance in meters from which the marker snaps, it stays where it
+    ///   was released if no frame is closer.
+    ///
+    /// Requires a transform lookup, see `TeachingMarkerServer::listen_tf`.
+    pub fn snap_to_frames(mut self, frames: &[&str], radius: f64) -> Self {
+        self.marker.frame_snap = Some(FrameSnap {
+            frames: frames.iter().map(|frame| frame.to_string()).collect(),
+            radius,
+        });
+        self
+    }
+
+    /// Makes the marker an SE(2) pose, e.g. a mobile robot goal or an AGV station.
+    ///
+    /// Uses `ControlProfile::Planar` with translation along X and Y and yaw only, and keeps the
+    /// marker gravity aligned. Combine with `fixed_height` to pin it to the floor.
+    pub fn planar(mut self) -> Self {
+        self.marker.profile = ControlProfile::Planar;
+        self.marker.constraints.gravity_aligned = true;
+        self
+    }
+
+    /// Pins the marker position to a height in meters along the Z axis of the parent frame.
+    ///
+    /// The height wins over the workspace and the half-spaces, which then only move the marker horizontally.
+    pub fn fixed_height(mut self, height: f64) -> Self {
+        self.marker.constraints.fixed_height = Some(height);
+        self
+    }
+
+    /// Keeps the Z axis of the marker aligned with the Z axis of the parent frame, allowing only yaw.
+    ///
+    /// Meant for place poses on horizontal surfaces. The roll and pitch rings are left out.
+    pub fn gravity_aligned(mut self, aligned: bool) -> Self {
+        self.marker.constraints.gravity_aligned = aligned;
+        self
+    }
+
+    /// Keeps the marker position inside an axis-aligned box in the parent frame.
+    pub fn workspace(mut self, min: Point, max: Point) -> Self {
+        self.marker.constraints.workspace = Some(Workspace { min, max });
+        self
+    }
+
+    /// Keeps the marker position in the half-space `normal · p >= offset` of the parent frame.
+    ///
+    /// Can be called multiple times, the half-spaces are enforced in order.
+    pub fn half_space(mut self, normal: Vector3, offset: f64) -> Self {
+        self.marker
+            .constraints
+            .half_spaces
+            .push(HalfSpace { normal, offset });
+        self
+    }
+
+    /// Sets all constraints applied to the poses received from RViz at once.
+    pub fn constraints(mut self, constraints: PoseConstraints) -> Self {
+        self.marker.constraints = constraints;
+        self
+    }
+
+    /// Sets whether a warning is logged when feedback with an invalid pose is dropped.
+    ///
+    /// Enabled by default.
+    pub fn warn_on_invalid_pose(mut self, warn: bool) -> Self {
+        self.marker.warn_on_invalid_pose = warn;
+        self
+    }
+
+    /// Sets where the marker transforms are published.
+    ///
+    /// Defaults to `TfOutput::Static`.
+    pub fn tf_output(mut self, output: TfOutput) -> Self {
+        self.marker.tf_output = output;
+        self
+    }
+
+    /// Sets the QoS the marker transforms are published with, instead of the one of its `TfOutput`.
+    ///
+    /// E.g. `QosProfile::default().best_effort()` for a marker dragged at a high rate, or a
+    /// reliable transient local profile with a deeper history for latching. The marker gets
+    /// publishers of its own if other markers use another QoS on the same topic.
+    pub fn tf_qos(mut self, qos: QosProfile) -> Self {
+        self.marker.tf_qos = Some(qos);
+        self
+    }
+
+    /// Sets the QoS of the `<namespace>/<name>/pose` topic, the default QoS otherwise.
+    pub fn pose_qos(mut self, qos: QosProfile) -> Self {
+        self.marker.pose_qos = Some(qos);
+        self
+    }
+
+    /// Limits the rate in Hz at which transforms are published while the marker is dragged.
+    ///
+    /// The final pose on release is always published.
+    pub fn max_rate(mut self, rate: f64) -> Self {
+        self.marker.max_rate = Some(rate);
+        self
+    }
+
+    /// Publishes a smoothed pose while the marker is dragged, for robots following it live.
+    ///
+    /// The transform follows the dragged pose as an exponential moving average that lags by
+    /// about `time_constant`, the orientation is interpolated with slerp. The final pose on
+    /// release is published as it is.
+    pub fn smoothing(mut self, time_constant: Duration) -> Self {
+        self.marker.smoothing = Some(time_constant);
+        self
+    }
+
+    /// Filters the orientation of a dragged marker with a fixed blending factor instead of the time constant.
+    ///
+    /// Each new orientation is blended in with slerp by `blend`, clamped to `[0, 1]`, where `1`
+    /// disables the orientation filter and smaller values smooth more. Can be combined with
+    /// `smoothing` or used alone, which leaves the position unfiltered.
+    pub fn orientation_smoothing(mut self, blend: f64) -> Self {
+        self.marker.orientation_blend = Some(blend);
+        self
+    }
+
+    /// Skips intermediate poses of a drag that barely differ from the last published pose.
+    ///
+    /// A pose is published once it has moved more than `translation` meters or rotated more
+    /// than `rotation` radians. Keeps mouse jitter out of bags and downstream caches, the final
+    /// pose on release is always published.
+    pub fn deadband(mut self, translation: f64, rotation: f64) -> Self {
+        self.marker.deadband = Some((translation, rotation));
+        self
+    }
+
+    /// Selects the feedback event types that publish the transform of the marker.
+    ///
+    /// Defaults to `FeedbackEvents::POSE_CHANGES`. E.g. `FeedbackEvents::MOUSE_UP` publishes only
+    /// the pose on release, without the intermediate poses of a drag. Without `MOUSE_UP` the pose
+    /// of a drag is never published as final. Poses set programmatically are always published.
+    pub fn tf_on_events(mut self, events: FeedbackEvents) -> Self {
+        self.marker.tf_events = events;
+        self
+    }
+
+    /// Waits, when inserting, until the parent frame and a `SpawnAt::Frame` are available in TF.
+    ///
+    /// Insertion fails with `TeachingMarkerError::FrameTimeout` if they don't appear within the timeout.
+    /// Requires a transform lookup, see `TeachingMarkerServer::listen_tf`. From async code insert
+    /// the marker with `TeachingMarkerServer::insert_marker_async`, the blocking insert functions
+    /// fail there with `TeachingMarkerError::BlockingWait` while a frame is missing.
+    pub fn wait_for_frame(mut self, timeout: Duration) -> Self {
+        self.marker.wait_for_frame = Some(timeout);
+        self
+    }
+
+    /// Sets whether poses have to be confirmed before they are published on the configured output.
+    ///
+    /// Until the operator selects "Confirm" in the context menu or `TeachingMarkerServer::confirm`
+    /// is called, the marker only publishes a candidate transform on `tf`. Moving the marker
+    /// makes it a candidate again. Disabled by default.
+    pub fn require_confirmation(mut self, require: bool) -> Self {
+        self.marker.require_confirmation = require;
+        self
+    }
+
+    /// Sets how many movements can be undone with `TeachingMarkerServer::undo`.
+    ///
+    /// Defaults to `DEFAULT_UNDO_DEPTH`, `0` disables the history.
+    pub fn undo_depth(mut self, depth: usize) -> Self {
+        self.marker.undo_depth = depth;
+        self
+    }
+
+    /// Sets how many published poses are recorded for `TeachingMarkerServer::history`.
+    ///
+    /// Defaults to `DEFAULT_HISTORY_DEPTH`, `0` disables recording.
+    pub fn history_depth(mut self, depth: usize) -> Self {
+        self.marker.history_depth = depth;
+        self
+    }
+
+    /// Sets whether the position and roll, pitch and yaw of the marker are shown as text above it in RViz.
+    ///
+    /// The text follows the marker while it is dragged. Disabled by default.
+    pub fn show_pose_label(mut self, show: bool) -> Self {
+        self.marker.pose_label = show;
+        self
+    }
+
+    /// Sets whether a small red, green and blue axis triad is drawn at the marker pose in RViz.
+    ///
+    /// Shows the orientation of the taught frame also when its controls are locked. Disabled by default.
+    pub fn show_axes(mut self, show: bool) -> Self {
+        self.marker.axis_triad = show;
+        self
+    }
+
+    /// Finishes building the teaching marker.
+    pub fn build(self) -> TeachingMarker {
+        self.marker
+    }
+}
@@ -0,0 +1,238 @@
+use r2r::geometry_msgs::msg::{Quaternion, Transform, Vector3};
+
+/// Converts a quaternion into roll, pitch and yaw angles in radians.
+///
+/// # Arguments
+///
+/// * `q` - A unit quaternion.
+///
+/// # Returns
+///
+/// The `(roll, pitch, yaw)` angles of the rotation, applied in the order X, Y, Z in fixed axes.
+pub(crate) fn quaternion_to_rpy(q: &Quaternion) -> (f64, f64, f64) {
+    let roll = (2.0 * (q.w * q.x + q.y * q.z)).atan2(1.0 - 2.0 * (q.x * q.x + q.y * q.y));
+    let sin_pitch = (2.0 * (q.w * q.y - q.z * q.x)).clamp(-1.0, 1.0);
+    let pitch = sin_pitch.asin();
+    let yaw = (2.0 * (q.w * q.z + q.x * q.y)).atan2(1.0 - 2.0 * (q.y * q.y + q.z * q.z));
+    (roll, pitch, yaw)
+}
+
+/// Converts roll, pitch and yaw angles in radians into a quaternion.
+///
+/// # Arguments
+///
+/// * `roll` - Rotation around X.
+/// * `pitch` - Rotation around Y.
+/// * `yaw` - Rotation around Z.
+pub(crate) fn rpy_to_quaternion(roll: f64, pitch: f64, yaw: f64) -> Quaternion {
+    let (sr, cr) = (roll * 0.5).sin_cos();
+    let (sp, cp) = (pitch * 0.5).sin_cos();
+    let (sy, cy) = (yaw * 0.5).sin_cos();
+    Quaternion {
+        x: sr * cp * cy - cr * sp * sy,
+        y: cr * sp * cy + sr * cp * sy,
+        z: cr * cp * sy - sr * sp * cy,
+        w: cr * cp * cy + sr * sp * sy,
+    }
+}
+
+/// Normalizes the quaternion in place.
+///
+/// # Arguments
+///
+/// * `quaternion` - A mutable reference to the quaternion to normalize.
+pub(crate) fn normalize_quaternion(quaternion: &mut Quaternion) {
+    let norm = quaternion.x * quaternion.x
+        + quaternion.y * quaternion.y
+        + quaternion.z * quaternion.z
+        + quaternion.w * quaternion.w;
+    let s = norm.powf(-0.5);
+    quaternion.x *= s;
+    quaternion.y *= s;
+    quaternion.z *= s;
+    quaternion.w *= s;
+}
+
+/// Multiplies two quaternions, `a * b` applies `b` first and then `a`.
+pub(crate) fn quaternion_multiply(a: &Quaternion, b: &Quaternion) -> Quaternion {
+    Quaternion {
+        x: a.w * b.x + a.x * b.w + a.y * b.z - a.z * b.y,
+        y: a.w * b.y - a.x * b.z + a.y * b.w + a.z * b.x,
+        z: a.w * b.z + a.x * b.y - a.y * b.x + a.z * b.w,
+        w: a.w * b.w - a.x * b.x - a.y * b.y - a.z * b.z,
+    }
+}
+
+/// Returns the conjugate of a quaternion, which is its inverse for unit quaternions.
+pub(crate) fn quaternion_conjugate(q: &Quaternion) -> Quaternion {
+    Quaternion {
+        x: -q.x,
+        y: -q.y,
+        z: -q.z,
+        w: q.w,
+    }
+}
+
+/// Returns the angle in radians of the rotation between two unit quaternions, in `[0, pi]`.
+pub(crate) fn rotation_angle(a: &Quaternion, b: &Quaternion) -> f64 {
+    let relative = quaternion_multiply(&quaternion_conjugate(a), b);
+    2.0 * relative.w.abs().clamp(0.0, 1.0).acos()
+}
+
+/// Interpolates along the shortest arc between two unit quaternions.
+///
+/// # Arguments
+///
+/// * `a` - The rotation at `t = 0`.
+/// * `b` - The rotation at `t = 1`.
+/// * `t` - The interpolation parameter, clamped to `[0, 1]`.
+///
+/// # Returns
+///
+/// A unit quaternion, normalized again to keep rounding errors from accumulating in filters.
+pub(crate) fn slerp(a: &Quaternion, b: &Quaternion, t: f64) -> Quaternion {
+    let t = t.clamp(0.0, 1.0);
+    // q and -q are the same rotation, take the shorter way
+    let mut dot = a.x * b.x + a.y * b.y + a.z * b.z + a.w * b.w;
+    let sign = if dot < 0.0 { -1.0 } else { 1.0 };
+    dot = (dot * sign).min(1.0);
+
+    // Nearly equal rotations, the linear interpolation is exact enough and avoids dividing by ~0
+    let (wa, wb) = if dot > 0.9995 {
+        (1.0 - t, t)
+    } else {
+        let theta = dot.acos();
+        let sin_theta = theta.sin();
+        (((1.0 - t) * theta).sin() / sin_theta, (t * theta).sin() / sin_theta)
+    };
+    let mut q = Quaternion {
+        x: wa * a.x + wb * sign * b.x,
+        y: wa * a.y + wb * sign * b.y,
+        z: wa * a.z + wb * sign * b.z,
+        w: wa * a.w + wb * sign * b.w,
+    };
+    normalize_quaternion(&mut q);
+    q
+}
+
+/// Rotates a vector by a unit quaternion.
+pub(crate) fn rotate_vector(q: &Quaternion, v: &Vector3) -> Vector3 {
+    let p = Quaternion {
+        x: v.x,
+        y: v.y,
+        z: v.z,
+        w: 0.0,
+    };
+    let r = quaternion_multiply(&quaternion_multiply(q, &p), &quaternion_conjugate(q));
+    Vector3 {
+        x: r.x,
+        y: r.y,
+        z: r.z,
+    }
+}
+
+/// Returns the identity transform.
+pub(crate) fn identity_transform() -> Transform {
+    Transform {
+        translation: Vector3 {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+        },
+        rotation: Quaternion {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+            w: 1.0,
+        },
+    }
+}
+
+/// Composes two transforms, `a * b` maps from the frame of `b` into the parent frame of `a`.
+pub(crate) fn compose(a: &Transform, b: &Transform) -> Transform {
+    let t = rotate_vector(&a.rotation, &b.translation);
+    Transform {
+        translation: Vector3 {
+            x: a.translation.x + t.x,
+            y: a.translation.y + t.y,
+            z: a.translation.z + t.z,
+        },
+        rotation: quaternion_multiply(&a.rotation, &b.rotation),
+    }
+}
+
+/// Inverts a transform with a unit quaternion rotation.
+pub(crate) fn inverse(t: &Transform) -> Transform {
+    let rotation = quaternion_conjugate(&t.rotation);
+    let translation = rotate_vector(&rotation, &t.translation);
+    Transform {
+        translation: Vector3 {
+            x: -translation.x,
+            y: -translation.y,
+            z: -translation.z,
+        },
+        rotation,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::assert_near;
+    use std::f64::consts::FRAC_PI_2;
+
+    fn assert_same_rotation(a: &Quaternion, b: &Quaternion) {
+        assert!(rotation_angle(a, b) < 1e-6, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn slerp_returns_the_ends() {
+        let a = rpy_to_quaternion(0.0, 0.0, 0.0);
+        let b = rpy_to_quaternion(0.0, 0.0, FRAC_PI_2);
+        assert_same_rotation(&slerp(&a, &b, 0.0), &a);
+        assert_same_rotation(&slerp(&a, &b, 1.0), &b);
+    }
+
+    #[test]
+    fn slerp_interpolates_the_angle() {
+        let a = rpy_to_quaternion(0.0, 0.0, 0.0);
+        let b = rpy_to_quaternion(0.0, 0.0, FRAC_PI_2);
+        let q = slerp(&a, &b, 0.25);
+        let (roll, pitch, yaw) = quaternion_to_rpy(&q);
+        assert_near(roll, 0.0);
+        assert_near(pitch, 0.0);
+        assert_near(yaw, FRAC_PI_2 * 0.25);
+    }
+
+    #[test]
+    fn slerp_takes_the_shorter_way() {
+        let a = rpy_to_quaternion(0.0, 0.0, 0.0);
+        let b = rpy_to_quaternion(0.0, 0.0, FRAC_PI_2);
+        let negated = Quaternion {
+            x: -b.x,
+            y: -b.y,
+            z: -b.z,
+            w: -b.w,
+        };
+        assert_same_rotation(&slerp(&a, &negated, 0.5), &slerp(&a, &b, 0.5));
+        assert_near(rotation_angle(&a, &slerp(&a, &negated, 0.5)), FRAC_PI_2 * 0.5);
+    }
+
+    #[test]
+    fn slerp_clamps_the_parameter_and_stays_normalized() {
+        let a = rpy_to_quaternion(0.3, -0.2, 0.1);
+        let b = rpy_to_quaternion(-0.1, 0.4, 2.0);
+        assert_same_rotation(&slerp(&a, &b, -1.0), &a);
+        assert_same_rotation(&slerp(&a, &b, 2.0), &b);
+        let q = slerp(&a, &b, 0.7);
+        assert_near(q.x * q.x + q.y * q.y + q.z * q.z + q.w * q.w, 1.0);
+    }
+
+    #[test]
+    fn slerp_of_nearly_equal_rotations() {
+        let a = rpy_to_quaternion(0.0, 0.0, 1.0);
+        let b = rpy_to_quaternion(0.0, 0.0, 1.0 + 1e-6);
+        let (_, _, yaw) = quaternion_to_rpy(&slerp(&a, &b, 0.5));
+        assert_near(yaw, 1.0 + 0.5e-6);
+    }
+}
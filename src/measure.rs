@@ -0,0 +1,181 @@
+use futures::StreamExt;
+use r2r::geometry_msgs::msg::Point;
+use r2r::std_msgs::msg::ColorRGBA;
+use r2r::visualization_msgs::msg::Marker;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::math::rotation_angle;
+use crate::{Result, TeachingMarkerServer};
+
+/// The color of the measurement line and text.
+const MEASURE_COLOR: ColorRGBA = ColorRGBA {
+    r: 0.0,
+    g: 1.0,
+    b: 1.0,
+    a: 1.0,
+};
+
+/// Height of the measurement text in meters.
+const MEASURE_TEXT_HEIGHT: f64 = 0.04;
+
+/// Returns a new name shared by the regular markers of a measurement.
+///
+/// The names are numbered, so marker names containing underscores can't make two measurements collide.
+fn measurement_name(name_a: &str, name_b: &str) -> String {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    format!("{name_a}_{name_b}_measure_{id}")
+}
+
+impl TeachingMarkerServer {
+    /// Shows the live distance between two markers in RViz.
+    ///
+    /// # Arguments
+    ///
+    /// * `name_a` - The name of the first marker.
+    /// * `name_b` - The name of the second marker.
+    /// * `rotation` - Whether to also show the angle of the rotation between the two markers.
+    ///
+    /// # Remarks
+    ///
+    /// A line is drawn between the markers with the distance written at its middle, updated
+    /// whenever one of them moves. Both poses are expressed in the frame of the first marker,
+    /// markers in different frames need a transform lookup, see `listen_tf`. The measurement
+    /// stops with `stop_measuring` or when one of the markers is removed. Measuring the same two
+    /// markers again replaces the running measurement.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::MarkerNotFound` if one of the markers doesn't exist.
+    pub fn measure(&self, name_a: &str, name_b: &str, rotation: bool) -> Result<()> {
+        let updates_a = self.pose_updates(name_a)?;
+        let updates_b = self.pose_updates(name_b)?;
+        let pair = (name_a.to_string(), name_b.to_string());
+        let key = measurement_name(name_a, name_b);
+        // The task of the replaced measurement stops once it sees the new name
+        let replaced = self.measurements.lock().unwrap().insert(pair.clone(), key.clone());
+        if let Some(replaced) = replaced {
+            self.erase_measurement(&replaced);
+        }
+        self.draw_measurement(name_a, name_b, &key, rotation);
+
+        // Each stream yields once more when its marker is removed, so the measurement is erased
+        let events = futures::stream::select(
+            updates_a.map(|_| ()).chain(futures::stream::once(async {})),
+            updates_b.map(|_| ()).chain(futures::stream::once(async {})),
+        );
+        let server = self.handle();
+        self.executor.spawn(async move {
+            futures::pin_mut!(events);
+            while events.next().await.is_some() {
+                if server.measurements.lock().unwrap().get(&pair) != Some(&key) {
+                    return;
+                }
+                if !server.draw_measurement(&pair.0, &pair.1, &key, rotation) {
+                    break;
+                }
+            }
+            let current = {
+                let mut measurements = server.measurements.lock().unwrap();
+                let current = measurements.get(&pair) == Some(&key);
+                if current {
+                    measurements.remove(&pair);
+                }
+                current
+            };
+            if current {
+                server.erase_measurement(&key);
+            }
+        });
+        Ok(())
+    }
+
+    /// Stops showing the distance between two markers started with `measure`.
+    ///
+    /// # Arguments
+    ///
+    /// * `name_a` - The name of the first marker, as passed to `measure`.
+    /// * `name_b` - The name of the second marker, as passed to `measure`.
+    pub fn stop_measuring(&self, name_a: &str, name_b: &str) {
+        let removed = self
+            .measurements
+            .lock()
+            .unwrap()
+            .remove(&(name_a.to_string(), name_b.to_string()));
+        if let Some(key) = removed {
+            self.erase_measurement(&key);
+        }
+    }
+
+    /// Draws the line and text of a measurement at the current poses of the markers.
+    ///
+    /// # Arguments
+    ///
+    /// * `name_a` - The name of the first marker.
+    /// * `name_b` - The name of the second marker.
+    /// * `key` - The name shared by the regular markers of the measurement.
+    /// * `rotation` - Whether to also show the angle of the rotation between the two markers.
+    ///
+    /// # Returns
+    ///
+    /// `false` if one of the markers no longer exists.
+    fn draw_measurement(&self, name_a: &str, name_b: &str, key: &str, rotation: bool) -> bool {
+        let (a, b) = match (self.pose(name_a), self.pose(name_b)) {
+            (Ok(a), Ok(b)) => (a, b),
+            _ => return false,
+        };
+        let frame = a.header.frame_id.clone();
+        let b_pose = match self.pose_in_frame(&frame, &b.header.frame_id, &b.pose) {
+            Some(pose) => pose,
+            None => {
+                // Nothing sensible to show until the frames are connected
+                self.erase_measurement(key);
+                return true;
+            }
+        };
+
+        let (p, q) = (&a.pose.position, &b_pose.position);
+        let (dx, dy, dz) = (q.x - p.x, q.y - p.y, q.z - p.z);
+        let distance = (dx * dx + dy * dy + dz * dz).sqrt();
+        let mut text = format!("{:.3} m", distance);
+        if rotation {
+            let angle = rotation_angle(&a.pose.orientation, &b_pose.orientation);
+            text.push_str(&format!("\n{:.1}°", angle.to_degrees()));
+        }
+
+        let mut line = Marker::default();
+        line.header.frame_id = frame.clone();
+        line.type_ = Marker::LINE_LIST as i32;
+        line.action = Marker::ADD as i32;
+        line.pose.orientation.w = 1.0;
+        line.scale.x = 0.005;
+        line.color = MEASURE_COLOR;
+        line.points = vec![p.clone(), q.clone()];
+
+        let mut label = Marker::default();
+        label.header.frame_id = frame;
+        label.type_ = Marker::TEXT_VIEW_FACING as i32;
+        label.action = Marker::ADD as i32;
+        label.pose.position = Point {
+            x: (p.x + q.x) / 2.0,
+            y: (p.y + q.y) / 2.0,
+            z: (p.z + q.z) / 2.0 + MEASURE_TEXT_HEIGHT,
+        };
+        label.pose.orientation.w = 1.0;
+        label.scale.z = MEASURE_TEXT_HEIGHT;
+        label.color = MEASURE_COLOR;
+        label.text = text;
+
+        self.regular_marker_server.insert(&format!("{key}_line"), line);
+        self.regular_marker_server.insert(&format!("{key}_text"), label);
+        self.regular_marker_server.apply_changes();
+        true
+    }
+
+    /// Erases the line and text of a measurement.
+    fn erase_measurement(&self, key: &str) {
+        self.regular_marker_server.erase(&format!("{key}_line"));
+        self.regular_marker_server.erase(&format!("{key}_text"));
+        self.regular_marker_server.apply_changes();
+    }
+}
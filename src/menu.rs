@@ -0,0 +1,105 @@
+use r2r::visualization_msgs::msg::{InteractiveMarkerFeedback, MenuEntry};
+use std::sync::Arc;
+
+/// A callback invoked when a menu entry is selected.
+pub type MenuCallback = Arc<dyn Fn(&InteractiveMarkerFeedback) + Send + Sync>;
+
+#[derive(Clone)]
+/// A menu entry together with its optional callback.
+struct MenuEntryContext {
+    entry: MenuEntry,
+    callback: Option<MenuCallback>,
+}
+
+#[derive(Clone, Default)]
+/// A context menu that can be attached to teaching markers.
+///
+/// Entries are shown when right-clicking the marker in RViz.
+pub struct MenuHandler {
+    entries: Vec<MenuEntryContext>,
+}
+
+impl MenuHandler {
+    /// Creates an empty menu.
+    pub fn new() -> Self {
+        MenuHandler::default()
+    }
+
+    /// Inserts a top level entry.
+    ///
+    /// # Arguments
+    ///
+    /// * `title` - The title shown in the menu.
+    /// * `callback` - The callback invoked when the entry is selected.
+    ///
+    /// # Returns
+    ///
+    /// The ID of the entry.
+    pub fn insert<F>(&mut self, title: &str, callback: F) -> u32
+    where
+        F: Fn(&InteractiveMarkerFeedback) + Send + Sync + 'static,
+    {
+        self.push(0, title, Some(Arc::new(callback)))
+    }
+
+    /// Inserts a top level entry without a callback, to be used as a submenu.
+    ///
+    /// # Returns
+    ///
+    /// The ID of the entry, to be passed as `parent` to `insert_child`.
+    pub fn insert_submenu(&mut self, title: &str) -> u32 {
+        self.push(0, title, None)
+    }
+
+    /// Inserts an entry below another entry.
+    ///
+    /// # Arguments
+    ///
+    /// * `parent` - The ID of the parent entry.
+    /// * `title` - The title shown in the menu.
+    /// * `callback` - The callback invoked when the entry is selected.
+    ///
+    /// # Returns
+    ///
+    /// The ID of the entry.
+    pub fn insert_child<F>(&mut self, parent: u32, title: &str, callback: F) -> u32
+    where
+        F: Fn(&InteractiveMarkerFeedback) + Send + Sync + 'static,
+    {
+        self.push(parent, title, Some(Arc::new(callback)))
+    }
+
+    /// Returns `true` if the menu has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The menu entries to be attached to an interactive marker.
+    pub(crate) fn entries(&self) -> Vec<MenuEntry> {
+        self.entries.iter().map(|e| e.entry.clone()).collect()
+    }
+
+    /// The callback registered for the entry with the given ID.
+    pub(crate) fn callback(&self, id: u32) -> Option<MenuCallback> {
+        self.entries
+            .iter()
+            .find(|e| e.entry.id == id)
+            .and_then(|e| e.callback.clone())
+    }
+
+    fn push(&mut self, parent: u32, title: &str, callback: Option<MenuCallback>) -> u32 {
+        // IDs start at 1 since a parent ID of 0 denotes a top level entry
+        let id = self.entries.len() as u32 + 1;
+        self.entries.push(MenuEntryContext {
+            entry: MenuEntry {
+                id,
+                parent_id: parent,
+                title: title.to_string(),
+                command: String::new(),
+                command_type: MenuEntry::FEEDBACK as u8,
+            },
+            callback,
+        });
+        id
+    }
+}
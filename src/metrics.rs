@@ -0,0 +1,122 @@
+use r2r::std_msgs::msg::String as StringMsg;
+use r2r::QosProfile;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::{Result, TeachingMarkerServer, NODE_ID};
+
+/// Length of the sliding window the rates and latencies are computed over.
+const WINDOW: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Debug, Default, PartialEq)]
+/// Runtime metrics of a server over the last second, see `TeachingMarkerServer::metrics`.
+pub struct Metrics {
+    /// Feedback messages received from RViz per second, for all markers.
+    pub feedback_rate: f64,
+    /// Mean time from receiving a feedback to publishing its transform, `None` without publishes.
+    pub mean_latency: Option<Duration>,
+    /// Longest time from receiving a feedback to publishing its transform.
+    pub max_latency: Option<Duration>,
+    /// Updates waiting for the TF publishing thread right now.
+    pub channel_depth: usize,
+}
+
+#[derive(Default)]
+/// Records the feedback and publish times of a server within the sliding window.
+pub(crate) struct MetricsRecorder {
+    feedback: VecDeque<Instant>,
+    latencies: VecDeque<(Instant, Duration)>,
+}
+
+impl MetricsRecorder {
+    /// Records a feedback received at the given time.
+    pub(crate) fn feedback(&mut self, at: Instant) {
+        self.feedback.push_back(at);
+        self.trim(at);
+    }
+
+    /// Records the publish of a transform for a feedback received at the given time.
+    pub(crate) fn published(&mut self, received: Instant) {
+        let now = Instant::now();
+        self.latencies.push_back((now, now.duration_since(received)));
+        self.trim(now);
+    }
+
+    /// Computes the metrics of the window ending now.
+    pub(crate) fn snapshot(&mut self, channel_depth: usize) -> Metrics {
+        self.trim(Instant::now());
+        let latencies = self.latencies.iter().map(|(_, latency)| *latency);
+        Metrics {
+            feedback_rate: self.feedback.len() as f64 / WINDOW.as_secs_f64(),
+            mean_latency: (!self.latencies.is_empty())
+                .then(|| latencies.clone().sum::<Duration>() / self.latencies.len() as u32),
+            max_latency: latencies.max(),
+            channel_depth,
+        }
+    }
+
+    /// Forgets the records that are older than the window.
+    fn trim(&mut self, now: Instant) {
+        let start = now.checked_sub(WINDOW).unwrap_or(now);
+        while self.feedback.front().is_some_and(|time| *time < start) {
+            self.feedback.pop_front();
+        }
+        while self.latencies.front().is_some_and(|(time, _)| *time < start) {
+            self.latencies.pop_front();
+        }
+    }
+}
+
+impl TeachingMarkerServer {
+    /// Returns the feedback rate, the latency from feedback to TF publish and the TF channel depth.
+    ///
+    /// # Remarks
+    ///
+    /// Rates and latencies are computed over the last second. Only poses dragged in RViz count
+    /// towards the latency, programmatic moves don't have a feedback.
+    pub fn metrics(&self) -> Metrics {
+        let depth = self.tf_channel.depth();
+        self.metrics.lock().unwrap().snapshot(depth)
+    }
+
+    /// Periodically publishes the metrics of the server as JSON on `<namespace>/metrics`.
+    ///
+    /// # Arguments
+    ///
+    /// * `period` - How often to publish.
+    ///
+    /// # Remarks
+    ///
+    /// The latencies are given in milliseconds, e.g.
+    /// `{"feedback_rate":30.0,"mean_latency_ms":1.2,"max_latency_ms":4.5,"channel_depth":0}`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the publisher can't be created.
+    pub fn publish_metrics(&self, period: Duration) -> Result<()> {
+        let publisher = self.node.lock().unwrap().create_publisher::<StringMsg>(
+            &format!("{}/metrics", self.namespace),
+            QosProfile::default(),
+        )?;
+
+        let server = self.handle();
+        self.executor.spawn(async move {
+            loop {
+                server.executor.sleep(period).await;
+                let metrics = server.metrics();
+                let millis = |latency: Option<Duration>| latency.map(|l| l.as_secs_f64() * 1000.0);
+                let data = serde_json::json!({
+                    "feedback_rate": metrics.feedback_rate,
+                    "mean_latency_ms": millis(metrics.mean_latency),
+                    "max_latency_ms": millis(metrics.max_latency),
+                    "channel_depth": metrics.channel_depth,
+                })
+                .to_string();
+                if let Err(e) = publisher.publish(&StringMsg { data }) {
+                    r2r::log_error!(NODE_ID, "Failed to publish the metrics with: '{}'.", e);
+                }
+            }
+        });
+        Ok(())
+    }
+}
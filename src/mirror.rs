@@ -0,0 +1,121 @@
+use r2r::geometry_msgs::msg::{Point, Pose, Quaternion, Vector3};
+
+#[derive(Clone, Debug, PartialEq)]
+/// A plane `normal · p = offset` in the parent frame of a mirrored marker.
+///
+/// For example, a normal of `(0, 1, 0)` with an offset of `0.0` mirrors left and right across the XZ plane.
+pub struct MirrorPlane {
+    /// The normal of the plane, it doesn't need to be normalized.
+    pub normal: Vector3,
+    /// The signed distance of the plane from the origin along the normalized normal.
+    pub offset: f64,
+}
+
+impl MirrorPlane {
+    /// Reflects a pose across the plane.
+    ///
+    /// # Returns
+    ///
+    /// The reflected pose, or the unchanged pose if the normal is zero. Since a reflection flips
+    /// handedness, the orientation is mirrored as `M R M` with the reflection matrix `M`,
+    /// which keeps it a proper rotation.
+    pub fn reflect(&self, pose: &Pose) -> Pose {
+        let n = &self.normal;
+        let norm = (n.x * n.x + n.y * n.y + n.z * n.z).sqrt();
+        if norm == 0.0 {
+            return pose.clone();
+        }
+        let (nx, ny, nz) = (n.x / norm, n.y / norm, n.z / norm);
+
+        let p = &pose.position;
+        let distance = nx * p.x + ny * p.y + nz * p.z - self.offset;
+        let position = Point {
+            x: p.x - 2.0 * distance * nx,
+            y: p.y - 2.0 * distance * ny,
+            z: p.z - 2.0 * distance * nz,
+        };
+
+        // The rotation axis is reflected and the angle negated
+        let q = &pose.orientation;
+        let dot = nx * q.x + ny * q.y + nz * q.z;
+        let orientation = Quaternion {
+            x: 2.0 * dot * nx - q.x,
+            y: 2.0 * dot * ny - q.y,
+            z: 2.0 * dot * nz - q.z,
+            w: q.w,
+        };
+
+        Pose {
+            position,
+            orientation,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::{quaternion_to_rpy, rpy_to_quaternion};
+    use crate::test_util::{assert_near, assert_position, pose};
+
+    fn plane(x: f64, y: f64, z: f64, offset: f64) -> MirrorPlane {
+        MirrorPlane {
+            normal: Vector3 { x, y, z },
+            offset,
+        }
+    }
+
+    fn assert_rpy(q: &Quaternion, roll: f64, pitch: f64, yaw: f64) {
+        let (r, p, y) = quaternion_to_rpy(q);
+        assert_near(r, roll);
+        assert_near(p, pitch);
+        assert_near(y, yaw);
+    }
+
+    #[test]
+    fn reflecting_twice_gives_the_pose() {
+        let mirror = plane(0.3, -1.2, 0.5, 0.4);
+        let original = Pose {
+            orientation: rpy_to_quaternion(0.3, -0.7, 1.9),
+            ..pose(0.8, -0.1, 1.5)
+        };
+        let twice = mirror.reflect(&mirror.reflect(&original));
+        assert_position(&twice, 0.8, -0.1, 1.5);
+        assert_near(twice.orientation.x, original.orientation.x);
+        assert_near(twice.orientation.y, original.orientation.y);
+        assert_near(twice.orientation.z, original.orientation.z);
+        assert_near(twice.orientation.w, original.orientation.w);
+    }
+
+    #[test]
+    fn mirrors_left_and_right_across_the_xz_plane() {
+        let mirror = plane(0.0, 1.0, 0.0, 0.0);
+        let left = Pose {
+            orientation: rpy_to_quaternion(0.2, 0.4, 0.6),
+            ..pose(1.0, 2.0, 3.0)
+        };
+        let right = mirror.reflect(&left);
+        assert_position(&right, 1.0, -2.0, 3.0);
+        // Rotations around X and Z turn the other way, rotations around the normal keep their sense
+        assert_rpy(&right.orientation, -0.2, 0.4, -0.6);
+    }
+
+    #[test]
+    fn mirrors_across_an_offset_plane_with_a_non_unit_normal() {
+        // The plane z = 0.5, the normal is normalized before use
+        let mirror = plane(0.0, 0.0, 2.0, 0.5);
+        let reflected = mirror.reflect(&pose(1.0, 1.0, 2.0));
+        assert_position(&reflected, 1.0, 1.0, -1.0);
+        assert_rpy(&reflected.orientation, 0.0, 0.0, 0.0);
+    }
+
+    #[test]
+    fn keeps_the_pose_for_a_zero_normal() {
+        let mirror = plane(0.0, 0.0, 0.0, 1.0);
+        let original = Pose {
+            orientation: rpy_to_quaternion(0.1, 0.2, 0.3),
+            ..pose(1.0, 2.0, 3.0)
+        };
+        assert_eq!(mirror.reflect(&original), original);
+    }
+}
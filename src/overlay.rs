@@ -0,0 +1,191 @@
+use futures::StreamExt;
+use r2r::geometry_msgs::msg::{Point, Pose, Vector3};
+use r2r::std_msgs::msg::ColorRGBA;
+use r2r::visualization_msgs::msg::Marker;
+
+use crate::math::{quaternion_to_rpy, rotate_vector};
+use crate::{Axis, Result, TeachingMarkerServer};
+
+/// The axes of a triad with their conventional colors, X red, Y green and Z blue.
+const TRIAD_AXES: [(Axis, &str, [f32; 3]); 3] = [
+    (Axis::X, "x", [1.0, 0.0, 0.0]),
+    (Axis::Y, "y", [0.0, 1.0, 0.0]),
+    (Axis::Z, "z", [0.0, 0.0, 1.0]),
+];
+
+/// Returns the name of the regular marker showing the pose of a marker as text.
+fn label_name(name: &str) -> String {
+    format!("{name}_pose_label")
+}
+
+/// Returns the name of the regular marker showing one axis of the triad of a marker.
+fn triad_name(name: &str, axis: &str) -> String {
+    format!("{name}_axis_{axis}")
+}
+
+/// Creates an arrow along one axis of a pose.
+///
+/// # Arguments
+///
+/// * `frame` - The parent frame of the pose.
+/// * `pose` - The pose whose axis is drawn, relative to the parent frame.
+/// * `axis` - The axis of the pose.
+/// * `rgb` - The color of the arrow.
+/// * `scale` - The scale of the teaching marker, used to size the arrow.
+fn triad_arrow(frame: &str, pose: &Pose, axis: Axis, rgb: [f32; 3], scale: f32) -> Marker {
+    // Short enough to stay inside the rotation rings
+    let length = scale as f64 * 0.4;
+    let unit = Vector3 {
+        x: if axis == Axis::X { length } else { 0.0 },
+        y: if axis == Axis::Y { length } else { 0.0 },
+        z: if axis == Axis::Z { length } else { 0.0 },
+    };
+    let direction = rotate_vector(&pose.orientation, &unit);
+    let start = pose.position.clone();
+    let end = Point {
+        x: start.x + direction.x,
+        y: start.y + direction.y,
+        z: start.z + direction.z,
+    };
+
+    let mut arrow = Marker::default();
+    arrow.header.frame_id = frame.to_string();
+    arrow.type_ = Marker::ARROW as i32;
+    arrow.action = Marker::ADD as i32;
+    arrow.pose.orientation.w = 1.0;
+    arrow.points = vec![start, end];
+    arrow.scale.x = length * 0.06;
+    arrow.scale.y = length * 0.12;
+    arrow.scale.z = length * 0.2;
+    arrow.color = ColorRGBA {
+        r: rgb[0],
+        g: rgb[1],
+        b: rgb[2],
+        a: 1.0,
+    };
+    arrow
+}
+
+/// Returns a brightened copy of a visual, shown while its teaching marker is dragged.
+pub(crate) fn highlighted(visual: &Marker) -> Marker {
+    let mut marker = visual.clone();
+    // Halfway towards white, and mostly opaque so translucent visuals stand out as well
+    marker.color.r += (1.0 - marker.color.r) * 0.5;
+    marker.color.g += (1.0 - marker.color.g) * 0.5;
+    marker.color.b += (1.0 - marker.color.b) * 0.5;
+    marker.color.a = marker.color.a.max(0.9);
+    for color in &mut marker.colors {
+        color.r += (1.0 - color.r) * 0.5;
+        color.g += (1.0 - color.g) * 0.5;
+        color.b += (1.0 - color.b) * 0.5;
+    }
+    // Embedded mesh materials would ignore the color
+    marker.mesh_use_embedded_materials = false;
+    marker
+}
+
+/// Returns a copy of a visual in a single color, keeping its transparency.
+pub(crate) fn tinted(visual: &Marker, rgb: [f32; 3]) -> Marker {
+    let mut marker = visual.clone();
+    marker.color.r = rgb[0];
+    marker.color.g = rgb[1];
+    marker.color.b = rgb[2];
+    // Per point colors and embedded mesh materials would override the color
+    marker.colors.clear();
+    marker.mesh_use_embedded_materials = false;
+    marker
+}
+
+/// Creates a text marker with the position and orientation of a pose.
+///
+/// # Arguments
+///
+/// * `frame` - The parent frame of the pose.
+/// * `pose` - The pose to show, relative to the parent frame.
+/// * `scale` - The scale of the teaching marker, used to size and place the text.
+fn pose_label(frame: &str, pose: &Pose, scale: f32) -> Marker {
+    let scale = scale as f64;
+    let p = &pose.position;
+    let (roll, pitch, yaw) = quaternion_to_rpy(&pose.orientation);
+
+    let mut label = Marker::default();
+    label.header.frame_id = frame.to_string();
+    label.type_ = Marker::TEXT_VIEW_FACING as i32;
+    label.action = Marker::ADD as i32;
+    label.pose.position = p.clone();
+    // Above the controls, so the text doesn't hide the rings
+    label.pose.position.z += scale * 0.75;
+    label.pose.orientation.w = 1.0;
+    label.scale.z = scale * 0.12;
+    label.color.r = 1.0;
+    label.color.g = 1.0;
+    label.color.b = 1.0;
+    label.color.a = 1.0;
+    label.text = format!(
+        "x {:.3}  y {:.3}  z {:.3}\nr {:.1}°  p {:.1}°  y {:.1}°",
+        p.x,
+        p.y,
+        p.z,
+        roll.to_degrees(),
+        pitch.to_degrees(),
+        yaw.to_degrees()
+    );
+    label
+}
+
+impl TeachingMarkerServer {
+    /// Redraws the overlays of a marker every time its transform is published, until it is removed.
+    pub(crate) fn track_overlays(&self, name: &str) -> Result<()> {
+        let mut updates = self.pose_updates(name)?;
+        self.draw_overlays(name);
+
+        let server = self.handle();
+        let name = name.to_string();
+        self.executor.spawn(async move {
+            while updates.next().await.is_some() {
+                server.draw_overlays(&name);
+            }
+        });
+        Ok(())
+    }
+
+    /// Draws the enabled overlays of a marker at its current pose.
+    pub(crate) fn draw_overlays(&self, name: &str) {
+        let (frame, pose, scale, show_label, show_axes) = match self.markers.lock().unwrap().get(name) {
+            Some(state) if !state.hidden => (
+                state.config.frame.clone(),
+                state.pose.clone(),
+                state.config.scale,
+                state.config.pose_label,
+                state.config.axis_triad,
+            ),
+            _ => return,
+        };
+
+        if show_label {
+            self.regular_marker_server
+                .insert(&label_name(name), pose_label(&frame, &pose, scale));
+        } else {
+            self.regular_marker_server.erase(&label_name(name));
+        }
+        for (axis, axis_name, rgb) in TRIAD_AXES {
+            if show_axes {
+                self.regular_marker_server.insert(
+                    &triad_name(name, axis_name),
+                    triad_arrow(&frame, &pose, axis, rgb, scale),
+                );
+            } else {
+                self.regular_marker_server.erase(&triad_name(name, axis_name));
+            }
+        }
+        self.regular_marker_server.apply_changes();
+    }
+
+    /// Erases the overlays of a marker, without applying the changes.
+    pub(crate) fn erase_overlays(&self, name: &str) {
+        self.regular_marker_server.erase(&label_name(name));
+        for (_, axis_name, _) in TRIAD_AXES {
+            self.regular_marker_server.erase(&triad_name(name, axis_name));
+        }
+    }
+}
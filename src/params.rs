@@ -0,0 +1,98 @@
+use r2r::geometry_msgs::msg::{Point, Pose};
+use r2r::ParameterValue;
+
+use crate::math::rpy_to_quaternion;
+use crate::{ControlProfile, Result, TeachingMarker, TeachingMarkerError};
+
+/// Reads the teaching markers declared in the parameters of a node.
+///
+/// # Arguments
+///
+/// * `node` - The node whose parameters are read.
+/// * `prefix` - The parameter prefix, e.g. `markers`.
+///
+/// # Remarks
+///
+/// ROS parameters can't hold lists of structs, so the markers are declared as
+///
+/// ```yaml
+/// markers:
+///   names: ["pick", "place"]
+///   pick:
+///     frame: "table"
+///     xyz: [0.1, 0.2, 0.0]
+///     rpy: [0.0, 0.0, 1.57]
+///     profile: "translate_only"
+/// ```
+///
+/// where everything but `names` is optional.
+///
+/// # Errors
+///
+/// Returns `TeachingMarkerError::InvalidParameter` if a parameter has the wrong type or value.
+pub(crate) fn markers_from_params(node: &r2r::Node, prefix: &str) -> Result<Vec<TeachingMarker>> {
+    let params = node.params.lock().unwrap();
+    let get = |key: &str| params.get(&format!("{prefix}.{key}")).map(|p| p.value.clone());
+
+    let names = match get("names") {
+        Some(ParameterValue::StringArray(names)) => names,
+        Some(ParameterValue::String(name)) => vec![name],
+        None | Some(ParameterValue::NotSet) => return Ok(vec![]),
+        Some(_) => return Err(invalid(prefix, "names", "a list of strings")),
+    };
+
+    let mut markers = vec![];
+    for name in names {
+        let mut builder = TeachingMarker::builder(&name);
+        match get(&format!("{name}.frame")) {
+            Some(ParameterValue::String(frame)) => builder = builder.frame(&frame),
+            None | Some(ParameterValue::NotSet) => (),
+            Some(_) => return Err(invalid(prefix, &format!("{name}.frame"), "a string")),
+        }
+        let xyz = vector3(prefix, &format!("{name}.xyz"), get(&format!("{name}.xyz")))?;
+        let rpy = vector3(prefix, &format!("{name}.rpy"), get(&format!("{name}.rpy")))?;
+        builder = builder.pose(Pose {
+            position: Point {
+                x: xyz[0],
+                y: xyz[1],
+                z: xyz[2],
+            },
+            orientation: rpy_to_quaternion(rpy[0], rpy[1], rpy[2]),
+        });
+        match get(&format!("{name}.profile")) {
+            Some(ParameterValue::String(profile)) => {
+                builder = builder.profile(profile.parse::<ControlProfile>()?)
+            }
+            None | Some(ParameterValue::NotSet) => (),
+            Some(_) => return Err(invalid(prefix, &format!("{name}.profile"), "a string")),
+        }
+        match get(&format!("{name}.scale")) {
+            Some(ParameterValue::Double(scale)) => builder = builder.scale(scale as f32),
+            Some(ParameterValue::Integer(scale)) => builder = builder.scale(scale as f32),
+            None | Some(ParameterValue::NotSet) => (),
+            Some(_) => return Err(invalid(prefix, &format!("{name}.scale"), "a number")),
+        }
+        markers.push(builder.build());
+    }
+    Ok(markers)
+}
+
+/// Reads a parameter holding three numbers, defaulting to zeros if it isn't set.
+fn vector3(prefix: &str, key: &str, value: Option<ParameterValue>) -> Result<[f64; 3]> {
+    let values: Vec<f64> = match value {
+        Some(ParameterValue::DoubleArray(values)) => values,
+        // YAML lists of whole numbers like [0, 0, 1] are parsed as integers
+        Some(ParameterValue::IntegerArray(values)) => values.into_iter().map(|v| v as f64).collect(),
+        None | Some(ParameterValue::NotSet) => vec![0.0; 3],
+        Some(_) => return Err(invalid(prefix, key, "a list of three numbers")),
+    };
+    match values.as_slice() {
+        [x, y, z] => Ok([*x, *y, *z]),
+        _ => Err(invalid(prefix, key, "a list of three numbers")),
+    }
+}
+
+/// Creates the error for a parameter of the wrong type.
+fn invalid(prefix: &str, key: &str, expected: &str) -> TeachingMarkerError {
+    TeachingMarkerError::InvalidParameter(format!("'{prefix}.{key}' must be {expected}"))
+}
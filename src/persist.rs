@@ -0,0 +1,150 @@
+use r2r::geometry_msgs::msg::Pose;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::{ControlProfile, Result, TeachingMarkerError};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// A teaching marker as stored in a file.
+pub(crate) struct SavedMarker {
+    pub(crate) name: String,
+    pub(crate) frame: String,
+    pub(crate) pose: Pose,
+    #[serde(default)]
+    pub(crate) profile: ControlProfile,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+/// The contents of a marker file.
+struct MarkerFile {
+    markers: Vec<SavedMarker>,
+}
+
+/// Returns `true` if the file at the path is to be read and written as YAML rather than JSON.
+pub(crate) fn is_yaml(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|e| e.to_str()),
+        Some("yaml") | Some("yml")
+    )
+}
+
+/// Writes markers to a file, as YAML if the extension is `.yaml` or `.yml` and as JSON otherwise.
+pub(crate) fn write(path: &Path, markers: Vec<SavedMarker>) -> Result<()> {
+    let file = MarkerFile { markers };
+    let contents = if is_yaml(path) {
+        serde_yaml::to_string(&file).map_err(|e| TeachingMarkerError::Serialization(e.to_string()))?
+    } else {
+        serde_json::to_string_pretty(&file)
+            .map_err(|e| TeachingMarkerError::Serialization(e.to_string()))?
+    };
+    std::fs::write(path, contents)?;
+    Ok(())
+}
+
+/// Reads markers from a file written by `write`.
+pub(crate) fn read(path: &Path) -> Result<Vec<SavedMarker>> {
+    let file: MarkerFile = read_serde(path)?;
+    Ok(file.markers)
+}
+
+/// Reads a value from a file, as YAML if the extension is `.yaml` or `.yml` and as JSON otherwise.
+pub(crate) fn read_serde<T: DeserializeOwned>(path: &Path) -> Result<T> {
+    let contents = std::fs::read_to_string(path)?;
+    if is_yaml(path) {
+        serde_yaml::from_str(&contents).map_err(|e| TeachingMarkerError::Serialization(e.to_string()))
+    } else {
+        serde_json::from_str(&contents).map_err(|e| TeachingMarkerError::Serialization(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{pose, round_trip, temp_path};
+    use r2r::geometry_msgs::msg::Quaternion;
+
+    fn markers() -> Vec<SavedMarker> {
+        vec![
+            SavedMarker {
+                name: "pick".to_string(),
+                frame: "world".to_string(),
+                pose: Pose {
+                    orientation: Quaternion {
+                        x: 0.0,
+                        y: 0.0,
+                        z: 0.5_f64.sqrt(),
+                        w: 0.5_f64.sqrt(),
+                    },
+                    ..pose(0.5, -0.25, 1.0)
+                },
+                profile: ControlProfile::TranslateOnly,
+            },
+            SavedMarker {
+                name: "place".to_string(),
+                frame: "table".to_string(),
+                pose: Pose::default(),
+                profile: ControlProfile::Full6D,
+            },
+        ]
+    }
+
+    fn assert_round_trips(name: &str) {
+        let saved = round_trip(name, |path| write(path, markers()).unwrap(), read).unwrap();
+        assert_eq!(saved.len(), 2);
+        for (read, written) in saved.iter().zip(markers()) {
+            assert_eq!(read.name, written.name);
+            assert_eq!(read.frame, written.frame);
+            assert_eq!(read.pose, written.pose);
+            assert_eq!(read.profile, written.profile);
+        }
+    }
+
+    #[test]
+    fn detects_yaml_by_the_extension() {
+        assert!(is_yaml(Path::new("markers.yaml")));
+        assert!(is_yaml(Path::new("markers.yml")));
+        assert!(!is_yaml(Path::new("markers.json")));
+        assert!(!is_yaml(Path::new("markers")));
+    }
+
+    #[test]
+    fn round_trips_yaml() {
+        assert_round_trips("round_trip.yaml");
+    }
+
+    #[test]
+    fn round_trips_json() {
+        assert_round_trips("round_trip.json");
+    }
+
+    #[test]
+    fn defaults_the_profile() {
+        let contents = r#"{"markers": [{"name": "pick", "frame": "world", "pose": {
+            "position": {"x": 1.0, "y": 2.0, "z": 3.0},
+            "orientation": {"x": 0.0, "y": 0.0, "z": 0.0, "w": 1.0}}}]}"#;
+        let saved = round_trip(
+            "default_profile.json",
+            |path| std::fs::write(path, contents).unwrap(),
+            read,
+        )
+        .unwrap();
+        assert_eq!(saved[0].profile, ControlProfile::Full6D);
+        assert_eq!(saved[0].pose.position.z, 3.0);
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        let result = round_trip(
+            "malformed.yaml",
+            |path| std::fs::write(path, "markers: [name: 1").unwrap(),
+            read,
+        );
+        assert!(matches!(result, Err(TeachingMarkerError::Serialization(_))));
+    }
+
+    #[test]
+    fn fails_on_missing_files() {
+        assert!(read(&temp_path("missing.json")).is_err());
+    }
+}
@@ -0,0 +1,239 @@
+use futures::StreamExt;
+use r2r::geometry_msgs::msg::Point;
+use r2r::std_msgs::msg::ColorRGBA;
+use r2r::visualization_msgs::msg::Marker;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::persist;
+use crate::{Result, TeachingMarkerError, TeachingMarkerServer};
+
+/// Transparency of the voxels, so the teaching marker stays visible inside the cloud.
+const VOXEL_ALPHA: f32 = 0.4;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+/// A voxel of a reachability map.
+pub struct Voxel {
+    /// The center of the voxel in the frame of the map.
+    pub position: [f64; 3],
+    /// How well the robot reaches the voxel, from `0.0` for unreachable to `1.0` for best.
+    pub score: f64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+/// A precomputed reachability map of a robot, a voxel grid with a score per voxel.
+pub struct ReachabilityMap {
+    /// The frame the voxels are given in, usually the base of the robot.
+    pub frame: String,
+    /// The edge length of the voxels in meters.
+    pub resolution: f64,
+    /// The scored voxels, voxels that aren't listed are unreachable.
+    pub voxels: Vec<Voxel>,
+}
+
+impl ReachabilityMap {
+    /// Reads a map from a file, as YAML if the extension is `.yaml` or `.yml` and as JSON otherwise.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can't be read or parsed.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        persist::read_serde(path.as_ref())
+    }
+}
+
+/// Returns the name of the regular marker showing the reachability map around a marker.
+fn cloud_name(name: &str) -> String {
+    format!("{name}_reachability")
+}
+
+/// Maps a score to a color from red for poorly reachable to green for well reachable voxels.
+fn score_color(score: f64) -> ColorRGBA {
+    let score = score.clamp(0.0, 1.0) as f32;
+    ColorRGBA {
+        r: 1.0 - score,
+        g: score,
+        b: 0.0,
+        a: VOXEL_ALPHA,
+    }
+}
+
+impl TeachingMarkerServer {
+    /// Shows a reachability map around a marker while it is dragged.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the marker.
+    /// * `map` - The reachability map of the robot.
+    /// * `radius` - Only voxels within this distance in meters of the marker are shown.
+    ///
+    /// # Remarks
+    ///
+    /// The voxels are drawn as a cube cloud colored from red to green by their score, and hidden
+    /// again when the marker is released. Markers in another frame than the map's need a
+    /// transform lookup, see `listen_tf`. Replaces any previous map of the marker.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::MarkerNotFound` if no marker with the given name exists.
+    pub fn show_reachability_map(&self, name: &str, map: ReachabilityMap, radius: f64) -> Result<()> {
+        self.track_reachability_map(name, Arc::new(map), radius)
+    }
+
+    /// Sets the reachability map of a marker and starts drawing it, unless already drawing one.
+    pub(crate) fn track_reachability_map(
+        &self,
+        name: &str,
+        map: Arc<ReachabilityMap>,
+        radius: f64,
+    ) -> Result<()> {
+        let tracked = match self.markers.lock().unwrap().get_mut(name) {
+            Some(state) => state.reachability_map.replace((map, radius)).is_some(),
+            None => return Err(TeachingMarkerError::MarkerNotFound(name.to_string())),
+        };
+        // The task of a previous map keeps drawing the new one
+        if tracked {
+            return Ok(());
+        }
+
+        let mut updates = self.pose_updates(name)?;
+        let server = self.handle();
+        let name = name.to_string();
+        self.executor.spawn(async move {
+            while updates.next().await.is_some() {
+                if !server.draw_reachability_map(&name) {
+                    break;
+                }
+            }
+            server.regular_marker_server.erase(&cloud_name(&name));
+            server.regular_marker_server.apply_changes();
+        });
+        Ok(())
+    }
+
+    /// Stops showing the reachability map of a marker.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::MarkerNotFound` if no marker with the given name exists.
+    pub fn hide_reachability_map(&self, name: &str) -> Result<()> {
+        match self.markers.lock().unwrap().get_mut(name) {
+            Some(state) => state.reachability_map = None,
+            None => return Err(TeachingMarkerError::MarkerNotFound(name.to_string())),
+        }
+        self.regular_marker_server.erase(&cloud_name(name));
+        self.regular_marker_server.apply_changes();
+        Ok(())
+    }
+
+    /// Draws the voxels around a dragged marker, or erases them once it is released.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the marker or its map no longer exists.
+    fn draw_reachability_map(&self, name: &str) -> bool {
+        let (map, radius, dragging, frame, pose) = match self.markers.lock().unwrap().get(name) {
+            Some(state) => match &state.reachability_map {
+                Some((map, radius)) => (
+                    map.clone(),
+                    *radius,
+                    state.dragging && !state.hidden,
+                    state.config.frame.clone(),
+                    state.pose.clone(),
+                ),
+                None => return false,
+            },
+            None => return false,
+        };
+        let key = cloud_name(name);
+        let center = match self.pose_in_frame(&map.frame, &frame, &pose) {
+            Some(pose) if dragging => pose.position,
+            _ => {
+                self.regular_marker_server.erase(&key);
+                self.regular_marker_server.apply_changes();
+                return true;
+            }
+        };
+
+        let mut cloud = Marker::default();
+        cloud.header.frame_id = map.frame.clone();
+        cloud.type_ = Marker::CUBE_LIST as i32;
+        cloud.action = Marker::ADD as i32;
+        cloud.pose.orientation.w = 1.0;
+        cloud.scale.x = map.resolution;
+        cloud.scale.y = map.resolution;
+        cloud.scale.z = map.resolution;
+        for voxel in &map.voxels {
+            let [x, y, z] = voxel.position;
+            let (dx, dy, dz) = (x - center.x, y - center.y, z - center.z);
+            if dx * dx + dy * dy + dz * dz <= radius * radius {
+                cloud.points.push(Point { x, y, z });
+                cloud.colors.push(score_color(voxel.score));
+            }
+        }
+        self.regular_marker_server.insert(&key, cloud);
+        self.regular_marker_server.apply_changes();
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::round_trip;
+
+    fn map() -> ReachabilityMap {
+        ReachabilityMap {
+            frame: "base_link".to_string(),
+            resolution: 0.05,
+            voxels: vec![
+                Voxel {
+                    position: [0.5, 0.0, 0.25],
+                    score: 1.0,
+                },
+                Voxel {
+                    position: [-0.1, 0.35, 1.0],
+                    score: 0.2,
+                },
+            ],
+        }
+    }
+
+    /// Writes the contents to a file and reads it back as a map.
+    fn read_back(name: &str, contents: &str) -> Result<ReachabilityMap> {
+        round_trip(
+            name,
+            |path| std::fs::write(path, contents).unwrap(),
+            |path| ReachabilityMap::from_file(path),
+        )
+    }
+
+    #[test]
+    fn reads_yaml_maps() {
+        let contents = serde_yaml::to_string(&map()).unwrap();
+        assert_eq!(read_back("map.yaml", &contents).unwrap(), map());
+    }
+
+    #[test]
+    fn reads_json_maps() {
+        let contents = serde_json::to_string(&map()).unwrap();
+        assert_eq!(read_back("map.json", &contents).unwrap(), map());
+    }
+
+    #[test]
+    fn rejects_maps_with_missing_fields() {
+        let result = read_back("incomplete.yml", r#"{"frame": "base_link", "voxels": []}"#);
+        assert!(matches!(result, Err(TeachingMarkerError::Serialization(_))));
+    }
+
+    #[test]
+    fn colors_by_the_score() {
+        let poor = score_color(0.0);
+        let good = score_color(1.0);
+        assert_eq!((poor.r, poor.g), (1.0, 0.0));
+        assert_eq!((good.r, good.g), (0.0, 1.0));
+        assert_eq!(score_color(2.0), good);
+        assert_eq!(poor.a, VOXEL_ALPHA);
+    }
+}
@@ -0,0 +1,61 @@
+use r2r::visualization_msgs::msg::Marker;
+use std::path::PathBuf;
+
+use crate::NODE_ID;
+
+/// The URI scheme of resources inside ROS packages.
+const PACKAGE_SCHEME: &str = "package://";
+
+/// Looks up the share directory of a package in the ament index.
+///
+/// # Returns
+///
+/// The share directory of the first prefix in `AMENT_PREFIX_PATH` that has the package registered.
+fn package_share(package: &str) -> Option<PathBuf> {
+    let prefixes = std::env::var_os("AMENT_PREFIX_PATH")?;
+    std::env::split_paths(&prefixes).find_map(|prefix| {
+        let registered = prefix
+            .join("share/ament_index/resource_index/packages")
+            .join(package);
+        registered
+            .exists()
+            .then(|| prefix.join("share").join(package))
+    })
+}
+
+/// Resolves a `package://<package>/<path>` URI into a `file://` URI through the ament index.
+///
+/// # Arguments
+///
+/// * `uri` - The URI to resolve, e.g. `package://my_robot_description/meshes/gripper.stl`.
+///
+/// # Returns
+///
+/// The `file://` URI, the URI itself if it isn't a `package://` URI, or `None` if the package
+/// isn't installed in a sourced workspace.
+pub fn resolve_package_uri(uri: &str) -> Option<String> {
+    let resource = match uri.strip_prefix(PACKAGE_SCHEME) {
+        Some(resource) => resource,
+        None => return Some(uri.to_string()),
+    };
+    let (package, path) = resource.split_once('/').unwrap_or((resource, ""));
+    let share = package_share(package)?;
+    Some(format!("file://{}", share.join(path).display()))
+}
+
+/// Resolves the `package://` mesh resource of a visual in place.
+///
+/// Unresolved URIs are kept, RViz may still find the package on its own machine.
+pub(crate) fn resolve_visual(marker: &mut Marker) {
+    if !marker.mesh_resource.starts_with(PACKAGE_SCHEME) {
+        return;
+    }
+    match resolve_package_uri(&marker.mesh_resource) {
+        Some(uri) => marker.mesh_resource = uri,
+        None => r2r::log_warn!(
+            NODE_ID,
+            "Package of mesh '{}' not found in the ament index, passing it to RViz as it is.",
+            marker.mesh_resource
+        ),
+    }
+}
@@ -0,0 +1,295 @@
+use futures::StreamExt;
+use r2r::geometry_msgs::msg::{Pose, PoseArray, PoseStamped};
+use r2r::nav_msgs::msg::Path;
+use r2r::std_msgs::msg::{ColorRGBA, Header};
+use r2r::visualization_msgs::msg::Marker;
+use r2r::QosProfile;
+use std::sync::{Arc, Mutex};
+
+use crate::{Result, SpawnAt, TeachingMarker, TeachingMarkerError, TeachingMarkerServer, NODE_ID};
+
+/// The default color of the line connecting the waypoints.
+const LINE_COLOR: ColorRGBA = ColorRGBA {
+    r: 1.0,
+    g: 0.8,
+    b: 0.0,
+    a: 0.8,
+};
+
+/// The waypoints of a sequence and where the sequence is published.
+struct SequenceState {
+    template: TeachingMarker,
+    line_color: ColorRGBA,
+    waypoints: Vec<String>,
+    next_id: u64,
+    pose_array_publisher: r2r::Publisher<PoseArray>,
+    path_publisher: r2r::Publisher<Path>,
+}
+
+#[derive(Clone)]
+/// An ordered sequence of teaching markers, e.g. the waypoints of a robot path.
+///
+/// Created with `TeachingMarkerServer::create_sequence`. The waypoints are numbered in order in
+/// RViz, and the whole sequence is published as a `PoseArray` on `<namespace>/<sequence>/poses`
+/// and as a `Path` on `<namespace>/<sequence>/path` whenever it changes or a waypoint is moved.
+/// A line through the waypoints is shown in RViz while the sequence is taught.
+pub struct WaypointSequence {
+    server: TeachingMarkerServer,
+    name: String,
+    state: Arc<Mutex<SequenceState>>,
+}
+
+impl TeachingMarkerServer {
+    /// Creates an empty waypoint sequence.
+    ///
+    /// # Arguments
+    ///
+    /// * `template` - The configuration of the waypoint markers. Its name is used as the name
+    ///   of the sequence and its frame as the frame of all waypoints.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the publishers of the sequence can't be created.
+    pub fn create_sequence(&self, template: TeachingMarker) -> Result<WaypointSequence> {
+        let name = template.name.clone();
+        let (pose_array_publisher, path_publisher) = {
+            let mut node = self.node.lock().unwrap();
+            (
+                node.create_publisher::<PoseArray>(
+                    &format!("{}/{}/poses", self.namespace, name),
+                    QosProfile::default(),
+                )?,
+                node.create_publisher::<Path>(
+                    &format!("{}/{}/path", self.namespace, name),
+                    QosProfile::default(),
+                )?,
+            )
+        };
+        Ok(WaypointSequence {
+            server: self.handle(),
+            name,
+            state: Arc::new(Mutex::new(SequenceState {
+                template,
+                line_color: LINE_COLOR,
+                waypoints: vec![],
+                next_id: 1,
+                pose_array_publisher,
+                path_publisher,
+            })),
+        })
+    }
+}
+
+impl WaypointSequence {
+    /// The name of the sequence.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The marker names of the waypoints, in order.
+    pub fn waypoints(&self) -> Vec<String> {
+        self.state.lock().unwrap().waypoints.clone()
+    }
+
+    /// The number of waypoints.
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().waypoints.len()
+    }
+
+    /// Returns `true` if the sequence has no waypoints.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The current poses of the waypoints, in order.
+    pub fn poses(&self) -> Vec<PoseStamped> {
+        self.waypoints()
+            .iter()
+            .filter_map(|name| self.server.pose(name).ok())
+            .collect()
+    }
+
+    /// Sets the color of the line connecting the waypoints.
+    pub fn set_line_color(&self, color: ColorRGBA) {
+        self.state.lock().unwrap().line_color = color;
+        self.publish();
+    }
+
+    /// Appends a waypoint.
+    ///
+    /// # Arguments
+    ///
+    /// * `pose` - The pose of the waypoint in the frame of the sequence.
+    ///
+    /// # Returns
+    ///
+    /// The name of the waypoint marker.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the marker can't be inserted.
+    pub fn push(&self, pose: Pose) -> Result<String> {
+        let index = self.len();
+        self.insert(index, pose)
+    }
+
+    /// Inserts a waypoint at a position, shifting the following waypoints back.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The position of the new waypoint, at most `len()`.
+    /// * `pose` - The pose of the waypoint in the frame of the sequence.
+    ///
+    /// # Returns
+    ///
+    /// The name of the waypoint marker.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index is out of range or the marker can't be inserted.
+    pub fn insert(&self, index: usize, pose: Pose) -> Result<String> {
+        let config = {
+            let mut state = self.state.lock().unwrap();
+            if index > state.waypoints.len() {
+                return Err(self.out_of_range(index));
+            }
+            let mut config = state.template.clone();
+            config.name = format!("{}_wp{}", self.name, state.next_id);
+            config.spawn_at = SpawnAt::Pose(pose);
+            state.next_id += 1;
+            config
+        };
+        let waypoint = config.name.clone();
+        self.server.insert_marker(config)?;
+
+        // Republish the sequence every time the waypoint moves, until it is removed
+        let mut updates = self.server.pose_updates(&waypoint)?;
+        let sequence = self.clone();
+        self.server.executor.spawn(async move {
+            while updates.next().await.is_some() {
+                sequence.publish();
+            }
+        });
+
+        self.state
+            .lock()
+            .unwrap()
+            .waypoints
+            .insert(index, waypoint.clone());
+        self.renumber()?;
+        Ok(waypoint)
+    }
+
+    /// Removes a waypoint and its marker.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The position of the waypoint.
+    ///
+    /// # Returns
+    ///
+    /// The name of the removed waypoint marker.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index is out of range.
+    pub fn remove(&self, index: usize) -> Result<String> {
+        let waypoint = {
+            let mut state = self.state.lock().unwrap();
+            if index >= state.waypoints.len() {
+                return Err(self.out_of_range(index));
+            }
+            state.waypoints.remove(index)
+        };
+        match self.server.remove(&waypoint) {
+            Ok(()) | Err(TeachingMarkerError::MarkerNotFound(_)) => (),
+            Err(e) => return Err(e),
+        }
+        self.renumber()?;
+        Ok(waypoint)
+    }
+
+    /// Moves a waypoint to another position in the sequence.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - The current position of the waypoint.
+    /// * `to` - The new position of the waypoint.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if either index is out of range.
+    pub fn reorder(&self, from: usize, to: usize) -> Result<()> {
+        {
+            let mut state = self.state.lock().unwrap();
+            let len = state.waypoints.len();
+            if from >= len || to >= len {
+                return Err(self.out_of_range(from.max(to)));
+            }
+            let waypoint = state.waypoints.remove(from);
+            state.waypoints.insert(to, waypoint);
+        }
+        self.renumber()
+    }
+
+    /// Labels the waypoints with their positions and publishes the sequence.
+    fn renumber(&self) -> Result<()> {
+        for (i, waypoint) in self.waypoints().iter().enumerate() {
+            match self
+                .server
+                .set_description(waypoint, &format!("{} {}", self.name, i + 1))
+            {
+                Ok(()) | Err(TeachingMarkerError::MarkerNotFound(_)) => (),
+                Err(e) => return Err(e),
+            }
+        }
+        self.publish();
+        Ok(())
+    }
+
+    /// Publishes the current poses of all waypoints.
+    pub fn publish(&self) {
+        let poses = self.poses();
+        let state = self.state.lock().unwrap();
+        let header = Header {
+            frame_id: state.template.frame.clone(),
+            ..Header::default()
+        };
+
+        let pose_array = PoseArray {
+            header: header.clone(),
+            poses: poses.iter().map(|pose| pose.pose.clone()).collect(),
+        };
+        if let Err(e) = state.pose_array_publisher.publish(&pose_array) {
+            r2r::log_error!(NODE_ID, "Failed to publish the poses of sequence '{}' with: '{}'.", self.name, e);
+        }
+
+        // A line strip needs at least two points, RViz complains otherwise
+        let line_name = format!("{}_line", self.name);
+        if poses.len() >= 2 {
+            let mut line = Marker::default();
+            line.header = header.clone();
+            line.ns = self.name.clone();
+            line.type_ = Marker::LINE_STRIP as i32;
+            line.action = Marker::ADD as i32;
+            line.pose.orientation.w = 1.0;
+            line.scale.x = state.template.scale as f64 * 0.05;
+            line.color = state.line_color.clone();
+            line.points = poses.iter().map(|pose| pose.pose.position.clone()).collect();
+            self.server.regular_marker_server.insert(&line_name, line);
+        } else {
+            self.server.regular_marker_server.erase(&line_name);
+        }
+        self.server.regular_marker_server.apply_changes();
+
+        let path = Path { header, poses };
+        if let Err(e) = state.path_publisher.publish(&path) {
+            r2r::log_error!(NODE_ID, "Failed to publish the path of sequence '{}' with: '{}'.", self.name, e);
+        }
+    }
+
+    /// Creates the error for a waypoint index outside of the sequence.
+    fn out_of_range(&self, index: usize) -> TeachingMarkerError {
+        TeachingMarkerError::WaypointOutOfRange(self.name.clone(), index)
+    }
+}
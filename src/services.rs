@@ -0,0 +1,112 @@
+use futures::{Stream, StreamExt};
+use r2r::geometry_msgs::msg::PoseStamped;
+use r2r::teaching_marker_interfaces::srv::{
+    AddMarker, AlignToFrame, GetPose, RemoveMarker, SelectMarker, SetPose,
+};
+use r2r::{QosProfile, ServiceRequest, WrappedServiceTypeSupport};
+
+use crate::{ControlProfile, Result, TeachingMarker, TeachingMarkerServer, NODE_ID};
+
+/// Converts the result of a request into the `success` and `message` fields of a response.
+fn outcome(result: Result<()>) -> (bool, String) {
+    match result {
+        Ok(()) => (true, String::new()),
+        Err(e) => (false, e.to_string()),
+    }
+}
+
+impl TeachingMarkerServer {
+    /// Advertises services to manage the markers from other nodes.
+    ///
+    /// # Remarks
+    ///
+    /// The services `<namespace>/add_marker`, `<namespace>/remove_marker`, `<namespace>/set_pose`,
+    /// `<namespace>/get_pose`, `<namespace>/align_to_frame` and `<namespace>/select_for_jogging`
+    /// use the types of the `teaching_marker_interfaces` package, which has to be built and
+    /// sourced before building with the `services` feature.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any of the services can't be created.
+    pub fn advertise_services(&self) -> Result<()> {
+        let service = |name: &str| format!("{}/{}", self.namespace, name);
+        let (add, remove, set_pose, get_pose, align, select) = {
+            let mut node = self.node.lock().unwrap();
+            (
+                node.create_service::<AddMarker::Service>(&service("add_marker"), QosProfile::default())?,
+                node.create_service::<RemoveMarker::Service>(&service("remove_marker"), QosProfile::default())?,
+                node.create_service::<SetPose::Service>(&service("set_pose"), QosProfile::default())?,
+                node.create_service::<GetPose::Service>(&service("get_pose"), QosProfile::default())?,
+                node.create_service::<AlignToFrame::Service>(&service("align_to_frame"), QosProfile::default())?,
+                node.create_service::<SelectMarker::Service>(&service("select_for_jogging"), QosProfile::default())?,
+            )
+        };
+
+        self.serve(add, |server, request: AddMarker::Request| {
+            let result = Self::add_from_request(server, request);
+            let (success, message) = outcome(result);
+            AddMarker::Response { success, message }
+        });
+        self.serve(remove, |server, request: RemoveMarker::Request| {
+            let (success, message) = outcome(server.remove(&request.name));
+            RemoveMarker::Response { success, message }
+        });
+        self.serve(set_pose, |server, request: SetPose::Request| {
+            let (success, message) = outcome(server.set_pose(&request.name, &request.pose));
+            SetPose::Response { success, message }
+        });
+        self.serve(get_pose, |server, request: GetPose::Request| match server.pose(&request.name) {
+            Ok(pose) => GetPose::Response {
+                success: true,
+                message: String::new(),
+                pose,
+            },
+            Err(e) => GetPose::Response {
+                success: false,
+                message: e.to_string(),
+                pose: PoseStamped::default(),
+            },
+        });
+        self.serve(align, |server, request: AlignToFrame::Request| {
+            let (success, message) = outcome(server.align_to_frame(&request.name, &request.target_frame));
+            AlignToFrame::Response { success, message }
+        });
+        self.serve(select, |server, request: SelectMarker::Request| {
+            let name = Some(request.name.as_str()).filter(|name| !name.is_empty());
+            let (success, message) = outcome(server.select_for_jogging(name));
+            SelectMarker::Response { success, message }
+        });
+
+        Ok(())
+    }
+
+    /// Inserts a marker described by an `AddMarker` request.
+    fn add_from_request(server: &TeachingMarkerServer, request: AddMarker::Request) -> Result<()> {
+        let mut builder = TeachingMarker::builder(&request.name).pose(request.pose);
+        if !request.frame.is_empty() {
+            builder = builder.frame(&request.frame);
+        }
+        if !request.profile.is_empty() {
+            builder = builder.profile(request.profile.parse::<ControlProfile>()?);
+        }
+        server.insert_marker(builder.build())
+    }
+
+    /// Answers the requests of a service until it is dropped.
+    fn serve<T, S, F>(&self, mut requests: S, handler: F)
+    where
+        T: WrappedServiceTypeSupport + 'static,
+        S: Stream<Item = ServiceRequest<T>> + Unpin + Send + 'static,
+        F: Fn(&TeachingMarkerServer, T::Request) -> T::Response + Send + 'static,
+    {
+        let server = self.handle();
+        self.executor.spawn(async move {
+            while let Some(request) = requests.next().await {
+                let response = handler(&server, request.message.clone());
+                if let Err(e) = request.respond(response) {
+                    r2r::log_error!(NODE_ID, "Failed to respond to a service request with: '{}'.", e);
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,135 @@
+use r2r::geometry_msgs::msg::{Point, Pose};
+use std::time::{Duration, Instant};
+
+use crate::math::slerp;
+
+/// An exponential moving average over the poses of a drag.
+///
+/// The position is blended with a weight that grows with the time since the previous pose, so
+/// the output lags the input by about the time constant regardless of the feedback rate. The
+/// orientation is filtered in its own stage with slerp, by the same weight or a fixed blending
+/// factor per pose.
+pub(crate) struct PoseFilter {
+    time_constant: Duration,
+    orientation_blend: Option<f64>,
+    last: Option<(Instant, Pose)>,
+}
+
+impl PoseFilter {
+    /// Creates a filter without history.
+    ///
+    /// # Arguments
+    ///
+    /// * `time_constant` - The time constant of the average, zero passes the input through.
+    /// * `orientation_blend` - The weight of a new orientation in `[0, 1]`, or `None` to
+    ///   filter the orientation with the time constant as well.
+    pub(crate) fn new(time_constant: Duration, orientation_blend: Option<f64>) -> Self {
+        PoseFilter {
+            time_constant,
+            orientation_blend: orientation_blend.map(|blend| blend.clamp(0.0, 1.0)),
+            last: None,
+        }
+    }
+
+    /// Blends a new pose into the average and returns the smoothed pose.
+    ///
+    /// The first pose after a `reset` is returned unchanged.
+    pub(crate) fn apply(&mut self, pose: &Pose, now: Instant) -> Pose {
+        let smoothed = match &self.last {
+            Some((last_time, last)) => {
+                let alpha = if self.time_constant.is_zero() {
+                    1.0
+                } else {
+                    let dt = now.duration_since(*last_time).as_secs_f64();
+                    1.0 - (-dt / self.time_constant.as_secs_f64()).exp()
+                };
+                let blend = self.orientation_blend.unwrap_or(alpha);
+                Pose {
+                    position: lerp_point(&last.position, &pose.position, alpha),
+                    orientation: slerp(&last.orientation, &pose.orientation, blend),
+                }
+            }
+            None => pose.clone(),
+        };
+        self.last = Some((now, smoothed.clone()));
+        smoothed
+    }
+
+    /// Forgets the history, e.g. when a drag ends.
+    pub(crate) fn reset(&mut self) {
+        self.last = None;
+    }
+}
+
+/// Interpolates linearly between two points.
+fn lerp_point(a: &Point, b: &Point, t: f64) -> Point {
+    Point {
+        x: a.x + (b.x - a.x) * t,
+        y: a.y + (b.y - a.y) * t,
+        z: a.z + (b.z - a.z) * t,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::{quaternion_to_rpy, rpy_to_quaternion};
+    use crate::test_util::{assert_near, pose};
+
+    /// A pose on the X axis, yawed by an angle.
+    fn yawed(x: f64, yaw: f64) -> Pose {
+        Pose {
+            orientation: rpy_to_quaternion(0.0, 0.0, yaw),
+            ..pose(x, 0.0, 0.0)
+        }
+    }
+
+    #[test]
+    fn passes_the_first_pose_through() {
+        let mut filter = PoseFilter::new(Duration::from_millis(100), None);
+        let smoothed = filter.apply(&yawed(1.0, 0.5), Instant::now());
+        assert_eq!(smoothed, yawed(1.0, 0.5));
+    }
+
+    #[test]
+    fn zero_time_constant_passes_the_input_through() {
+        let mut filter = PoseFilter::new(Duration::ZERO, None);
+        let start = Instant::now();
+        filter.apply(&yawed(0.0, 0.0), start);
+        let smoothed = filter.apply(&yawed(1.0, 1.0), start + Duration::from_millis(10));
+        assert_near(smoothed.position.x, 1.0);
+        assert_near(quaternion_to_rpy(&smoothed.orientation).2, 1.0);
+    }
+
+    #[test]
+    fn blends_by_the_elapsed_time() {
+        let mut filter = PoseFilter::new(Duration::from_millis(100), None);
+        let start = Instant::now();
+        filter.apply(&yawed(0.0, 0.0), start);
+        // After one time constant the average covers 1 - 1/e of the step
+        let smoothed = filter.apply(&yawed(1.0, 1.0), start + Duration::from_millis(100));
+        let alpha = 1.0 - (-1.0f64).exp();
+        assert_near(smoothed.position.x, alpha);
+        assert_near(quaternion_to_rpy(&smoothed.orientation).2, alpha);
+    }
+
+    #[test]
+    fn blends_the_orientation_by_a_fixed_factor() {
+        let mut filter = PoseFilter::new(Duration::from_millis(100), Some(0.5));
+        let start = Instant::now();
+        filter.apply(&yawed(0.0, 0.0), start);
+        let smoothed = filter.apply(&yawed(1.0, 1.0), start + Duration::from_millis(100));
+        assert_near(smoothed.position.x, 1.0 - (-1.0f64).exp());
+        assert_near(quaternion_to_rpy(&smoothed.orientation).2, 0.5);
+    }
+
+    #[test]
+    fn reset_forgets_the_history() {
+        let mut filter = PoseFilter::new(Duration::from_millis(100), None);
+        let start = Instant::now();
+        filter.apply(&yawed(0.0, 0.0), start);
+        filter.reset();
+        let smoothed = filter.apply(&yawed(1.0, 1.0), start + Duration::from_millis(10));
+        assert_eq!(smoothed, yawed(1.0, 1.0));
+    }
+}
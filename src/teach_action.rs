@@ -0,0 +1,140 @@
+use futures::channel::oneshot;
+use futures::{FutureExt, StreamExt};
+use r2r::geometry_msgs::msg::PoseStamped;
+use r2r::teaching_marker_interfaces::action::TeachPose;
+use r2r::{ActionServerCancelRequest, ActionServerGoalRequest};
+use std::sync::{Arc, Mutex};
+
+use crate::{MenuHandler, Result, TeachingMarker, TeachingMarkerServer, NODE_ID};
+
+/// Something that happened while a pose is being taught.
+enum TeachEvent {
+    /// The marker was moved.
+    Pose(PoseStamped),
+    /// The operator confirmed the pose, or the marker went away if `false`.
+    Confirmed(bool),
+    /// The client asked to cancel the goal.
+    Cancel(ActionServerCancelRequest),
+}
+
+impl TeachingMarkerServer {
+    /// Advertises the `<namespace>/teach_pose` action.
+    ///
+    /// # Remarks
+    ///
+    /// For every goal a marker is spawned with the requested name, frame and initial pose.
+    /// Its poses are streamed as feedback while it is dragged, and the goal succeeds with the
+    /// final pose once the operator selects "Confirm" in the marker's context menu. The marker
+    /// stays after the goal succeeds and is removed if the goal is canceled.
+    ///
+    /// The action type is defined in the `teaching_marker_interfaces` package, see `advertise_services`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the action server can't be created.
+    pub fn advertise_teach_action(&self) -> Result<()> {
+        let mut goals = self
+            .node
+            .lock()
+            .unwrap()
+            .create_action_server::<TeachPose::Action>(&format!("{}/teach_pose", self.namespace))?;
+
+        let server = self.handle();
+        self.executor.spawn(async move {
+            while let Some(request) = goals.next().await {
+                let teacher = server.clone();
+                server.executor.spawn(async move {
+                    teacher.teach(request).await;
+                });
+            }
+        });
+        Ok(())
+    }
+
+    /// Runs a single teach goal until it is confirmed or canceled.
+    async fn teach(&self, request: ActionServerGoalRequest<TeachPose::Action>) {
+        let name = request.goal.name.clone();
+        let mut builder = TeachingMarker::builder(&name).pose(request.goal.initial_pose.clone());
+        if !request.goal.frame.is_empty() {
+            builder = builder.frame(&request.goal.frame);
+        }
+        if let Err(e) = self.insert_marker(builder.build()) {
+            r2r::log_error!(NODE_ID, "Failed to spawn the marker for teach goal '{}' with: '{}'.", name, e);
+            if let Err(e) = request.reject() {
+                r2r::log_error!(NODE_ID, "Failed to reject teach goal '{}' with: '{}'.", name, e);
+            }
+            return;
+        }
+
+        let (mut goal, cancels) = match request.accept() {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                r2r::log_error!(NODE_ID, "Failed to accept teach goal '{}' with: '{}'.", name, e);
+                let _ = self.remove(&name);
+                return;
+            }
+        };
+
+        // The menu callback may run several times, but the pose can only be confirmed once
+        let (confirm_tx, confirm_rx) = oneshot::channel();
+        let confirm_tx = Arc::new(Mutex::new(Some(confirm_tx)));
+        let mut menu = MenuHandler::new();
+        menu.insert("Confirm", move |_| {
+            if let Some(tx) = confirm_tx.lock().unwrap().take() {
+                let _ = tx.send(());
+            }
+        });
+        let poses = match self.set_menu(&name, menu).and_then(|()| self.pose_updates(&name)) {
+            Ok(poses) => poses,
+            Err(e) => {
+                r2r::log_error!(NODE_ID, "Failed to set up teach goal '{}' with: '{}'.", name, e);
+                let _ = goal.abort(TeachPose::Result::default());
+                return;
+            }
+        };
+
+        let events = futures::stream::select(
+            poses.map(TeachEvent::Pose),
+            futures::stream::select(
+                confirm_rx.map(|confirmed| TeachEvent::Confirmed(confirmed.is_ok())).into_stream(),
+                cancels.map(TeachEvent::Cancel),
+            ),
+        );
+        futures::pin_mut!(events);
+
+        while let Some(event) = events.next().await {
+            match event {
+                TeachEvent::Pose(pose) => {
+                    if let Err(e) = goal.publish_feedback(TeachPose::Feedback { pose }) {
+                        r2r::log_warn!(NODE_ID, "Failed to publish teach feedback for '{}' with: '{}'.", name, e);
+                    }
+                }
+                TeachEvent::Confirmed(true) => {
+                    let result = match self.pose(&name) {
+                        Ok(pose) => goal.succeed(TeachPose::Result { pose }),
+                        Err(_) => goal.abort(TeachPose::Result::default()),
+                    };
+                    if let Err(e) = result {
+                        r2r::log_error!(NODE_ID, "Failed to finish teach goal '{}' with: '{}'.", name, e);
+                    }
+                    return;
+                }
+                TeachEvent::Confirmed(false) => {
+                    let _ = goal.abort(TeachPose::Result::default());
+                    return;
+                }
+                TeachEvent::Cancel(cancel) => {
+                    cancel.accept();
+                    let _ = self.remove(&name);
+                    if let Err(e) = goal.cancel(TeachPose::Result::default()) {
+                        r2r::log_error!(NODE_ID, "Failed to cancel teach goal '{}' with: '{}'.", name, e);
+                    }
+                    return;
+                }
+            }
+        }
+
+        // The marker was removed while teaching
+        let _ = goal.abort(TeachPose::Result::default());
+    }
+}
@@ -0,0 +1,54 @@
+use r2r::geometry_msgs::msg::{Point, Pose, Quaternion, Vector3};
+use std::path::{Path, PathBuf};
+
+/// A pose at a position with the identity orientation.
+pub(crate) fn pose(x: f64, y: f64, z: f64) -> Pose {
+    Pose {
+        position: Point { x, y, z },
+        orientation: Quaternion {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+            w: 1.0,
+        },
+    }
+}
+
+/// Asserts that two numbers are equal up to rounding errors.
+pub(crate) fn assert_near(actual: f64, expected: f64) {
+    assert!((actual - expected).abs() < 1e-9, "{} != {}", actual, expected);
+}
+
+/// Asserts that a vector has the expected components.
+pub(crate) fn assert_vector(v: &Vector3, x: f64, y: f64, z: f64) {
+    assert_near(v.x, x);
+    assert_near(v.y, y);
+    assert_near(v.z, z);
+}
+
+/// Asserts that a pose is at the expected position.
+pub(crate) fn assert_position(pose: &Pose, x: f64, y: f64, z: f64) {
+    assert_near(pose.position.x, x);
+    assert_near(pose.position.y, y);
+    assert_near(pose.position.z, z);
+}
+
+/// Runs a test against a file in the temporary directory and removes the file afterwards.
+///
+/// # Arguments
+///
+/// * `name` - The file name, unique per test. The extension selects the format where it matters.
+/// * `write` - Creates the file.
+/// * `read` - Reads the file back, its result is returned.
+pub(crate) fn round_trip<T>(name: &str, write: impl FnOnce(&Path), read: impl FnOnce(&Path) -> T) -> T {
+    let path = temp_path(name);
+    write(&path);
+    let result = read(&path);
+    let _ = std::fs::remove_file(&path);
+    result
+}
+
+/// A file in the temporary directory, unique per process so parallel test runs don't collide.
+pub(crate) fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("teaching_markers_{}_{}", std::process::id(), name))
+}
@@ -0,0 +1,487 @@
+use r2r::geometry_msgs::msg::TransformStamped;
+use r2r::tf2_msgs::msg::TFMessage;
+use r2r::QosProfile;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::Notify;
+
+use crate::{Result, TeachingMarkerError, TfBuffer};
+
+#[derive(Clone, Debug, Default, PartialEq)]
+/// Enum representing where the transforms of a teaching marker are published.
+pub enum TfOutput {
+    /// Publish on `tf_static` with transient local QoS, so late subscribers get the last pose.
+    ///
+    /// Every latched message carries the transforms of all static markers of the server, as
+    /// with tf2's `StaticTransformBroadcaster`, so a late subscriber gets all of them at once.
+    #[default]
+    Static,
+    /// Publish on `tf`, the semantically correct choice for frames that keep moving.
+    Dynamic,
+    /// Publish on both `tf` and `tf_static`.
+    Both,
+    /// Publish on a custom topic with default QoS.
+    Custom(String),
+    /// Publish on `tf` while the marker is dragged and on `tf_static` once it is released,
+    /// giving smooth feedback while keeping the final pose latched for late subscribers.
+    LatchOnRelease,
+}
+
+impl TfOutput {
+    /// The topics and QoS profiles transforms are published on.
+    ///
+    /// # Arguments
+    ///
+    /// * `tf_topic` - The topic standing for `tf`.
+    /// * `tf_static_topic` - The topic standing for `tf_static`.
+    fn topics(&self, tf_topic: &str, tf_static_topic: &str) -> Vec<(String, QosProfile)> {
+        // Each message holds the whole set of static transforms, so only the last one is kept
+        let tf_static = || {
+            (
+                tf_static_topic.to_string(),
+                QosProfile::default().keep_last(1).transient_local(),
+            )
+        };
+        let tf = || (tf_topic.to_string(), QosProfile::default());
+        match self {
+            TfOutput::Static => vec![tf_static()],
+            TfOutput::Dynamic => vec![tf()],
+            TfOutput::Both => vec![tf(), tf_static()],
+            TfOutput::Custom(topic) => vec![(topic.clone(), QosProfile::default())],
+            TfOutput::LatchOnRelease => vec![tf(), tf_static()],
+        }
+    }
+}
+
+/// Where the server delivers the transforms of its markers.
+///
+/// The default sink publishes on the topics of each marker's `TfOutput`. Implement this to
+/// write the transforms into a transform buffer instead, e.g. of r2r_transforms or sms, or use
+/// `TfBuffer`. Pass the sink to `TeachingMarkerServer::new_with_sink`.
+pub trait TransformSink: Send {
+    /// Prepares the sink for an output target, called when a marker using it is inserted.
+    ///
+    /// The default implementation does nothing.
+    ///
+    /// # Errors
+    ///
+    /// An error fails the insertion of the marker.
+    fn prepare(&mut self, _node: &mut r2r::Node, _output: &TfOutput) -> Result<()> {
+        Ok(())
+    }
+
+    /// Delivers the transforms of a marker.
+    ///
+    /// # Arguments
+    ///
+    /// * `output` - The output target configured for the marker.
+    /// * `transforms` - The transforms, stamped with the current time.
+    /// * `latch` - Whether this is a final pose, as opposed to an intermediate pose during a drag.
+    fn send(&mut self, output: &TfOutput, transforms: &TFMessage, latch: bool) -> Result<()>;
+
+    /// Prepares the sink for an output target of a marker with its own QoS, see
+    /// `TeachingMarkerBuilder::tf_qos`.
+    ///
+    /// The default implementation ignores the QoS and calls `prepare`.
+    ///
+    /// # Errors
+    ///
+    /// An error fails the insertion of the marker.
+    fn prepare_with_qos(&mut self, node: &mut r2r::Node, output: &TfOutput, _qos: &QosProfile) -> Result<()> {
+        self.prepare(node, output)
+    }
+
+    /// Delivers the transforms of a marker with its own QoS.
+    ///
+    /// The default implementation ignores the QoS and calls `send`.
+    fn send_with_qos(
+        &mut self,
+        output: &TfOutput,
+        _qos: &QosProfile,
+        transforms: &TFMessage,
+        latch: bool,
+    ) -> Result<()> {
+        self.send(output, transforms, latch)
+    }
+
+    /// Forgets the latched transform of a frame, called when its marker is removed or renamed.
+    ///
+    /// The default implementation does nothing.
+    ///
+    /// # Errors
+    ///
+    /// The error is logged, the marker is removed regardless.
+    fn forget(&mut self, _child_frame_id: &str) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl TransformSink for TfBuffer {
+    fn send(&mut self, _output: &TfOutput, transforms: &TFMessage, _latch: bool) -> Result<()> {
+        for transform in &transforms.transforms {
+            self.insert(transform);
+        }
+        Ok(())
+    }
+}
+
+/// A transform update sent to the TF publishing thread.
+pub(crate) struct TfUpdate {
+    /// The name of the marker the update belongs to.
+    pub(crate) name: String,
+    /// The transforms to publish.
+    pub(crate) data: TFMessage,
+    /// Whether this is a final pose, as opposed to an intermediate pose during a drag.
+    pub(crate) latch: bool,
+    /// When the feedback the update stems from was received, `None` for programmatic moves.
+    pub(crate) received: Option<Instant>,
+}
+
+#[derive(Default)]
+/// The pending updates of a single marker.
+struct Pending {
+    latched: Option<TfUpdate>,
+    streaming: Option<TfUpdate>,
+}
+
+#[derive(Default)]
+/// The pending updates of all markers.
+struct Slots {
+    pending: HashMap<String, Pending>,
+    closed: bool,
+}
+
+#[derive(Clone, Default)]
+/// A channel keeping only the newest transform update per marker.
+///
+/// Memory stays constant no matter how far the consumer falls behind. A final update is kept
+/// until published, so a pose released in RViz is never replaced by an older intermediate one.
+pub(crate) struct LatestChannel {
+    slots: Arc<Mutex<Slots>>,
+    notify: Arc<Notify>,
+}
+
+impl LatestChannel {
+    /// Stores an update, replacing any older pending update of the same marker.
+    ///
+    /// # Returns
+    ///
+    /// `true` if an intermediate update that wasn't published yet was dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::ChannelClosed` if the channel has been closed.
+    pub(crate) fn send(&self, update: TfUpdate) -> Result<bool> {
+        let mut slots = self.slots.lock().unwrap();
+        if slots.closed {
+            return Err(TeachingMarkerError::ChannelClosed);
+        }
+        let pending = slots.pending.entry(update.name.clone()).or_default();
+        let dropped = if update.latch {
+            // Any pending intermediate pose is older than the final one
+            pending.latched = Some(update);
+            pending.streaming.take().is_some()
+        } else {
+            pending.streaming.replace(update).is_some()
+        };
+        self.notify.notify_one();
+        Ok(dropped)
+    }
+
+    /// Returns the number of updates waiting to be published.
+    pub(crate) fn depth(&self) -> usize {
+        let slots = self.slots.lock().unwrap();
+        slots
+            .pending
+            .values()
+            .map(|pending| pending.latched.is_some() as usize + pending.streaming.is_some() as usize)
+            .sum()
+    }
+
+    /// Closes the channel, pending updates can still be taken.
+    pub(crate) fn close(&self) {
+        self.slots.lock().unwrap().closed = true;
+        self.notify.notify_one();
+    }
+
+    /// Takes all pending updates without waiting.
+    ///
+    /// # Returns
+    ///
+    /// The pending updates, oldest first per marker, empty if there are none.
+    pub(crate) fn take(&self) -> Vec<TfUpdate> {
+        let mut updates = vec![];
+        for (_, pending) in self.slots.lock().unwrap().pending.drain() {
+            updates.extend(pending.latched);
+            updates.extend(pending.streaming);
+        }
+        updates
+    }
+
+    /// Waits for pending updates, without taking them.
+    ///
+    /// # Returns
+    ///
+    /// `true` once updates are pending, `false` once the channel is closed and drained.
+    pub(crate) async fn ready(&self) -> bool {
+        loop {
+            {
+                let slots = self.slots.lock().unwrap();
+                if !slots.pending.is_empty() {
+                    return true;
+                }
+                if slots.closed {
+                    return false;
+                }
+            }
+            // A notification sent before we start waiting is kept as a permit, so none are lost
+            self.notify.notified().await;
+        }
+    }
+}
+
+/// The TF publishers shared by all markers of a server, one per topic and QoS, the default `TransformSink`.
+pub(crate) struct TfPublishers {
+    tf_topic: String,
+    tf_static_topic: String,
+    publishers: HashMap<String, r2r::Publisher<TFMessage>>,
+    /// The latched transforms of all markers by child frame, republished together on `tf_static`.
+    static_transforms: BTreeMap<String, TransformStamped>,
+}
+
+impl TfPublishers {
+    /// Creates the publishers, with the topic names standing for `tf` and `tf_static`.
+    pub(crate) fn new(tf_topic: &str, tf_static_topic: &str) -> Self {
+        TfPublishers {
+            tf_topic: tf_topic.to_string(),
+            tf_static_topic: tf_static_topic.to_string(),
+            publishers: HashMap::new(),
+            static_transforms: BTreeMap::new(),
+        }
+    }
+
+    /// The message holding all latched transforms.
+    fn static_message(&self) -> TFMessage {
+        TFMessage {
+            transforms: self.static_transforms.values().cloned().collect(),
+        }
+    }
+
+    /// The key of the publisher of a topic, markers with their own QoS get separate publishers.
+    fn key(topic: &str, qos: Option<&QosProfile>) -> String {
+        match qos {
+            Some(qos) => format!("{topic} {qos:?}"),
+            None => topic.to_string(),
+        }
+    }
+
+    /// Creates the publishers of an output target, with the QoS of the target or the given one.
+    fn create(&mut self, node: &mut r2r::Node, output: &TfOutput, qos: Option<&QosProfile>) -> Result<()> {
+        for (topic, default_qos) in output.topics(&self.tf_topic, &self.tf_static_topic) {
+            let key = Self::key(&topic, qos);
+            if !self.publishers.contains_key(&key) {
+                let qos = qos.cloned().unwrap_or(default_qos);
+                let publisher = node.create_publisher::<TFMessage>(&topic, qos)?;
+                self.publishers.insert(key, publisher);
+            }
+        }
+        Ok(())
+    }
+
+    /// Publishes the transforms on the publishers of an output target.
+    ///
+    /// Transforms for `tf_static` are merged into the latched set, which is published as a whole.
+    fn publish(
+        &mut self,
+        output: &TfOutput,
+        qos: Option<&QosProfile>,
+        transforms: &TFMessage,
+        latch: bool,
+    ) -> Result<()> {
+        let mut result = Ok(());
+        for (i, (topic, _)) in output.topics(&self.tf_topic, &self.tf_static_topic).iter().enumerate() {
+            // The latch-on-release topics are ordered as [tf, tf_static]
+            let selected = match output {
+                TfOutput::LatchOnRelease => (i == 1) == latch,
+                _ => true,
+            };
+            if !selected {
+                continue;
+            }
+            let message = if *topic == self.tf_static_topic {
+                for transform in &transforms.transforms {
+                    self.static_transforms
+                        .insert(transform.child_frame_id.clone(), transform.clone());
+                }
+                self.static_message()
+            } else {
+                transforms.clone()
+            };
+            if let Some(publisher) = self.publishers.get(&Self::key(topic, qos)) {
+                if let Err(e) = publisher.publish(&message) {
+                    result = Err(e.into());
+                }
+            }
+        }
+        result
+    }
+}
+
+impl TransformSink for TfPublishers {
+    /// Creates the publishers an output target needs, unless they already exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any of the publishers can't be created.
+    fn prepare(&mut self, node: &mut r2r::Node, output: &TfOutput) -> Result<()> {
+        self.create(node, output, None)
+    }
+
+    /// Publishes the transforms on the topics of an output target.
+    ///
+    /// # Errors
+    ///
+    /// Returns the last error encountered, after trying all topics.
+    fn send(&mut self, output: &TfOutput, transforms: &TFMessage, latch: bool) -> Result<()> {
+        self.publish(output, None, transforms, latch)
+    }
+
+    fn prepare_with_qos(&mut self, node: &mut r2r::Node, output: &TfOutput, qos: &QosProfile) -> Result<()> {
+        self.create(node, output, Some(qos))
+    }
+
+    fn send_with_qos(
+        &mut self,
+        output: &TfOutput,
+        qos: &QosProfile,
+        transforms: &TFMessage,
+        latch: bool,
+    ) -> Result<()> {
+        self.publish(output, Some(qos), transforms, latch)
+    }
+
+    /// Drops the latched transform of a frame and republishes the remaining set on `tf_static`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the last error encountered, after trying all `tf_static` publishers.
+    fn forget(&mut self, child_frame_id: &str) -> Result<()> {
+        if self.static_transforms.remove(child_frame_id).is_none() {
+            return Ok(());
+        }
+        let message = self.static_message();
+        let mut result = Ok(());
+        // Publishers with a custom QoS are keyed by the topic followed by the QoS
+        let static_key = format!("{} ", self.tf_static_topic);
+        for (key, publisher) in &self.publishers {
+            if *key == self.tf_static_topic || key.starts_with(&static_key) {
+                if let Err(e) = publisher.publish(&message) {
+                    result = Err(e.into());
+                }
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    fn update(name: &str, frame: &str, latch: bool) -> TfUpdate {
+        let transform = TransformStamped {
+            child_frame_id: frame.to_string(),
+            ..TransformStamped::default()
+        };
+        TfUpdate {
+            name: name.to_string(),
+            data: TFMessage {
+                transforms: vec![transform],
+            },
+            latch,
+            received: None,
+        }
+    }
+
+    fn frames(updates: &[TfUpdate]) -> Vec<&str> {
+        updates
+            .iter()
+            .map(|update| update.data.transforms[0].child_frame_id.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn keeps_only_the_newest_streaming_update() {
+        let channel = LatestChannel::default();
+        assert!(!channel.send(update("a", "first", false)).unwrap());
+        assert!(channel.send(update("a", "second", false)).unwrap());
+        assert_eq!(channel.depth(), 1);
+        assert!(block_on(channel.ready()));
+        let updates = channel.take();
+        assert_eq!(frames(&updates), vec!["second"]);
+        assert_eq!(channel.depth(), 0);
+    }
+
+    #[test]
+    fn a_latched_update_replaces_the_pending_streaming_one() {
+        let channel = LatestChannel::default();
+        channel.send(update("a", "dragged", false)).unwrap();
+        assert!(channel.send(update("a", "released", true)).unwrap());
+        assert!(block_on(channel.ready()));
+        let updates = channel.take();
+        assert_eq!(frames(&updates), vec!["released"]);
+    }
+
+    #[test]
+    fn keeps_the_latched_update_before_newer_streaming_ones() {
+        let channel = LatestChannel::default();
+        channel.send(update("a", "released", true)).unwrap();
+        assert!(!channel.send(update("a", "dragged", false)).unwrap());
+        assert_eq!(channel.depth(), 2);
+        assert!(block_on(channel.ready()));
+        let updates = channel.take();
+        assert_eq!(frames(&updates), vec!["released", "dragged"]);
+    }
+
+    #[test]
+    fn keeps_the_updates_of_each_marker() {
+        let channel = LatestChannel::default();
+        channel.send(update("a", "a", false)).unwrap();
+        channel.send(update("b", "b", false)).unwrap();
+        assert!(block_on(channel.ready()));
+        let mut updates = channel.take();
+        updates.sort_by(|x, y| x.name.cmp(&y.name));
+        assert_eq!(frames(&updates), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn delivers_the_pending_updates_after_closing() {
+        let channel = LatestChannel::default();
+        channel.send(update("a", "last", true)).unwrap();
+        channel.close();
+        assert!(matches!(
+            channel.send(update("a", "late", false)),
+            Err(TeachingMarkerError::ChannelClosed)
+        ));
+        assert!(block_on(channel.ready()));
+        let updates = channel.take();
+        assert_eq!(frames(&updates), vec!["last"]);
+        assert!(!block_on(channel.ready()));
+    }
+
+    #[test]
+    fn ready_waits_for_an_update() {
+        let channel = LatestChannel::default();
+        let sender = channel.clone();
+        let thread = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            sender.send(update("a", "later", false)).unwrap();
+        });
+        assert!(block_on(channel.ready()));
+        let updates = channel.take();
+        assert_eq!(frames(&updates), vec!["later"]);
+        thread.join().unwrap();
+    }
+}
@@ -0,0 +1,109 @@
+use r2r::geometry_msgs::msg::Pose;
+
+use crate::tf::TfUpdate;
+use crate::{Result, TeachingMarker, TeachingMarkerServer};
+
+/// A change of a transaction, applied in the order it was added.
+enum Change {
+    Insert(TeachingMarker),
+    SetPose(String, Pose),
+    Remove(String),
+}
+
+#[derive(Default)]
+/// Changes to several markers applied together, see `TeachingMarkerServer::transaction`.
+pub struct Transaction {
+    changes: Vec<Change>,
+}
+
+impl Transaction {
+    /// Inserts a marker, as with `TeachingMarkerServer::insert_marker`.
+    pub fn insert(&mut self, teaching_marker: TeachingMarker) -> &mut Self {
+        self.changes.push(Change::Insert(teaching_marker));
+        self
+    }
+
+    /// Moves a marker to a pose, as with `TeachingMarkerServer::set_pose`.
+    pub fn set_pose(&mut self, name: &str, pose: &Pose) -> &mut Self {
+        self.changes.push(Change::SetPose(name.to_string(), pose.clone()));
+        self
+    }
+
+    /// Removes a marker, as with `TeachingMarkerServer::remove`.
+    pub fn remove(&mut self, name: &str) -> &mut Self {
+        self.changes.push(Change::Remove(name.to_string()));
+        self
+    }
+}
+
+impl TeachingMarkerServer {
+    /// Applies changes to several markers together.
+    ///
+    /// # Arguments
+    ///
+    /// * `build` - Adds the changes to the transaction, e.g.
+    ///   `|tx| { tx.set_pose("pick", &pose); tx.remove("place"); }`.
+    ///
+    /// # Remarks
+    ///
+    /// RViz receives all changes in a single update, as in the deferred mode of `set_deferred`,
+    /// and the resulting transforms are published together with the same timestamp, so no
+    /// consumer sees a mix of old and new poses. The changes are applied in the order they were
+    /// added. Transactions can be nested, the outer one publishes the changes of both.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error of the changes. The changes before it are kept and published,
+    /// the ones after it are skipped.
+    pub fn transaction<F>(&self, build: F) -> Result<()>
+    where
+        F: FnOnce(&mut Transaction),
+    {
+        let mut transaction = Transaction::default();
+        build(&mut transaction);
+
+        let outer = {
+            let mut tf_batch = self.tf_batch.lock().unwrap();
+            let outer = tf_batch.is_none();
+            tf_batch.get_or_insert_with(Vec::new);
+            outer
+        };
+        let deferred = self.is_deferred();
+        self.set_deferred(true);
+
+        let result = transaction.changes.into_iter().try_for_each(|change| match change {
+            Change::Insert(teaching_marker) => self.insert_marker(teaching_marker),
+            Change::SetPose(name, pose) => self.set_pose(&name, &pose),
+            Change::Remove(name) => self.remove(&name),
+        });
+        if !outer {
+            return result;
+        }
+
+        // One stamp for all transforms, taken once the changes are in place
+        let updates = self.tf_batch.lock().unwrap().take().unwrap_or_default();
+        let published = Self::now().and_then(|stamp| {
+            updates.into_iter().try_for_each(|mut update| {
+                for transform in &mut update.data.transforms {
+                    transform.header.stamp = stamp.clone();
+                }
+                self.send_tf(update)
+            })
+        });
+        self.set_deferred(deferred);
+        result.and(published)
+    }
+
+    /// Holds back a transform update while a transaction is applied.
+    ///
+    /// Returns the update if it should be published right away.
+    pub(crate) fn hold_tf(&self, update: TfUpdate) -> Option<TfUpdate> {
+        match self.tf_batch.lock().unwrap().as_mut() {
+            Some(updates) => {
+                updates.push(update);
+                None
+            }
+            None => Some(update),
+        }
+    }
+}
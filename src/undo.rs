@@ -0,0 +1,134 @@
+use r2r::geometry_msgs::msg::Pose;
+use std::collections::VecDeque;
+
+/// Default number of movements that can be undone per marker.
+pub const DEFAULT_UNDO_DEPTH: usize = 50;
+
+/// The bounded undo and redo history of a marker.
+///
+/// Only settled poses are recorded, i.e. where a drag ended, not the intermediate poses.
+pub(crate) struct UndoStack {
+    undo: VecDeque<Pose>,
+    redo: Vec<Pose>,
+    depth: usize,
+    settled: Pose,
+}
+
+impl UndoStack {
+    /// Creates an empty history starting at a pose.
+    pub(crate) fn new(depth: usize, pose: Pose) -> Self {
+        UndoStack {
+            undo: VecDeque::new(),
+            redo: vec![],
+            depth,
+            settled: pose,
+        }
+    }
+
+    /// Records that the marker settled at a new pose, making the previous pose undoable.
+    pub(crate) fn settle(&mut self, pose: &Pose) {
+        if *pose == self.settled {
+            return;
+        }
+        let previous = std::mem::replace(&mut self.settled, pose.clone());
+        if self.depth == 0 {
+            return;
+        }
+        self.undo.push_back(previous);
+        while self.undo.len() > self.depth {
+            self.undo.pop_front();
+        }
+        self.redo.clear();
+    }
+
+    /// Moves the settled pose without recording it, e.g. for programmatic moves.
+    pub(crate) fn reset(&mut self, pose: &Pose) {
+        self.settled = pose.clone();
+    }
+
+    /// Steps back in the history.
+    ///
+    /// # Returns
+    ///
+    /// The pose to move the marker to, or `None` if there is nothing to undo.
+    pub(crate) fn undo(&mut self) -> Option<Pose> {
+        let pose = self.undo.pop_back()?;
+        self.redo.push(std::mem::replace(&mut self.settled, pose.clone()));
+        Some(pose)
+    }
+
+    /// Steps forward in the history after `undo`.
+    ///
+    /// # Returns
+    ///
+    /// The pose to move the marker to, or `None` if there is nothing to redo.
+    pub(crate) fn redo(&mut self) -> Option<Pose> {
+        let pose = self.redo.pop()?;
+        self.undo
+            .push_back(std::mem::replace(&mut self.settled, pose.clone()));
+        Some(pose)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::pose;
+
+    #[test]
+    fn undoes_and_redoes_settled_poses() {
+        let mut stack = UndoStack::new(DEFAULT_UNDO_DEPTH, pose(0.0, 0.0, 0.0));
+        stack.settle(&pose(1.0, 0.0, 0.0));
+        stack.settle(&pose(2.0, 0.0, 0.0));
+        assert_eq!(stack.undo(), Some(pose(1.0, 0.0, 0.0)));
+        assert_eq!(stack.undo(), Some(pose(0.0, 0.0, 0.0)));
+        assert_eq!(stack.undo(), None);
+        assert_eq!(stack.redo(), Some(pose(1.0, 0.0, 0.0)));
+        assert_eq!(stack.redo(), Some(pose(2.0, 0.0, 0.0)));
+        assert_eq!(stack.redo(), None);
+    }
+
+    #[test]
+    fn settling_clears_the_redo_history() {
+        let mut stack = UndoStack::new(DEFAULT_UNDO_DEPTH, pose(0.0, 0.0, 0.0));
+        stack.settle(&pose(1.0, 0.0, 0.0));
+        assert_eq!(stack.undo(), Some(pose(0.0, 0.0, 0.0)));
+        stack.settle(&pose(3.0, 0.0, 0.0));
+        assert_eq!(stack.redo(), None);
+        assert_eq!(stack.undo(), Some(pose(0.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn ignores_settling_at_the_same_pose() {
+        let mut stack = UndoStack::new(DEFAULT_UNDO_DEPTH, pose(0.0, 0.0, 0.0));
+        stack.settle(&pose(0.0, 0.0, 0.0));
+        assert_eq!(stack.undo(), None);
+    }
+
+    #[test]
+    fn drops_the_oldest_poses_beyond_the_depth() {
+        let mut stack = UndoStack::new(2, pose(0.0, 0.0, 0.0));
+        for x in 1..=4 {
+            stack.settle(&pose(x as f64, 0.0, 0.0));
+        }
+        assert_eq!(stack.undo(), Some(pose(3.0, 0.0, 0.0)));
+        assert_eq!(stack.undo(), Some(pose(2.0, 0.0, 0.0)));
+        assert_eq!(stack.undo(), None);
+    }
+
+    #[test]
+    fn zero_depth_records_nothing() {
+        let mut stack = UndoStack::new(0, pose(0.0, 0.0, 0.0));
+        stack.settle(&pose(1.0, 0.0, 0.0));
+        assert_eq!(stack.undo(), None);
+    }
+
+    #[test]
+    fn reset_moves_without_recording() {
+        let mut stack = UndoStack::new(DEFAULT_UNDO_DEPTH, pose(0.0, 0.0, 0.0));
+        stack.reset(&pose(5.0, 0.0, 0.0));
+        assert_eq!(stack.undo(), None);
+        stack.settle(&pose(6.0, 0.0, 0.0));
+        assert_eq!(stack.undo(), Some(pose(5.0, 0.0, 0.0)));
+    }
+}
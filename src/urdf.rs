@@ -0,0 +1,445 @@
+use r2r::geometry_msgs::msg::{Point, Pose, Vector3};
+use r2r::std_msgs::msg::ColorRGBA;
+use r2r::visualization_msgs::msg::Marker;
+use r2r::ParameterValue;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::math::rpy_to_quaternion;
+use crate::{identity_pose, Result, SpawnAt, TeachingMarker, TeachingMarkerError, TeachingMarkerServer};
+
+#[derive(Clone, Debug, PartialEq)]
+/// Where a URDF robot description is read from.
+pub enum UrdfSource {
+    /// A string parameter of the node, usually `robot_description`.
+    Parameter(String),
+    /// A URDF file.
+    File(PathBuf),
+    /// The URDF itself.
+    Xml(String),
+}
+
+#[derive(Clone, Debug)]
+/// The first visual of a URDF link, in the frame of the link.
+pub(crate) struct LinkVisual {
+    origin: Pose,
+    geometry: Geometry,
+    color: Option<ColorRGBA>,
+}
+
+#[derive(Clone, Debug)]
+/// The geometry of a URDF visual.
+enum Geometry {
+    Mesh { filename: String, scale: Vector3 },
+    Box { size: Vector3 },
+    Cylinder { radius: f64, length: f64 },
+    Sphere { radius: f64 },
+}
+
+/// The visuals of the links of a URDF robot description.
+pub(crate) struct Urdf {
+    visuals: HashMap<String, Option<LinkVisual>>,
+}
+
+/// Creates the error for an invalid robot description.
+fn invalid(message: &str) -> TeachingMarkerError {
+    TeachingMarkerError::InvalidUrdf(message.to_string())
+}
+
+/// Parses a list of whitespace separated numbers, e.g. the `xyz` attribute of a URDF origin.
+fn numbers<const N: usize>(text: Option<&str>, default: [f64; N]) -> Result<[f64; N]> {
+    let text = match text {
+        Some(text) => text,
+        None => return Ok(default),
+    };
+    let values = text
+        .split_whitespace()
+        .map(|v| v.parse::<f64>())
+        .collect::<std::result::Result<Vec<f64>, _>>()
+        .map_err(|_| invalid(&format!("'{text}' is not a list of numbers")))?;
+    values
+        .try_into()
+        .map_err(|_| invalid(&format!("'{text}' doesn't have {N} numbers")))
+}
+
+impl Urdf {
+    /// Reads a robot description.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the parameter isn't a string, the file can't be read or the URDF is invalid.
+    pub(crate) fn load(node: &r2r::Node, source: &UrdfSource) -> Result<Self> {
+        let xml = match source {
+            UrdfSource::Parameter(name) => match node.params.lock().unwrap().get(name).map(|p| &p.value) {
+                Some(ParameterValue::String(xml)) => xml.clone(),
+                _ => {
+                    return Err(TeachingMarkerError::InvalidParameter(format!(
+                        "'{name}' must be a string with the URDF"
+                    )))
+                }
+            },
+            UrdfSource::File(path) => std::fs::read_to_string(path)?,
+            UrdfSource::Xml(xml) => xml.clone(),
+        };
+        Self::parse(&xml)
+    }
+
+    /// Parses the links of a URDF.
+    pub(crate) fn parse(xml: &str) -> Result<Self> {
+        let document = roxmltree::Document::parse(xml).map_err(|e| invalid(&e.to_string()))?;
+        let robot = document.root_element();
+        if robot.tag_name().name() != "robot" {
+            return Err(invalid("the root element isn't <robot>"));
+        }
+
+        // Materials can be defined once at the top level and referenced by name
+        let mut materials = HashMap::new();
+        for material in robot.children().filter(|n| n.has_tag_name("material")) {
+            if let (Some(name), Some(color)) = (material.attribute("name"), Self::color(&material)?) {
+                materials.insert(name.to_string(), color);
+            }
+        }
+
+        let mut visuals = HashMap::new();
+        for link in robot.children().filter(|n| n.has_tag_name("link")) {
+            let name = link
+                .attribute("name")
+                .ok_or_else(|| invalid("a link has no name"))?;
+            let visual = match link.children().find(|n| n.has_tag_name("visual")) {
+                Some(visual) => Self::visual(&visual, &materials)?,
+                None => None,
+            };
+            visuals.insert(name.to_string(), visual);
+        }
+        Ok(Urdf { visuals })
+    }
+
+    /// Parses a `<visual>` element, `None` if its geometry isn't supported.
+    fn visual(visual: &roxmltree::Node, materials: &HashMap<String, ColorRGBA>) -> Result<Option<LinkVisual>> {
+        let mut origin = identity_pose();
+        if let Some(element) = visual.children().find(|n| n.has_tag_name("origin")) {
+            let [x, y, z] = numbers(element.attribute("xyz"), [0.0; 3])?;
+            let [roll, pitch, yaw] = numbers(element.attribute("rpy"), [0.0; 3])?;
+            origin.position = Point { x, y, z };
+            origin.orientation = rpy_to_quaternion(roll, pitch, yaw);
+        }
+
+        let shape = match visual
+            .children()
+            .find(|n| n.has_tag_name("geometry"))
+            .and_then(|geometry| geometry.children().find(|n| n.is_element()))
+        {
+            Some(shape) => shape,
+            None => return Ok(None),
+        };
+        let geometry = match shape.tag_name().name() {
+            "mesh" => {
+                let filename = shape
+                    .attribute("filename")
+                    .ok_or_else(|| invalid("a mesh has no filename"))?;
+                let [x, y, z] = numbers(shape.attribute("scale"), [1.0; 3])?;
+                Geometry::Mesh {
+                    filename: filename.to_string(),
+                    scale: Vector3 { x, y, z },
+                }
+            }
+            "box" => {
+                let [x, y, z] = numbers(shape.attribute("size"), [0.0; 3])?;
+                Geometry::Box {
+                    size: Vector3 { x, y, z },
+                }
+            }
+            "cylinder" => Geometry::Cylinder {
+                radius: numbers::<1>(shape.attribute("radius"), [0.0])?[0],
+                length: numbers::<1>(shape.attribute("length"), [0.0])?[0],
+            },
+            "sphere" => Geometry::Sphere {
+                radius: numbers::<1>(shape.attribute("radius"), [0.0])?[0],
+            },
+            _ => return Ok(None),
+        };
+
+        let color = match visual.children().find(|n| n.has_tag_name("material")) {
+            Some(material) => match Self::color(&material)? {
+                Some(color) => Some(color),
+                None => material.attribute("name").and_then(|name| materials.get(name).cloned()),
+            },
+            None => None,
+        };
+        Ok(Some(LinkVisual {
+            origin,
+            geometry,
+            color,
+        }))
+    }
+
+    /// Parses the `<color rgba>` of a `<material>` element.
+    fn color(material: &roxmltree::Node) -> Result<Option<ColorRGBA>> {
+        match material.children().find(|n| n.has_tag_name("color")) {
+            Some(color) => {
+                let [r, g, b, a] = numbers(color.attribute("rgba"), [0.7, 0.7, 0.7, 1.0])?;
+                Ok(Some(ColorRGBA {
+                    r: r as f32,
+                    g: g as f32,
+                    b: b as f32,
+                    a: a as f32,
+                }))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Returns `true` if the robot has a link with the name.
+    pub(crate) fn has_link(&self, link: &str) -> bool {
+        self.visuals.contains_key(link)
+    }
+
+    /// Creates a regular marker showing the visual of a link.
+    ///
+    /// # Arguments
+    ///
+    /// * `link` - The name of the link.
+    /// * `frame` - The frame to draw the visual in, where the link frame would be.
+    ///
+    /// # Returns
+    ///
+    /// `None` if the link doesn't exist or has no supported visual.
+    pub(crate) fn visual_marker(&self, link: &str, frame: &str) -> Option<Marker> {
+        let visual = self.visuals.get(link)?.as_ref()?;
+        let mut marker = Marker::default();
+        marker.header.frame_id = frame.to_string();
+        marker.action = Marker::ADD as i32;
+        marker.pose = visual.origin.clone();
+        match &visual.geometry {
+            Geometry::Mesh { filename, scale } => {
+                marker.type_ = Marker::MESH_RESOURCE as i32;
+                marker.mesh_resource = filename.clone();
+                marker.scale = scale.clone();
+                // Without a URDF color the materials of the mesh file are shown
+                marker.mesh_use_embedded_materials = visual.color.is_none();
+            }
+            Geometry::Box { size } => {
+                marker.type_ = Marker::CUBE as i32;
+                marker.scale = size.clone();
+            }
+            Geometry::Cylinder { radius, length } => {
+                marker.type_ = Marker::CYLINDER as i32;
+                marker.scale = Vector3 {
+                    x: radius * 2.0,
+                    y: radius * 2.0,
+                    z: *length,
+                };
+            }
+            Geometry::Sphere { radius } => {
+                marker.type_ = Marker::SPHERE as i32;
+                marker.scale = Vector3 {
+                    x: radius * 2.0,
+                    y: radius * 2.0,
+                    z: radius * 2.0,
+                };
+            }
+        }
+        marker.color = visual.color.clone().unwrap_or(ColorRGBA {
+            r: 0.7,
+            g: 0.7,
+            b: 0.7,
+            a: 1.0,
+        });
+        Some(marker)
+    }
+}
+
+impl TeachingMarkerServer {
+    /// Attaches the visual of a URDF link to a marker, e.g. the end effector of a robot.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the marker.
+    /// * `source` - Where to read the URDF from.
+    /// * `link` - The name of the tool link. The marker pose stands for the pose of this link,
+    ///   its visual is drawn with the visual origin of the URDF.
+    ///
+    /// # Remarks
+    ///
+    /// Only the first visual of the link is attached, meshes, boxes, cylinders and spheres are
+    /// supported. Replaces the current visual of the marker.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the marker doesn't exist, the URDF can't be read or parsed, or the
+    /// link doesn't exist or has no supported visual.
+    pub fn attach_urdf_visual(&self, name: &str, source: &UrdfSource, link: &str) -> Result<()> {
+        if !self.markers.lock().unwrap().contains_key(name) {
+            return Err(TeachingMarkerError::MarkerNotFound(name.to_string()));
+        }
+        let urdf = Urdf::load(&self.node.lock().unwrap(), source)?;
+        if !urdf.has_link(link) {
+            return Err(invalid(&format!("link '{link}' not found")));
+        }
+        let visual = urdf
+            .visual_marker(link, name)
+            .ok_or_else(|| invalid(&format!("link '{link}' has no supported visual")))?;
+        self.set_visual(name, Some(visual))
+    }
+
+    /// Inserts a marker at each of the given links of a robot, showing the link geometry.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - Where to read the URDF from.
+    /// * `links` - The names of the links to teach from.
+    /// * `template` - The configuration of the markers. The markers are named
+    ///   `<name>_<link>`, so they don't clash with the link frames, and use its frame as parent.
+    ///
+    /// # Returns
+    ///
+    /// The names of the inserted markers.
+    ///
+    /// # Remarks
+    ///
+    /// Each marker starts at the current location of its link, looked up from TF, e.g. as
+    /// published by robot_state_publisher. This needs a transform lookup, see `listen_tf`, and
+    /// `wait_for_frame` on the template helps when the robot starts at the same time. The first
+    /// visual of the link is attached, meshes, boxes, cylinders and spheres are supported.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the URDF can't be read or parsed, a link doesn't exist or a marker
+    /// can't be inserted.
+    pub fn insert_at_urdf_links(
+        &self,
+        source: &UrdfSource,
+        links: &[&str],
+        template: TeachingMarker,
+    ) -> Result<Vec<String>> {
+        let urdf = Urdf::load(&self.node.lock().unwrap(), source)?;
+        let mut markers = vec![];
+        for link in links {
+            if !urdf.has_link(link) {
+                return Err(invalid(&format!("link '{link}' not found")));
+            }
+            let mut config = template.clone();
+            config.name = format!("{}_{}", template.name, link);
+            config.spawn_at = SpawnAt::Frame(link.to_string());
+            config.visual = urdf.visual_marker(link, &config.name);
+            markers.push(config);
+        }
+        let names = markers.iter().map(|marker| marker.name.clone()).collect();
+        self.insert_many(markers)?;
+        Ok(names)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{assert_position, assert_vector};
+
+    /// A robot with the given links and top-level materials.
+    fn robot(body: &str) -> String {
+        format!(r#"<?xml version="1.0"?><robot name="test">{body}</robot>"#)
+    }
+
+    /// A link with a single visual.
+    fn link(name: &str, visual: &str) -> String {
+        format!(r#"<link name="{name}"><visual>{visual}</visual></link>"#)
+    }
+
+    fn marker(xml: &str, link: &str) -> Option<Marker> {
+        Urdf::parse(xml).unwrap().visual_marker(link, "frame")
+    }
+
+    #[test]
+    fn parses_numbers() {
+        assert_eq!(numbers(Some(" 1 -2.5\t3 "), [0.0; 3]).unwrap(), [1.0, -2.5, 3.0]);
+        assert_eq!(numbers(None, [0.7, 0.7, 0.7, 1.0]).unwrap(), [0.7, 0.7, 0.7, 1.0]);
+        assert!(matches!(numbers(Some("1 a 3"), [0.0; 3]), Err(TeachingMarkerError::InvalidUrdf(_))));
+        assert!(matches!(numbers(Some("1 2"), [0.0; 3]), Err(TeachingMarkerError::InvalidUrdf(_))));
+    }
+
+    #[test]
+    fn draws_boxes_at_the_visual_origin() {
+        let xml = robot(&link(
+            "base",
+            r#"<origin xyz="1 2 3" rpy="0 0 0"/><geometry><box size="0.1 0.2 0.3"/></geometry>"#,
+        ));
+        let marker = marker(&xml, "base").unwrap();
+        assert_eq!(marker.type_, Marker::CUBE as i32);
+        assert_eq!(marker.header.frame_id, "frame");
+        assert_vector(&marker.scale, 0.1, 0.2, 0.3);
+        assert_position(&marker.pose, 1.0, 2.0, 3.0);
+    }
+
+    #[test]
+    fn doubles_the_radii_into_the_scale() {
+        let xml = robot(&format!(
+            "{}{}",
+            link("shaft", r#"<geometry><cylinder radius="0.1" length="0.5"/></geometry>"#),
+            link("ball", r#"<geometry><sphere radius="0.25"/></geometry>"#)
+        ));
+        let cylinder = marker(&xml, "shaft").unwrap();
+        assert_eq!(cylinder.type_, Marker::CYLINDER as i32);
+        assert_vector(&cylinder.scale, 0.2, 0.2, 0.5);
+        let sphere = marker(&xml, "ball").unwrap();
+        assert_eq!(sphere.type_, Marker::SPHERE as i32);
+        assert_vector(&sphere.scale, 0.5, 0.5, 0.5);
+    }
+
+    #[test]
+    fn shows_the_materials_of_meshes_without_a_color() {
+        let xml = robot(&link(
+            "tool",
+            r#"<geometry><mesh filename="package://tools/gripper.dae"/></geometry>"#,
+        ));
+        let marker = marker(&xml, "tool").unwrap();
+        assert_eq!(marker.type_, Marker::MESH_RESOURCE as i32);
+        assert_eq!(marker.mesh_resource, "package://tools/gripper.dae");
+        assert_vector(&marker.scale, 1.0, 1.0, 1.0);
+        assert!(marker.mesh_use_embedded_materials);
+    }
+
+    #[test]
+    fn resolves_named_top_level_materials() {
+        let xml = robot(&format!(
+            r#"<material name="blue"><color rgba="0 0 1 1"/></material>{}{}"#,
+            link("named", r#"<geometry><box size="1 1 1"/></geometry><material name="blue"/>"#),
+            link(
+                "inline",
+                r#"<geometry><box size="1 1 1"/></geometry>
+                   <material name="blue"><color rgba="1 0 0 0.5"/></material>"#
+            )
+        ));
+        let named = marker(&xml, "named").unwrap();
+        assert_eq!((named.color.r, named.color.g, named.color.b, named.color.a), (0.0, 0.0, 1.0, 1.0));
+        // A color given in the visual wins over the top-level material of the same name
+        let inline = marker(&xml, "inline").unwrap();
+        assert_eq!((inline.color.r, inline.color.g, inline.color.b, inline.color.a), (1.0, 0.0, 0.0, 0.5));
+    }
+
+    #[test]
+    fn links_without_a_supported_visual_have_no_marker() {
+        let xml = robot(&format!(
+            r#"{}<link name="empty"/>"#,
+            link("capsule", r#"<geometry><capsule radius="0.1" length="0.5"/></geometry>"#)
+        ));
+        let urdf = Urdf::parse(&xml).unwrap();
+        assert!(urdf.has_link("capsule"));
+        assert!(urdf.visual_marker("capsule", "frame").is_none());
+        assert!(urdf.has_link("empty"));
+        assert!(urdf.visual_marker("empty", "frame").is_none());
+        assert!(!urdf.has_link("missing"));
+        assert!(urdf.visual_marker("missing", "frame").is_none());
+    }
+
+    #[test]
+    fn rejects_invalid_descriptions() {
+        let malformed_origin = robot(&link(
+            "base",
+            r#"<origin xyz="1 2"/><geometry><box size="1 1 1"/></geometry>"#,
+        ));
+        assert!(matches!(Urdf::parse(&malformed_origin), Err(TeachingMarkerError::InvalidUrdf(_))));
+        assert!(Urdf::parse("<robot>").is_err());
+        assert!(Urdf::parse("<model/>").is_err());
+        assert!(Urdf::parse(&robot("<link/>")).is_err());
+    }
+}
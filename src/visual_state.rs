@@ -0,0 +1,83 @@
+use r2r::visualization_msgs::msg::Marker;
+
+use crate::{Result, TeachingMarkerError, TeachingMarkerServer};
+
+impl TeachingMarkerServer {
+    /// Sets the visual states a marker can switch between, e.g. an open and a closed gripper.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the marker.
+    /// * `states` - The name and the visual of each state, drawn like `with_visual`.
+    ///
+    /// # Remarks
+    ///
+    /// The first state is shown right away. The states are listed in a "Visual state" submenu of
+    /// the marker's context menu, and can be switched with `set_visual_state`. An empty list
+    /// removes the states and the submenu, the current visual is kept.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::MarkerNotFound` if no marker with the given name exists.
+    pub fn set_visual_states(&self, name: &str, states: Vec<(String, Marker)>) -> Result<()> {
+        let first = states.first().map(|(state, _)| state.clone());
+        match self.markers.lock().unwrap().get_mut(name) {
+            Some(state) => {
+                state.visual_states = states;
+                state.visual_state = None;
+            }
+            None => return Err(TeachingMarkerError::MarkerNotFound(name.to_string())),
+        }
+        if let Some(first) = first {
+            self.set_visual_state(name, &first)?;
+        }
+        // Rebuilds the context menu with the states
+        self.refresh_marker(name)
+    }
+
+    /// Switches a marker to one of its visual states.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the marker.
+    /// * `state` - The name of the state, as given to `set_visual_states`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::MarkerNotFound` if no marker with the given name exists, or
+    /// `TeachingMarkerError::VisualStateNotFound` if the marker has no such state.
+    pub fn set_visual_state(&self, name: &str, state: &str) -> Result<()> {
+        let visual = match self.markers.lock().unwrap().get_mut(name) {
+            Some(marker) => {
+                let visual = marker
+                    .visual_states
+                    .iter()
+                    .find(|(visual_state, _)| visual_state == state)
+                    .map(|(_, visual)| visual.clone())
+                    .ok_or_else(|| {
+                        TeachingMarkerError::VisualStateNotFound(name.to_string(), state.to_string())
+                    })?;
+                marker.visual_state = Some(state.to_string());
+                visual
+            }
+            None => return Err(TeachingMarkerError::MarkerNotFound(name.to_string())),
+        };
+        self.set_visual(name, Some(visual))
+    }
+
+    /// Returns the name of the visual state a marker currently shows.
+    ///
+    /// # Returns
+    ///
+    /// `None` if the marker has no visual states.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TeachingMarkerError::MarkerNotFound` if no marker with the given name exists.
+    pub fn visual_state(&self, name: &str) -> Result<Option<String>> {
+        match self.markers.lock().unwrap().get(name) {
+            Some(state) => Ok(state.visual_state.clone()),
+            None => Err(TeachingMarkerError::MarkerNotFound(name.to_string())),
+        }
+    }
+}